@@ -0,0 +1,114 @@
+//! Times the VM's dispatch loop on a few instruction-heavy programs.
+//!
+//! Run with `cargo bench`, or `cargo bench -- NAME` to run only the programs
+//! whose names contain NAME. Each program is compiled for and executed on a
+//! fresh VM `ITERATIONS` times; the best and mean times of the executions are
+//! reported.
+//!
+//! The times are a baseline for comparing changes to the VM against their
+//! parent commit. They can vary by 10-20% between runs on a busy machine,
+//! so compare the best times from several runs of each build.
+
+extern crate rlox;
+
+use rlox::compiler;
+use rlox::object::ObjClosure;
+use rlox::parser::Parser;
+use rlox::symbol::GlobalSlots;
+use rlox::vm::VM;
+use std::env;
+use std::io;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 20;
+
+const FIB: &str = "
+fun fib(n) {
+    if (n < 2) return n;
+    return fib(n - 1) + fib(n - 2);
+}
+print fib(22);
+";
+
+const LOOP: &str = "
+var sum = 0;
+for (var i = 0; i < 300000; i = i + 1) {
+    sum = sum + i * 2 - i / 2;
+}
+print sum;
+";
+
+const METHODS: &str = "
+class Counter {
+    init() { this.count = 0; }
+    increment() { this.count = this.count + 1; return this; }
+}
+var counter = Counter();
+for (var i = 0; i < 50000; i = i + 1) {
+    counter.increment().increment();
+}
+print counter.count;
+";
+
+const CLOSURES: &str = "
+fun make_adder(n) {
+    fun add(x) { return x + n; }
+    return add;
+}
+var total = 0;
+{
+    var add_two = make_adder(2);
+    for (var i = 0; i < 100000; i = i + 1) {
+        total = add_two(total);
+    }
+}
+print total;
+";
+
+fn compile(source: &str, globals: &mut GlobalSlots) -> ObjClosure {
+    let ast = Parser::new(source)
+        .parse_program()
+        .unwrap_or_else(|_| panic!("Benchmark failed to parse"));
+    compiler::compile(ast, globals).unwrap_or_else(|_| panic!("Benchmark failed to compile"))
+}
+
+fn bench(name: &str, source: &str) {
+    let mut best = Duration::MAX;
+    let mut total = Duration::ZERO;
+
+    for _ in 0..ITERATIONS {
+        let mut vm = VM::new();
+        let script = compile(source, vm.global_slots_mut());
+        let start = Instant::now();
+        vm.execute(&script, &mut io::sink())
+            .unwrap_or_else(|_| panic!("Benchmark {} failed to run", name));
+        let elapsed = start.elapsed();
+
+        best = best.min(elapsed);
+        total += elapsed;
+    }
+
+    println!(
+        "{:<10} best {:>8.2?}  mean {:>8.2?}",
+        name,
+        best,
+        total / ITERATIONS
+    );
+}
+
+fn main() {
+    // Cargo passes `--bench` to benchmarks; anything else is a filter
+    let filters: Vec<String> = env::args().skip(1).filter(|a| a != "--bench").collect();
+
+    let programs = [
+        ("fib", FIB),
+        ("loop", LOOP),
+        ("methods", METHODS),
+        ("closures", CLOSURES),
+    ];
+    for (name, source) in programs.iter() {
+        if filters.is_empty() || filters.iter().any(|f| name.contains(f.as_str())) {
+            bench(name, source);
+        }
+    }
+}
@@ -2,7 +2,7 @@ use crate::token::{Span, Token};
 use crate::value::Value;
 
 /// Contains either an expression or a statement node, tagged with a Span `span`
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SpannedAstNode {
     pub span: Span,
     pub node: Option<AstNode>,
@@ -39,7 +39,7 @@ impl SpannedAstNode {
 
 /// An expression is an AST Node that results in a Value
 /// being produced at runtime.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum AstNode {
     Unary {
         operator: Token,
@@ -58,10 +58,28 @@ pub enum AstNode {
         left: Box<SpannedAstNode>,
         right: Box<SpannedAstNode>,
     },
+    Conditional {
+        condition: Box<SpannedAstNode>,
+        then_branch: Box<SpannedAstNode>,
+        else_branch: Box<SpannedAstNode>,
+    },
+    NilCoalesce {
+        left: Box<SpannedAstNode>,
+        right: Box<SpannedAstNode>,
+    },
     Assignment {
         lvalue: Box<SpannedAstNode>,
         rvalue: Box<SpannedAstNode>,
     },
+    CompoundAssignment {
+        lvalue: Box<SpannedAstNode>,
+        operator: Token,
+        rvalue: Box<SpannedAstNode>,
+    },
+    PostfixUpdate {
+        lvalue: Box<SpannedAstNode>,
+        operator: Token,
+    },
     Variable {
         name: String,
     },
@@ -76,12 +94,34 @@ pub enum AstNode {
         target: Box<SpannedAstNode>,
         name: String,
     },
+    OptionalFieldAccess {
+        target: Box<SpannedAstNode>,
+        name: String,
+    },
+    OptionalChain {
+        expression: Box<SpannedAstNode>,
+    },
+    ListLiteral {
+        elements: Vec<SpannedAstNode>,
+    },
+    MapLiteral {
+        entries: Vec<(SpannedAstNode, SpannedAstNode)>,
+    },
+    Interpolation {
+        parts: Vec<SpannedAstNode>,
+    },
+    Index {
+        target: Box<SpannedAstNode>,
+        index: Box<SpannedAstNode>,
+    },
     SuperAccess {
         name: String,
     },
     ClassDeclaration {
         name: String,
         methods: Vec<SpannedAstNode>,
+        class_methods: Vec<SpannedAstNode>,
+        getters: Vec<SpannedAstNode>,
         superclass: Option<String>,
     },
     FunDeclaration {
@@ -89,10 +129,18 @@ pub enum AstNode {
         parameters: Vec<Token>,
         body: Box<SpannedAstNode>,
     },
+    Lambda {
+        parameters: Vec<Token>,
+        body: Box<SpannedAstNode>,
+    },
     VarDeclaration {
         name: String,
         initializer: Option<Box<SpannedAstNode>>,
     },
+    Import {
+        path: String,
+        name: String,
+    },
     ExpressionStmt {
         expression: Box<SpannedAstNode>,
     },
@@ -105,19 +153,40 @@ pub enum AstNode {
     Block {
         declarations: Vec<SpannedAstNode>,
     },
+    Throw {
+        value: Box<SpannedAstNode>,
+    },
+    Try {
+        body: Box<SpannedAstNode>,
+        catch: Option<(String, Box<SpannedAstNode>)>,
+        finally: Option<Box<SpannedAstNode>>,
+    },
     If {
         condition: Box<SpannedAstNode>,
         if_block: Box<SpannedAstNode>,
         else_block: Option<Box<SpannedAstNode>>,
     },
+    Switch {
+        scrutinee: Box<SpannedAstNode>,
+        cases: Vec<(Vec<SpannedAstNode>, SpannedAstNode)>,
+        default: Option<Box<SpannedAstNode>>,
+    },
     While {
+        label: Option<String>,
         condition: Box<SpannedAstNode>,
         block: Box<SpannedAstNode>,
     },
     For {
+        label: Option<String>,
         initializer: Option<Box<SpannedAstNode>>,
         condition: Option<Box<SpannedAstNode>>,
         update: Option<Box<SpannedAstNode>>,
         block: Box<SpannedAstNode>,
     },
+    Break {
+        label: Option<String>,
+    },
+    Continue {
+        label: Option<String>,
+    },
 }
@@ -1,57 +1,181 @@
 use crate::ast::{AstNode, SpannedAstNode};
 use crate::error::CompilerError;
 use crate::executable::Executable;
-use crate::object::{ObjClass, ObjClosure, ObjFunction, ObjString};
+use crate::module::SourceFile;
+use crate::object::{ObjClass, ObjClosure, ObjFunction, ObjModule, ObjString};
 use crate::opcode::OpCode;
-use crate::token::{Kind, Span};
+use crate::symbol::{GlobalSlots, Symbol};
+use crate::token::{Kind, Span, Token};
 use crate::value::Value;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::collections::VecDeque;
 use std::rc::Rc;
 
 /// The state of a compiler
 #[derive(Debug)]
-pub struct Compiler {
+pub struct Compiler<'a> {
     /// The call frames (containing variables) that are expected
     /// to be on the stack when the code currently being compiled
     /// is executed.
     frames: VecDeque<Frame>,
+
+    /// The errors encountered so far. Compilation continues past an
+    /// erroneous declaration so that every error can be reported.
+    errors: Vec<CompilerError>,
+
+    /// The module being compiled, if the program is an imported module
+    module: Option<ModuleScope>,
+
+    /// The jumps taken by each optional chain being compiled when the target of
+    /// a '?.' is nil, innermost last
+    optional_chains: Vec<Vec<usize>>,
+
+    /// The slots of the global variables of the VM that will run the code
+    globals: &'a mut GlobalSlots,
+}
+
+/// The state of a compiler that is compiling an imported module
+#[derive(Debug)]
+struct ModuleScope {
+    /// The source of the module
+    source: Rc<SourceFile>,
+
+    /// The names defined at the top level of the module. Their globals are
+    /// private to the module and are exposed through its namespace.
+    members: HashSet<String>,
+}
+
+impl ModuleScope {
+    /// The name of the global variable that holds the member `name`
+    fn global_name(&self, name: &str) -> String {
+        format!("{}::{}", self.source.path, name)
+    }
 }
 
 /// Compile the given AST root nodes into an executable
 ///
 /// Returns a closure representing the executable script if compilation is successful.
-/// Returns a `CompilerError` if compilation is unsuccessful.
+/// Returns every `CompilerError` encountered if compilation is unsuccessful.
 ///
 /// # Arguments
 ///
 /// * `program` - the declaration nodes that make up the program to be compiled
-pub fn compile(program: Vec<SpannedAstNode>) -> Result<ObjClosure, CompilerError> {
-    let mut compiler = Compiler::new();
-    let mut bin = Executable::new(String::from("script"));
-
-    for node in program {
-        compiler.compile_node(&mut bin, &node)?;
-    }
+/// * `globals` - the global slots of the VM that will run the program
+pub fn compile(
+    program: Vec<SpannedAstNode>,
+    globals: &mut GlobalSlots,
+) -> Result<ObjClosure, Vec<CompilerError>> {
+    Compiler::new(globals).compile_program(program)
+}
 
-    Ok(ObjClosure {
-        function: Rc::new(ObjFunction {
-            arity: 0,
-            bin,
-            name: Box::new(ObjString::from("script")),
-            upvalues: vec![],
-        }),
-        upvalues: RefCell::new(vec![]),
-    })
+/// Compile an imported module. The resulting closure runs the module's
+/// top-level code and returns the module's namespace.
+///
+/// Globals defined at the top level of the module are private to it, so
+/// that modules may define the same names as each other and the script.
+pub fn compile_module(
+    program: Vec<SpannedAstNode>,
+    source: Rc<SourceFile>,
+    globals: &mut GlobalSlots,
+) -> Result<ObjClosure, Vec<CompilerError>> {
+    let members = program
+        .iter()
+        .filter_map(|node| match &node.node {
+            Some(AstNode::VarDeclaration { name, .. })
+            | Some(AstNode::FunDeclaration { name, .. })
+            | Some(AstNode::ClassDeclaration { name, .. })
+            | Some(AstNode::Import { name, .. }) => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+
+    let mut compiler = Compiler::new(globals);
+    compiler.module = Some(ModuleScope { source, members });
+    compiler.compile_program(program)
 }
 
-impl Compiler {
-    /// A new compiler with only a global scope defined.
-    pub fn new() -> Self {
+impl<'a> Compiler<'a> {
+    /// A new compiler with only a global scope defined, which assigns global
+    /// variables to slots in `globals`.
+    pub fn new(globals: &'a mut GlobalSlots) -> Self {
         let mut scopes = VecDeque::new();
         scopes.push_back(Frame::new(true, FunctionType::None));
-        Compiler { frames: scopes }
+        Compiler {
+            frames: scopes,
+            errors: vec![],
+            module: None,
+            optional_chains: vec![],
+            globals,
+        }
+    }
+
+    /// Compile every declaration of `program` into a script closure
+    fn compile_program(
+        mut self,
+        program: Vec<SpannedAstNode>,
+    ) -> Result<ObjClosure, Vec<CompilerError>> {
+        let mut bin = Executable::new(String::from("script"));
+        bin.source = self.source();
+
+        for node in program.iter() {
+            self.compile_declaration(&mut bin, node);
+        }
+
+        // A module finishes by returning its namespace
+        if let Some(module) = &self.module {
+            let globals = &mut self.globals;
+            let members = module
+                .members
+                .iter()
+                .map(|name| (Symbol::new(name), globals.slot(&module.global_name(name))))
+                .collect();
+            let namespace = Value::from(ObjModule {
+                name: Box::new(ObjString::from(module.source.path.as_str())),
+                members,
+            });
+            let end = Span::new(module.source.text.len(), module.source.text.len());
+            if let Err(error) = bin.push_constant(namespace, end) {
+                self.errors.push(error);
+            }
+            bin.push_opcode(OpCode::Return, end);
+        }
+
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+
+        Ok(ObjClosure {
+            function: Rc::new(ObjFunction {
+                arity: 0,
+                bin,
+                name: Box::new(ObjString::from("script")),
+                upvalues: vec![],
+            }),
+            upvalues: vec![],
+        })
+    }
+
+    /// Compile a single declaration or statement into the provided binary.
+    ///
+    /// If compilation fails, the error is recorded and the frames and scopes
+    /// are restored so that compilation may continue with the next declaration.
+    fn compile_declaration(&mut self, bin: &mut Executable, node: &SpannedAstNode) {
+        let frame_count = self.frames.len();
+        let scope_count = self.current_frame().scopes.len();
+        let try_block_count = self.current_frame().try_blocks.len();
+        let loop_count = self.current_frame().loops.len();
+
+        if let Err(error) = self.compile_node(bin, node) {
+            self.errors.push(error);
+            self.frames.truncate(frame_count);
+            self.current_frame_mut().scopes.truncate(scope_count);
+            self.current_frame_mut()
+                .try_blocks
+                .truncate(try_block_count);
+            self.current_frame_mut().loops.truncate(loop_count);
+        }
     }
 
     /// Compile a single AST node into the provided binary.
@@ -75,6 +199,9 @@ impl Compiler {
                     Kind::Bang => {
                         bin.push_opcode(OpCode::Not, node_span);
                     }
+                    Kind::Tilde => {
+                        bin.push_opcode(OpCode::BitNot, node_span);
+                    }
                     _ => {
                         return Err(CompilerError {
                             message: format!("Invalid unary operator '{}'", operator.kind),
@@ -91,44 +218,65 @@ impl Compiler {
                 self.compile_node(bin, left)?;
                 self.compile_node(bin, right)?;
 
-                let opcode = match operator.kind {
-                    Kind::Plus => OpCode::Add,
-                    Kind::Minus => OpCode::Subtract,
-                    Kind::Star => OpCode::Multiply,
-                    Kind::Slash => OpCode::Divide,
-                    Kind::Less => OpCode::Less,
-                    Kind::LessEqual => OpCode::LessEqual,
-                    Kind::Greater => OpCode::Greater,
-                    Kind::GreaterEqual => OpCode::GreaterEqual,
-                    Kind::EqualEqual => OpCode::Equal,
-                    Kind::BangEqual => OpCode::NotEqual,
-                    _ => {
-                        return Err(CompilerError {
-                            message: format!("Invalid binary operator '{}'", operator.kind),
-                            span: operator.span,
-                        });
-                    }
-                };
+                let opcode = Self::binary_opcode(operator)?;
                 bin.push_opcode(opcode, node_span);
             }
             AstNode::Assignment { lvalue, rvalue, .. } => match &lvalue.node {
                 Some(AstNode::Variable { name }) => {
                     self.compile_node(bin, rvalue)?;
-                    self.set_variable(name, bin, &node_span);
+                    self.set_variable(name, bin, &node_span)?;
                 }
                 Some(AstNode::FieldAccess { target, name }) => {
                     self.compile_node(bin, target)?;
                     self.compile_node(bin, rvalue)?;
-                    let index = bin.add_constant(Value::from(name.to_string()));
+                    let index = bin.add_identifier(name, node_span)?;
                     bin.push_opcode(OpCode::SetField(index), node_span);
                 }
+                Some(AstNode::Index { target, index }) => {
+                    self.compile_node(bin, target)?;
+                    self.compile_node(bin, index)?;
+                    self.compile_node(bin, rvalue)?;
+                    bin.push_opcode(OpCode::SetIndex, node_span);
+                }
+                Some(AstNode::OptionalChain { .. }) => {
+                    return Err(CompilerError {
+                        message: "Cannot assign to an optional chain".to_string(),
+                        span: lvalue.span,
+                    });
+                }
                 _ => {
                     return Err(CompilerError {
-                        message: format!("Assignment to non-lvalue {:?}", lvalue),
+                        message: "Invalid assignment target".to_string(),
                         span: lvalue.span,
                     });
                 }
             },
+            AstNode::CompoundAssignment {
+                lvalue,
+                operator,
+                rvalue,
+            } => {
+                let opcode = Self::binary_opcode(operator)?;
+                self.compile_update_target(bin, lvalue)?;
+                self.compile_node(bin, rvalue)?;
+                bin.push_opcode(opcode, node_span);
+                self.compile_update_assignment(bin, lvalue, node_span)?;
+            }
+            AstNode::PostfixUpdate { lvalue, operator } => {
+                // Keep a copy of the current value beneath the target and
+                // index, then drop everything above it once assigned
+                let opcode = Self::binary_opcode(operator)?;
+                let operands = self.compile_update_target(bin, lvalue)?;
+                bin.push_opcode(OpCode::Duplicate(operands + 1), node_span);
+                bin.push_constant(Value::Number(1f64), operator.span)?;
+                bin.push_opcode(opcode, node_span);
+                self.compile_update_assignment(bin, lvalue, node_span)?;
+                bin.push_opcode(OpCode::Pop, node_span);
+                for _ in 0..operands {
+                    bin.push_opcode(OpCode::Swap, node_span);
+                    bin.push_opcode(OpCode::Pop, node_span);
+                }
+            }
             AstNode::Variable { name } => {
                 if name == &"this".to_string() && !self.currently_within_method() {
                     return Err(CompilerError {
@@ -136,19 +284,23 @@ impl Compiler {
                         span: node_span,
                     });
                 }
-                self.get_variable(name, bin, &node_span);
+                self.get_variable(name, bin, &node_span)?;
             }
             AstNode::Constant { value } => {
-                let index = bin.add_constant(value.clone());
-                bin.push_opcode(OpCode::Constant(index), node_span);
+                bin.push_constant(value.clone(), node_span)?;
             }
             AstNode::Invokation { target, arguments } => {
                 self.compile_node(bin, target)?;
 
                 // Empty stack slot to be replaced by `this` when the target is a method
-                let index = bin.add_constant(Value::Nil);
-                bin.push_opcode(OpCode::Constant(index), node_span);
+                bin.push_constant(Value::Nil, node_span)?;
 
+                if arguments.len() > u8::MAX as usize {
+                    return Err(CompilerError {
+                        message: format!("Cannot have more than {} arguments", u8::MAX),
+                        span: node_span,
+                    });
+                }
                 for arg in arguments {
                     self.compile_node(bin, arg)?;
                 }
@@ -156,9 +308,97 @@ impl Compiler {
             }
             AstNode::FieldAccess { target, name } => {
                 self.compile_node(bin, target)?;
-                let index = bin.add_constant(Value::from(name.to_string()));
+                let index = bin.add_identifier(name, node_span)?;
                 bin.push_opcode(OpCode::ReadField(index), node_span);
             }
+            AstNode::OptionalFieldAccess { target, name } => {
+                self.compile_node(bin, target)?;
+                bin.push_opcode(OpCode::Duplicate(1), node_span);
+                bin.push_constant(Value::Nil, node_span)?;
+                bin.push_opcode(OpCode::Equal, node_span);
+                let jump_index = bin.push_opcode(OpCode::JumpIfTrue(0), node_span);
+                bin.push_opcode(OpCode::Pop, node_span);
+                match self.optional_chains.last_mut() {
+                    Some(jumps) => jumps.push(jump_index),
+                    None => {
+                        return Err(CompilerError {
+                            message: "Cannot use '?.' outside of an optional chain".to_string(),
+                            span: node_span,
+                        })
+                    }
+                }
+
+                let index = bin.add_identifier(name, node_span)?;
+                bin.push_opcode(OpCode::ReadField(index), node_span);
+            }
+            AstNode::OptionalChain { expression } => {
+                self.optional_chains.push(vec![]);
+                let result = self.compile_node(bin, expression);
+                let jumps = self.optional_chains.pop().unwrap_or_default();
+                result?;
+
+                // A nil target leaves itself and the result of the comparison on the stack
+                let end_jump = bin.push_opcode(OpCode::Jump(0), node_span);
+                for jump in jumps {
+                    bin.patch_jump_to_end(jump, &node_span)?;
+                }
+                bin.push_opcode(OpCode::Pop, node_span);
+                bin.patch_jump_to_end(end_jump, &node_span)?;
+            }
+            AstNode::ListLiteral { elements } => {
+                if elements.len() > u16::MAX as usize {
+                    return Err(CompilerError {
+                        message: format!("Cannot have more than {} list elements", u16::MAX),
+                        span: node_span,
+                    });
+                }
+                for element in elements {
+                    self.compile_node(bin, element)?;
+                }
+                bin.push_opcode(OpCode::BuildList(elements.len()), node_span);
+            }
+            AstNode::MapLiteral { entries } => {
+                if entries.len() > u16::MAX as usize {
+                    return Err(CompilerError {
+                        message: format!("Cannot have more than {} map entries", u16::MAX),
+                        span: node_span,
+                    });
+                }
+                for (key, value) in entries {
+                    self.compile_node(bin, key)?;
+                    self.compile_node(bin, value)?;
+                }
+                bin.push_opcode(OpCode::BuildMap(entries.len()), node_span);
+            }
+            AstNode::Interpolation { parts } => {
+                // Concatenate the text with the embedded expressions, skipping empty text
+                let mut is_first = true;
+                for (index, part) in parts.iter().enumerate() {
+                    let is_expression = index % 2 == 1;
+                    if let Some(AstNode::Constant {
+                        value: Value::String(text),
+                    }) = &part.node
+                    {
+                        if !is_expression && !is_first && text.string.is_empty() {
+                            continue;
+                        }
+                    }
+
+                    self.compile_node(bin, part)?;
+                    if is_expression {
+                        bin.push_opcode(OpCode::Stringify, part.span);
+                    }
+                    if !is_first {
+                        bin.push_opcode(OpCode::Add, part.span);
+                    }
+                    is_first = false;
+                }
+            }
+            AstNode::Index { target, index } => {
+                self.compile_node(bin, target)?;
+                self.compile_node(bin, index)?;
+                bin.push_opcode(OpCode::GetIndex, node_span);
+            }
             AstNode::SuperAccess { name } => {
                 // Put the current instance on the stack
                 if let Some((index, _)) = self.current_frame().resolve_local("this") {
@@ -172,6 +412,7 @@ impl Compiler {
 
                 // Put the superclass on the stack
                 if let Some(index) = self.resolve_upvalue(0, "super") {
+                    self.assert_upvalue_encodable(index, &node_span)?;
                     bin.push_opcode(OpCode::GetUpvalue(index), node_span);
                 } else {
                     return Err(CompilerError {
@@ -180,48 +421,101 @@ impl Compiler {
                     });
                 }
 
-                let index = bin.add_constant(Value::from(name.to_string()));
+                let index = bin.add_identifier(name, node_span)?;
                 bin.push_opcode(OpCode::GetSuper(index), node_span);
             }
             AstNode::ClassDeclaration {
                 name,
                 methods,
+                class_methods,
+                getters,
                 superclass,
             } => {
                 // Create an empty class and bind it to a variable
                 let class = Value::from(ObjClass {
                     name: Box::new(ObjString::from(name.clone())),
                     methods: RefCell::new(HashMap::new()),
+                    class_methods: RefCell::new(HashMap::new()),
+                    getters: RefCell::new(HashMap::new()),
                 });
-                let index = bin.add_constant(class);
-                bin.push_opcode(OpCode::Constant(index), node_span);
+                bin.push_constant(class, node_span)?;
                 self.declare_variable(name, bin, &node_span)?;
 
                 // Leave the superclass on the stack to be captured by any super calls
                 if let Some(superclass_name) = superclass {
                     self.current_frame_mut().begin_scope();
-                    self.get_variable(superclass_name, bin, &node_span);
+                    self.get_variable(superclass_name, bin, &node_span)?;
                     self.declare_variable("super", bin, &node_span)?;
                 }
 
                 // Put the new class on the top of the stack
-                self.get_variable(name, bin, &node_span);
+                self.get_variable(name, bin, &node_span)?;
 
                 // Inherit from the superclass if there is one
                 if superclass.is_some() {
                     bin.push_opcode(OpCode::Inherit, node_span);
                 }
 
+                // A getter cannot share a name with a method or be the initializer
+                let method_names: Vec<&str> = methods
+                    .iter()
+                    .filter_map(|method| match &method.node {
+                        Some(AstNode::FunDeclaration { name, .. }) => Some(name.as_str()),
+                        _ => None,
+                    })
+                    .collect();
+                for getter in getters {
+                    if let Some(AstNode::FunDeclaration {
+                        name: getter_name, ..
+                    }) = &getter.node
+                    {
+                        let message = if getter_name == "init" {
+                            "A getter cannot be named 'init'".to_string()
+                        } else if method_names.contains(&getter_name.as_str()) {
+                            format!(
+                                "Class {} has both a method and a getter named {}",
+                                name, getter_name
+                            )
+                        } else {
+                            continue;
+                        };
+                        return Err(CompilerError {
+                            message,
+                            span: getter.span,
+                        });
+                    }
+                }
+
                 // Compile each method and add to the class
                 for SpannedAstNode { node, span } in methods {
                     self.function_declaration(
                         bin,
-                        &node.as_ref().unwrap(),
+                        node.as_ref().unwrap(),
                         node_span,
                         FunctionType::Method,
                     )?;
                     bin.push_opcode(OpCode::Method, *span);
                 }
+                for SpannedAstNode { node, span } in getters {
+                    self.function_declaration(
+                        bin,
+                        node.as_ref().unwrap(),
+                        node_span,
+                        FunctionType::Method,
+                    )?;
+                    bin.push_opcode(OpCode::Getter, *span);
+                }
+
+                // Class methods have no instance to use as 'this'
+                for SpannedAstNode { node, span } in class_methods {
+                    self.function_declaration(
+                        bin,
+                        node.as_ref().unwrap(),
+                        node_span,
+                        FunctionType::Function,
+                    )?;
+                    bin.push_opcode(OpCode::ClassMethod, *span);
+                }
 
                 // Pop the class, then the superclass
                 bin.push_opcode(OpCode::Pop, node_span);
@@ -229,9 +523,18 @@ impl Compiler {
                     self.current_frame_mut().end_scope(bin, node_span);
                 }
             }
-            AstNode::FunDeclaration { name, .. } => {
+            AstNode::Lambda { .. } => {
                 self.function_declaration(bin, node, node_span, FunctionType::Function)?;
-                self.declare_variable(name, bin, &node_span)?;
+            }
+            AstNode::FunDeclaration { name, .. } => {
+                if self.current_frame().is_global() {
+                    self.function_declaration(bin, node, node_span, FunctionType::Function)?;
+                    self.declare_variable(name, bin, &node_span)?;
+                } else {
+                    // Declare the local first so that the function can capture itself
+                    self.declare_local(name, &node_span)?;
+                    self.function_declaration(bin, node, node_span, FunctionType::Function)?;
+                }
             }
             AstNode::VarDeclaration {
                 name, initializer, ..
@@ -240,11 +543,17 @@ impl Compiler {
                 if let Some(init_expression) = initializer {
                     self.compile_node(bin, init_expression)?;
                 } else {
-                    let index = bin.add_constant(Value::Nil);
-                    bin.push_opcode(OpCode::Constant(index), node_span);
+                    bin.push_constant(Value::Nil, node_span)?;
                 }
                 self.declare_variable(name, bin, &node_span)?;
             }
+            AstNode::Import { path, name } => {
+                // The path is resolved when the import runs, relative to the
+                // file that the importing code was loaded from
+                let index = bin.add_operand_constant(Value::from(path.clone()), node_span)?;
+                bin.push_opcode(OpCode::Import(index), node_span);
+                self.declare_variable(name, bin, &node_span)?;
+            }
             AstNode::ExpressionStmt { expression } => {
                 self.compile_node(bin, expression)?;
                 bin.push_opcode(OpCode::Pop, expression.span);
@@ -259,19 +568,163 @@ impl Compiler {
                         self.compile_node(bin, expression)?;
                     }
                     None => {
-                        let index = bin.add_constant(Value::Nil);
-                        bin.push_opcode(OpCode::Constant(index), node_span);
+                        bin.push_constant(Value::Nil, node_span)?;
                     }
                 }
+                if self.module.is_some() && self.current_frame().is_global() {
+                    return Err(CompilerError {
+                        message: "Cannot return from the top level of a module".to_string(),
+                        span: node_span,
+                    });
+                }
+                if !self.current_frame().try_blocks.is_empty() {
+                    // Keep the return value below any locals declared by the finally blocks
+                    self.current_frame_mut().begin_scope();
+                    self.declare_local("(return)", &node_span)?;
+                    let result = self.exit_try_blocks(bin, 0, node_span);
+                    self.current_frame_mut().discard_scope();
+                    result?;
+                }
                 bin.push_opcode(OpCode::Return, node_span);
             }
+            AstNode::Switch {
+                scrutinee,
+                cases,
+                default,
+            } => {
+                // Keep the scrutinee in a hidden local to compare each case with
+                self.current_frame_mut().begin_scope();
+                self.compile_node(bin, scrutinee)?;
+                self.declare_local("(switch)", &scrutinee.span)?;
+
+                let mut constants: Vec<Value> = vec![];
+                let mut case_jumps = vec![];
+                for (values, _) in cases {
+                    let mut jumps = vec![];
+                    for value in values {
+                        if let Some(constant) = Self::constant_value(value) {
+                            if constants.contains(&constant) {
+                                return Err(CompilerError {
+                                    message: format!("Duplicate case {} in switch", constant),
+                                    span: value.span,
+                                });
+                            }
+                            constants.push(constant);
+                        }
+
+                        self.get_variable("(switch)", bin, &value.span)?;
+                        self.compile_node(bin, value)?;
+                        bin.push_opcode(OpCode::Equal, value.span);
+                        jumps.push(bin.push_opcode(OpCode::JumpIfTrue(0), value.span));
+                        bin.push_opcode(OpCode::Pop, value.span);
+                    }
+                    case_jumps.push(jumps);
+                }
+
+                // No case matched
+                let default_jump = bin.push_opcode(OpCode::Jump(0), node_span);
+                let mut end_jumps = vec![];
+                for ((_, body), jumps) in cases.iter().zip(case_jumps) {
+                    for jump in jumps {
+                        bin.patch_jump_to_end(jump, &node_span)?;
+                    }
+                    bin.push_opcode(OpCode::Pop, body.span);
+                    self.compile_node(bin, body)?;
+                    end_jumps.push(bin.push_opcode(OpCode::Jump(0), body.span));
+                }
+                bin.patch_jump_to_end(default_jump, &node_span)?;
+                if let Some(body) = default {
+                    self.compile_node(bin, body)?;
+                }
+                for jump in end_jumps {
+                    bin.patch_jump_to_end(jump, &node_span)?;
+                }
+                self.current_frame_mut().end_scope(bin, node_span);
+            }
             AstNode::Block { declarations } => {
                 self.current_frame_mut().begin_scope();
                 for statement in declarations.iter() {
-                    self.compile_node(bin, statement)?
+                    self.compile_declaration(bin, statement);
                 }
                 self.current_frame_mut().end_scope(bin, node_span);
             }
+            AstNode::Break { label } => {
+                self.jump_out_of_loop(bin, label, true, node_span)?;
+            }
+            AstNode::Continue { label } => {
+                self.jump_out_of_loop(bin, label, false, node_span)?;
+            }
+            AstNode::Throw { value } => {
+                self.compile_node(bin, value)?;
+                bin.push_opcode(OpCode::Throw, node_span);
+            }
+            AstNode::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                let finally = finally.as_ref().map(|block| Rc::new((**block).clone()));
+
+                // Run the body with a handler for anything it throws
+                let handler = bin.push_opcode(OpCode::PushHandler(0), node_span);
+                self.current_frame_mut().try_blocks.push(TryBlock {
+                    has_handler: true,
+                    finally: finally.clone(),
+                });
+                self.compile_node(bin, body)?;
+                self.current_frame_mut().try_blocks.pop();
+                bin.push_opcode(OpCode::PopHandler, node_span);
+                let skip_catch = bin.push_opcode(OpCode::Jump(0), node_span);
+                bin.patch_jump_to_end(handler, &node_span)?;
+
+                // The handler leaves the thrown value where the next local will go
+                let rethrow_handler = if let Some((name, block)) = catch {
+                    self.current_frame_mut().begin_scope();
+                    self.declare_local(name, &node_span)?;
+
+                    // Anything thrown by the catch block must still run the finally block
+                    let rethrow_handler = if finally.is_some() {
+                        let rethrow_handler = bin.push_opcode(OpCode::PushHandler(0), node_span);
+                        self.current_frame_mut().try_blocks.push(TryBlock {
+                            has_handler: true,
+                            finally: finally.clone(),
+                        });
+                        self.compile_node(bin, block)?;
+                        self.current_frame_mut().try_blocks.pop();
+                        bin.push_opcode(OpCode::PopHandler, node_span);
+                        Some(rethrow_handler)
+                    } else {
+                        self.compile_node(bin, block)?;
+                        None
+                    };
+                    self.current_frame_mut().end_scope(bin, block.span);
+                    rethrow_handler
+                } else {
+                    Some(handler)
+                };
+
+                bin.patch_jump_to_end(skip_catch, &node_span)?;
+
+                if let (Some(finally), Some(rethrow_handler)) = (finally, rethrow_handler) {
+                    self.compile_node(bin, &finally)?;
+                    let skip_rethrow = bin.push_opcode(OpCode::Jump(0), node_span);
+                    bin.patch_jump_to_end(rethrow_handler, &node_span)?;
+
+                    // Run the finally block, then throw the value again. The
+                    // thrown value (and the caught value, if this was thrown
+                    // from the catch block) are left on the stack as locals.
+                    self.current_frame_mut().begin_scope();
+                    if catch.is_some() {
+                        self.declare_local("(caught)", &node_span)?;
+                    }
+                    self.declare_local("(thrown)", &node_span)?;
+                    self.compile_node(bin, &finally)?;
+                    bin.push_opcode(OpCode::Rethrow, node_span);
+                    self.current_frame_mut().discard_scope();
+
+                    bin.patch_jump_to_end(skip_rethrow, &node_span)?;
+                }
+            }
             AstNode::If {
                 condition,
                 if_block,
@@ -283,32 +736,37 @@ impl Compiler {
                 bin.push_opcode(OpCode::Pop, node_span);
                 self.compile_node(bin, if_block)?;
 
-                bin.assert_not_too_long(&node_span)?;
-
                 let second_jump = bin.push_opcode(OpCode::Jump(0), node_span);
-                bin[first_jump] = OpCode::JumpIfFalse(bin.len());
+                bin.patch_jump_to_end(first_jump, &node_span)?;
                 bin.push_opcode(OpCode::Pop, node_span);
 
                 if let Some(else_block) = else_block {
                     self.compile_node(bin, else_block)?;
                 }
 
-                bin.assert_not_too_long(&node_span)?;
-                bin[second_jump] = OpCode::Jump(bin.len());
+                bin.patch_jump_to_end(second_jump, &node_span)?;
             }
-            AstNode::While { condition, block } => {
+            AstNode::While {
+                label,
+                condition,
+                block,
+            } => {
                 let condition_index = bin.len();
                 self.compile_node(bin, condition)?;
                 let jump_to_end_index = bin.push_opcode(OpCode::JumpIfFalse(0), node_span);
                 bin.push_opcode(OpCode::Pop, node_span);
+                self.begin_loop(label);
                 self.compile_node(bin, block)?;
+                let body = self.end_loop();
+                bin.assert_not_too_long(&node_span)?;
                 bin.push_opcode(OpCode::Jump(condition_index), node_span);
 
-                bin.assert_not_too_long(&node_span)?;
-                bin[jump_to_end_index] = OpCode::JumpIfFalse(bin.len());
+                bin.patch_jump_to_end(jump_to_end_index, &node_span)?;
                 bin.push_opcode(OpCode::Pop, node_span);
+                body.patch_jumps(bin, condition_index, &node_span)?;
             }
             AstNode::For {
+                label,
                 initializer,
                 condition,
                 update,
@@ -329,18 +787,22 @@ impl Compiler {
                     0
                 };
 
+                self.begin_loop(label);
                 self.compile_node(bin, block)?;
+                let body = self.end_loop();
+                let update_index = bin.len();
                 if let Some(update) = update {
                     self.compile_node(bin, update)?;
                     bin.push_opcode(OpCode::Pop, update.span);
                 }
+                bin.assert_not_too_long(&node_span)?;
                 bin.push_opcode(OpCode::Jump(condition_index), node_span);
 
                 if condition.is_some() {
-                    bin.assert_not_too_long(&node_span)?;
-                    bin[jump_to_end_index] = OpCode::JumpIfFalse(bin.len())
+                    bin.patch_jump_to_end(jump_to_end_index, &node_span)?;
                 }
                 bin.push_opcode(OpCode::Pop, node_span);
+                body.patch_jumps(bin, update_index, &node_span)?;
                 self.current_frame_mut().end_scope(bin, block.span);
             }
             AstNode::Or { left, right } => {
@@ -348,7 +810,7 @@ impl Compiler {
                 let jump_index = bin.push_opcode(OpCode::JumpIfTrue(0), node_span);
                 bin.push_opcode(OpCode::Pop, node_span);
                 self.compile_node(bin, right)?;
-                bin[jump_index] = OpCode::JumpIfTrue(bin.len());
+                bin.patch_jump_to_end(jump_index, &node_span)?;
                 bin.push_opcode(OpCode::Bool, node_span);
             }
             AstNode::And { left, right } => {
@@ -356,14 +818,132 @@ impl Compiler {
                 let jump_index = bin.push_opcode(OpCode::JumpIfFalse(0), node_span);
                 bin.push_opcode(OpCode::Pop, node_span);
                 self.compile_node(bin, right)?;
-                bin[jump_index] = OpCode::JumpIfFalse(bin.len());
+                bin.patch_jump_to_end(jump_index, &node_span)?;
                 bin.push_opcode(OpCode::Bool, node_span);
             }
+            AstNode::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.compile_node(bin, condition)?;
+                let else_jump = bin.push_opcode(OpCode::JumpIfFalse(0), node_span);
+                bin.push_opcode(OpCode::Pop, node_span);
+                self.compile_node(bin, then_branch)?;
+                let end_jump = bin.push_opcode(OpCode::Jump(0), node_span);
+                bin.patch_jump_to_end(else_jump, &node_span)?;
+                bin.push_opcode(OpCode::Pop, node_span);
+                self.compile_node(bin, else_branch)?;
+                bin.patch_jump_to_end(end_jump, &node_span)?;
+            }
+            AstNode::NilCoalesce { left, right } => {
+                // Keep the left value unless it is nil, comparing a copy of it with nil
+                self.compile_node(bin, left)?;
+                bin.push_opcode(OpCode::Duplicate(1), node_span);
+                bin.push_constant(Value::Nil, node_span)?;
+                bin.push_opcode(OpCode::NotEqual, node_span);
+                let jump_index = bin.push_opcode(OpCode::JumpIfTrue(0), node_span);
+                bin.push_opcode(OpCode::Pop, node_span);
+                bin.push_opcode(OpCode::Pop, node_span);
+                self.compile_node(bin, right)?;
+                let end_jump = bin.push_opcode(OpCode::Jump(0), node_span);
+                bin.patch_jump_to_end(jump_index, &node_span)?;
+                bin.push_opcode(OpCode::Pop, node_span);
+                bin.patch_jump_to_end(end_jump, &node_span)?;
+            }
+        };
+
+        Ok(())
+    }
+
+    /// Emit the instructions to leave the enclosing try blocks of the current
+    /// function beyond the outermost `depth`: remove their handlers and run
+    /// their finally blocks, innermost first.
+    fn exit_try_blocks(
+        &mut self,
+        bin: &mut Executable,
+        depth: usize,
+        span: Span,
+    ) -> Result<(), CompilerError> {
+        let try_blocks = self.current_frame().try_blocks.clone();
+
+        let mut result = Ok(());
+        for (index, try_block) in try_blocks.iter().enumerate().skip(depth).rev() {
+            // Leaving a finally block early only leaves the blocks around it
+            self.current_frame_mut().try_blocks.truncate(index);
+            if try_block.has_handler {
+                bin.push_opcode(OpCode::PopHandler, span);
+            }
+            if let Some(finally) = &try_block.finally {
+                result = self.compile_node(bin, finally);
+                if result.is_err() {
+                    break;
+                }
+            }
+        }
+
+        self.current_frame_mut().try_blocks = try_blocks;
+        result
+    }
+
+    /// Emit the instructions for a `break` or `continue` statement: leave the
+    /// try blocks and scopes inside the target loop, then jump to be patched
+    /// when the loop is complete.
+    fn jump_out_of_loop(
+        &mut self,
+        bin: &mut Executable,
+        label: &Option<String>,
+        is_break: bool,
+        span: Span,
+    ) -> Result<(), CompilerError> {
+        let keyword = if is_break { "break" } else { "continue" };
+        let loops = &self.current_frame().loops;
+        let index = match label {
+            Some(label) => loops
+                .iter()
+                .rposition(|l| l.label.as_ref() == Some(label))
+                .ok_or_else(|| CompilerError {
+                    message: format!("Cannot {} to unknown loop label {}", keyword, label),
+                    span,
+                })?,
+            None => loops.len().checked_sub(1).ok_or_else(|| CompilerError {
+                message: format!("Cannot use '{}' outside of a loop", keyword),
+                span,
+            })?,
         };
+        let (scope_depth, try_depth) = (loops[index].scope_depth, loops[index].try_depth);
+
+        self.exit_try_blocks(bin, try_depth, span)?;
+        self.current_frame().exit_scopes(scope_depth, bin, span);
+        let jump = bin.push_opcode(OpCode::Jump(0), span);
 
+        let target = &mut self.current_frame_mut().loops[index];
+        if is_break {
+            target.breaks.push(jump);
+        } else {
+            target.continues.push(jump);
+        }
         Ok(())
     }
 
+    /// Start compiling the body of a loop, which `break` and `continue` may leave
+    fn begin_loop(&mut self, label: &Option<String>) {
+        let frame = self.current_frame_mut();
+        let enclosing_loop = Loop {
+            label: label.clone(),
+            scope_depth: frame.scopes.len(),
+            try_depth: frame.try_blocks.len(),
+            breaks: vec![],
+            continues: vec![],
+        };
+        frame.loops.push(enclosing_loop);
+    }
+
+    /// Finish compiling the body of the innermost loop, returning the jumps to patch
+    fn end_loop(&mut self) -> Loop {
+        self.current_frame_mut().loops.pop().unwrap()
+    }
+
     /// Emit the instructions to bind a new variable to the value that
     /// is at the top of the stack. Consumes the value at the top of the
     /// stack.
@@ -373,55 +953,224 @@ impl Compiler {
         bin: &mut Executable,
         span: &Span,
     ) -> Result<(), CompilerError> {
-        let name_value = Value::from(name);
-
         if self.current_frame().is_global() {
-            let index = bin.add_constant(name_value.clone());
-            bin.push_opcode(OpCode::DeclareGlobal(index), *span);
-            let index = bin.add_constant(name_value);
-            bin.push_opcode(OpCode::SetGlobal(index), *span);
+            let slot = self.global_slot(name, span)?;
+            bin.push_opcode(OpCode::DeclareGlobal(slot), *span);
+            bin.push_opcode(OpCode::SetGlobal(slot), *span);
             bin.push_opcode(OpCode::Pop, *span);
         } else {
-            if let Some((_, distance)) = self.current_frame().resolve_local(name) {
-                if distance == 0 {
-                    return Err(CompilerError {
-                        message: format!("Redeclaration of local variable {}", name),
-                        span: *span,
-                    });
-                }
-            }
-            self.current_frame_mut().add_local(name);
+            self.declare_local(name, span)?;
         }
 
         Ok(())
     }
 
+    /// Add a new local variable to the current scope, erroring if the
+    /// scope already contains a variable with the same name.
+    fn declare_local(&mut self, name: &str, span: &Span) -> Result<(), CompilerError> {
+        if let Some((_, distance)) = self.current_frame().resolve_local(name) {
+            if distance == 0 {
+                return Err(CompilerError {
+                    message: format!("Redeclaration of local variable {}", name),
+                    span: *span,
+                });
+            }
+        }
+        if self.current_frame().local_count() > u8::MAX as usize {
+            return Err(CompilerError {
+                message: "Too many local variables in function".to_string(),
+                span: *span,
+            });
+        }
+        self.current_frame_mut().add_local(name);
+        Ok(())
+    }
+
     /// Emit the instructions to set an existing variable to the value at the top of the stack.
     /// Does not consume the value at the top of the stack.
-    fn set_variable(&mut self, name: &str, bin: &mut Executable, span: &Span) {
+    fn set_variable(
+        &mut self,
+        name: &str,
+        bin: &mut Executable,
+        span: &Span,
+    ) -> Result<(), CompilerError> {
         if let Some((index, _)) = self.current_frame().resolve_local(name) {
             bin.push_opcode(OpCode::SetLocal(index), *span);
         } else if let Some(index) = self.resolve_upvalue(0, name) {
+            self.assert_upvalue_encodable(index, span)?;
             bin.push_opcode(OpCode::SetUpvalue(index), *span);
         } else {
-            let name_value = Value::from(name);
-            let index = bin.add_constant(name_value);
-            bin.push_opcode(OpCode::SetGlobal(index), *span);
+            let slot = self.global_slot(name, span)?;
+            bin.push_opcode(OpCode::SetGlobal(slot), *span);
         }
+        Ok(())
+    }
+
+    /// Emit the instructions to read the value of `lvalue`, the target of a compound
+    /// assignment, leaving the target object and index (if any) beneath it so that
+    /// they are only evaluated once. Returns the number of values left beneath it.
+    fn compile_update_target(
+        &mut self,
+        bin: &mut Executable,
+        lvalue: &SpannedAstNode,
+    ) -> Result<usize, CompilerError> {
+        match &lvalue.node {
+            Some(AstNode::Variable { name }) => {
+                self.get_variable(name, bin, &lvalue.span)?;
+                Ok(0)
+            }
+            Some(AstNode::FieldAccess { target, name }) => {
+                self.compile_node(bin, target)?;
+                bin.push_opcode(OpCode::Duplicate(1), lvalue.span);
+                let index = bin.add_identifier(name, lvalue.span)?;
+                bin.push_opcode(OpCode::ReadField(index), lvalue.span);
+                Ok(1)
+            }
+            Some(AstNode::Index { target, index }) => {
+                self.compile_node(bin, target)?;
+                self.compile_node(bin, index)?;
+                bin.push_opcode(OpCode::Duplicate(2), lvalue.span);
+                bin.push_opcode(OpCode::GetIndex, lvalue.span);
+                Ok(2)
+            }
+            Some(AstNode::OptionalChain { .. }) => Err(CompilerError {
+                message: "Cannot assign to an optional chain".to_string(),
+                span: lvalue.span,
+            }),
+            _ => Err(CompilerError {
+                message: "Invalid assignment target".to_string(),
+                span: lvalue.span,
+            }),
+        }
+    }
+
+    /// Emit the instruction to assign the value at the top of the stack to `lvalue`,
+    /// using the target and index left beneath it by `compile_update_target`
+    fn compile_update_assignment(
+        &mut self,
+        bin: &mut Executable,
+        lvalue: &SpannedAstNode,
+        span: Span,
+    ) -> Result<(), CompilerError> {
+        match &lvalue.node {
+            Some(AstNode::Variable { name }) => self.set_variable(name, bin, &span),
+            Some(AstNode::FieldAccess { name, .. }) => {
+                let index = bin.add_identifier(name, span)?;
+                bin.push_opcode(OpCode::SetField(index), span);
+                Ok(())
+            }
+            _ => {
+                bin.push_opcode(OpCode::SetIndex, span);
+                Ok(())
+            }
+        }
+    }
+
+    /// The value of `node` if it is a constant, such as a literal or a negated number
+    fn constant_value(node: &SpannedAstNode) -> Option<Value> {
+        match &node.node {
+            Some(AstNode::Constant { value }) => Some(value.clone()),
+            Some(AstNode::Unary {
+                operator,
+                expression,
+            }) if operator.kind == Kind::Minus => match Self::constant_value(expression) {
+                Some(Value::Number(n)) => Some(Value::Number(-n)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The instruction that applies the binary `operator`
+    fn binary_opcode(operator: &Token) -> Result<OpCode, CompilerError> {
+        let opcode = match operator.kind {
+            Kind::Plus => OpCode::Add,
+            Kind::Minus => OpCode::Subtract,
+            Kind::Star => OpCode::Multiply,
+            Kind::Slash => OpCode::Divide,
+            Kind::Percent => OpCode::Modulo,
+            Kind::StarStar => OpCode::Power,
+            Kind::Ampersand => OpCode::BitAnd,
+            Kind::Pipe => OpCode::BitOr,
+            Kind::Caret => OpCode::BitXor,
+            Kind::LessLess => OpCode::ShiftLeft,
+            Kind::GreaterGreater => OpCode::ShiftRight,
+            Kind::Less => OpCode::Less,
+            Kind::LessEqual => OpCode::LessEqual,
+            Kind::Greater => OpCode::Greater,
+            Kind::GreaterEqual => OpCode::GreaterEqual,
+            Kind::EqualEqual => OpCode::Equal,
+            Kind::BangEqual => OpCode::NotEqual,
+            _ => {
+                return Err(CompilerError {
+                    message: format!("Invalid binary operator '{}'", operator.kind),
+                    span: operator.span,
+                });
+            }
+        };
+        Ok(opcode)
     }
 
     /// Emit the instructions to load a variable onto the top of the stack.
     /// Prioritize local variables over upvalues (closure variables) over
     /// global variables.
-    fn get_variable(&mut self, name: &str, bin: &mut Executable, span: &Span) {
+    fn get_variable(
+        &mut self,
+        name: &str,
+        bin: &mut Executable,
+        span: &Span,
+    ) -> Result<(), CompilerError> {
         if let Some((index, _)) = self.current_frame().resolve_local(name) {
             bin.push_opcode(OpCode::GetLocal(index), *span);
         } else if let Some(index) = self.resolve_upvalue(0, name) {
+            self.assert_upvalue_encodable(index, span)?;
             bin.push_opcode(OpCode::GetUpvalue(index), *span);
         } else {
-            let name_value = Value::from(name);
-            let index = bin.add_constant(name_value);
-            bin.push_opcode(OpCode::GetGlobal(index), *span);
+            let slot = self.global_slot(name, span)?;
+            bin.push_opcode(OpCode::GetGlobal(slot), *span);
+        }
+        Ok(())
+    }
+
+    /// Resolves the global variable `name` to its slot, erroring if the slot
+    /// is too large to be referenced by an instruction
+    fn global_slot(&mut self, name: &str, span: &Span) -> Result<usize, CompilerError> {
+        let slot = self.member_slot(name);
+        if slot > u16::MAX as usize {
+            Err(CompilerError {
+                message: "Too many global variables".to_string(),
+                span: *span,
+            })
+        } else {
+            Ok(slot)
+        }
+    }
+
+    /// The slot of the global variable `name`. The top-level definitions of a
+    /// module have slots of their own, named after the module.
+    fn member_slot(&mut self, name: &str) -> usize {
+        match &self.module {
+            Some(module) if module.members.contains(name) => {
+                self.globals.slot(&module.global_name(name))
+            }
+            _ => self.globals.slot(name),
+        }
+    }
+
+    /// The source of the module being compiled, if it is an imported module
+    fn source(&self) -> Option<Rc<SourceFile>> {
+        self.module.as_ref().map(|module| module.source.clone())
+    }
+
+    /// Errors if the upvalue `index` is too large to be referenced by an instruction
+    fn assert_upvalue_encodable(&self, index: usize, span: &Span) -> Result<(), CompilerError> {
+        if index > u8::MAX as usize {
+            Err(CompilerError {
+                message: "Too many closure variables in function".to_string(),
+                span: *span,
+            })
+        } else {
+            Ok(())
         }
     }
 
@@ -443,43 +1192,29 @@ impl Compiler {
             .any(|f| f.function_type == FunctionType::Method)
     }
 
-    /// Resolves a variable name to an upvalue index, if possible.
+    /// Resolves a variable name to an upvalue index in the frame `frame_depth`
+    /// frames below the current frame, if possible.
     ///
-    /// First looks for an existing upvalue. If not found, then creates a new
-    /// one, if it can find the referenced variable on the stack.
+    /// If the variable is a local of the enclosing frame, it is marked as captured.
+    /// Otherwise the enclosing frame must itself capture the variable as an upvalue.
     fn resolve_upvalue(&mut self, frame_depth: usize, name: &str) -> Option<usize> {
-        if frame_depth >= self.frames.len() {
+        if frame_depth + 1 >= self.frames.len() {
             return None;
         }
 
-        if let Some((index, _)) = self
-            .frames
-            .get_mut(self.frames.len() - frame_depth - 1)
-            .unwrap()
-            .resolve_local(name)
-        {
-            return Some(
-                self.frames
-                    .get_mut(self.frames.len() - frame_depth - 1)
-                    .unwrap()
-                    .add_upvalue(index, true),
-            );
-        }
-
-        if let Some(index) = self.resolve_upvalue(frame_depth + 1, name) {
-            Some(
-                self.frames
-                    .get_mut(self.frames.len() - frame_depth - 1)
-                    .unwrap()
-                    .add_upvalue(index, false),
-            )
-        } else {
-            None
+        let frame_index = self.frames.len() - frame_depth - 1;
+        let enclosing = &mut self.frames[frame_index - 1];
+        if let Some((index, _)) = enclosing.resolve_local(name) {
+            enclosing.capture_local(index);
+            return Some(self.frames[frame_index].add_upvalue(index, true));
         }
+
+        self.resolve_upvalue(frame_depth + 1, name)
+            .map(|index| self.frames[frame_index].add_upvalue(index, false))
     }
 
-    /// Compiles a function or method definition and leaves a closure
-    /// containing the function on the top of the stack
+    /// Compiles a function or method definition, or an anonymous function,
+    /// and leaves a closure containing the function on the top of the stack
     fn function_declaration(
         &mut self,
         bin: &mut Executable,
@@ -487,92 +1222,145 @@ impl Compiler {
         function_span: Span,
         function_type: FunctionType,
     ) -> Result<(), CompilerError> {
-        if let AstNode::FunDeclaration {
-            name,
-            parameters,
-            body,
-        } = function_node
-        {
-            // Track the frame that will be on the call stack at runtime
-            let mut function_frame = Frame::new(false, function_type);
+        let (name, parameters, body) = match function_node {
+            AstNode::FunDeclaration {
+                name,
+                parameters,
+                body,
+            } => (name.as_str(), parameters, body),
+            AstNode::Lambda { parameters, body } => ("lambda", parameters, body),
+            _ => {
+                return Err(CompilerError {
+                    message:
+                        "compiler.function_declaration called with non-FunctionDeclaration node"
+                            .to_string(),
+                    span: function_span,
+                })
+            }
+        };
+
+        // Track the frame that will be on the call stack at runtime
+        let mut function_frame = Frame::new(false, function_type);
+
+        // Add "this" as a local for methods, or a dummy parameter for functions
+        if function_type == FunctionType::Method {
+            function_frame.add_local("this");
+        } else {
+            function_frame.add_local("");
+        }
 
-            // Add "this" as a local for methods, or a dummy parameter for functions
-            if function_type == FunctionType::Method {
-                function_frame.add_local("this");
+        // Add the parameters to the list of Locals
+        if parameters.len() > u8::MAX as usize {
+            return Err(CompilerError {
+                message: format!("Cannot have more than {} parameters", u8::MAX),
+                span: function_span,
+            });
+        }
+        for param in parameters.iter() {
+            if let Kind::IdentifierLiteral(param_name) = &param.kind {
+                function_frame.add_local(param_name);
             } else {
-                function_frame.add_local("");
+                return Err(CompilerError {
+                    message: "Expected parameter name to be IdentifierLiteral".to_string(),
+                    span: param.span,
+                });
             }
+        }
 
-            // Add the parameters to the list of Locals
-            for param in parameters.iter() {
-                if let Kind::IdentifierLiteral(param_name) = &param.kind {
-                    function_frame.add_local(param_name);
-                } else {
-                    return Err(CompilerError {
-                        message: "Expected parameter name to be IdentifierLiteral".to_string(),
-                        span: param.span,
-                    });
-                }
-            }
+        // Push the frame so that nested functions can see it
+        self.frames.push_back(function_frame);
 
-            // Push the frame so that nested functions can see it
-            self.frames.push_back(function_frame);
+        // Compile the function body
+        let mut function_binary = Executable::new(name.to_string());
+        function_binary.source = self.source();
+        self.compile_node(&mut function_binary, body)?;
 
-            // Compile the function body
-            let mut function_binary = Executable::new(name.clone());
-            self.compile_node(&mut function_binary, body)?;
+        // Always add return nil; to the end in case there is no explicit return statement
+        function_binary.push_constant(Value::Nil, function_span)?;
+        function_binary.push_opcode(OpCode::Return, body.span);
 
-            // Always add return nil; to the end in case there is no explicit return statement
-            let index = function_binary.add_constant(Value::Nil);
-            function_binary.push_opcode(OpCode::Constant(index), function_span);
-            function_binary.push_opcode(OpCode::Return, body.span);
+        // Disassemble the function body if enabled
+        if cfg!(feature = "disassemble") {
+            function_binary.dump(self.globals, &mut std::io::stdout());
+        }
 
-            // Disassemble the function body if enabled
-            if cfg!(feature = "disassemble") {
-                function_binary.dump(&mut std::io::stdout());
-            }
+        // End the scope and restore the outer function's frame
+        self.current_frame_mut()
+            .end_scope(&mut function_binary, body.span);
+        let function_frame = self.frames.pop_back().unwrap();
+
+        // Put the function object on the top of the stack and create a closure
+        let function_value = Value::from(ObjFunction {
+            name: Box::new(ObjString::from(name)),
+            arity: parameters.len() as u8,
+            bin: function_binary,
+            upvalues: function_frame.upvalues.into_iter().collect(),
+        });
+        let index = bin.add_operand_constant(function_value, function_span)?;
+        bin.push_opcode(OpCode::Closure(index), function_span);
 
-            // End the scope and restore the outer function's frame
-            self.current_frame_mut()
-                .end_scope(&mut function_binary, body.span);
-            self.frames.pop_back();
-
-            // Put the function object on the top of the stack and create a closure
-            let function_value = Value::from(ObjFunction {
-                name: Box::new(ObjString::from(name.clone())),
-                arity: parameters.len() as u8,
-                bin: function_binary,
-                upvalues: self.current_frame_mut().upvalues.drain(0..).collect(),
-            });
-            let index = bin.add_constant(function_value);
-            bin.push_opcode(OpCode::Closure(index), function_span);
+        Ok(())
+    }
+}
 
-            Ok(())
+impl Executable {
+    /// Add `value` as a constant and emit the instruction to load it, using
+    /// `ConstantLong` once there are too many constants for `Constant`.
+    fn push_constant(&mut self, value: Value, span: Span) -> Result<usize, CompilerError> {
+        let index = self.add_constant(value);
+        let op = if index <= OpCode::Constant(0).max_argument() {
+            OpCode::Constant(index)
+        } else if index <= OpCode::ConstantLong(0).max_argument() {
+            OpCode::ConstantLong(index)
         } else {
+            return Err(CompilerError {
+                message: "Too many constants in one function".to_string(),
+                span,
+            });
+        };
+        Ok(self.push_opcode(op, span))
+    }
+
+    /// Add the name of a variable, field, or method as a constant
+    fn add_identifier(&mut self, name: &str, span: Span) -> Result<usize, CompilerError> {
+        self.add_operand_constant(Value::from(name), span)
+    }
+
+    /// Add a constant to be used as the argument of an instruction other than
+    /// `Constant`, erroring if its index is too large to be encoded
+    fn add_operand_constant(&mut self, value: Value, span: Span) -> Result<usize, CompilerError> {
+        let index = self.add_constant(value);
+        if index > u16::MAX as usize {
             Err(CompilerError {
-                message: "compiler.function_declaration called with non-FunctionDeclaration node"
-                    .to_string(),
-                span: function_span,
+                message: "Too many constants in one function".to_string(),
+                span,
             })
+        } else {
+            Ok(index)
         }
     }
-}
 
-impl Executable {
-    /// Errors if self is longer than the executable length limit
+    /// Errors if self is longer than the executable length limit, which is
+    /// the furthest destination a jump can encode
     fn assert_not_too_long(&self, span: &Span) -> Result<(), CompilerError> {
-        if self.len() > u16::max_value() as usize {
+        let max_length = OpCode::Jump(0).max_argument();
+        if self.len() > max_length {
             Err(CompilerError {
-                message: format!(
-                    "Binary may not be more than {} bytes long.",
-                    u16::max_value()
-                ),
+                message: format!("Binary may not be more than {} bytes long.", max_length),
                 span: *span,
             })
         } else {
             Ok(())
         }
     }
+
+    /// Point the jump at `offset` to the end of the executable, erroring if
+    /// the executable is too long for the jump to reach its end
+    fn patch_jump_to_end(&mut self, offset: usize, span: &Span) -> Result<(), CompilerError> {
+        self.assert_not_too_long(span)?;
+        self.patch_jump(offset, self.len());
+        Ok(())
+    }
 }
 
 impl SpannedAstNode {
@@ -594,11 +1382,20 @@ impl SpannedAstNode {
     }
 }
 
+/// A variable declared in a local scope
+#[derive(Debug)]
+struct Local {
+    name: String,
+
+    /// Whether any closure captures this variable as an upvalue
+    is_captured: bool,
+}
+
 /// A record of all the variables declared in a single scope
 #[derive(Debug)]
 struct LocalScope {
     pub offset: usize,
-    locals: Vec<String>,
+    locals: Vec<Local>,
 }
 
 impl LocalScope {
@@ -610,8 +1407,8 @@ impl LocalScope {
     }
 
     fn resolve(&self, name: &str) -> Option<usize> {
-        for (index, n) in self.locals.iter().enumerate() {
-            if name == n {
+        for (index, local) in self.locals.iter().enumerate() {
+            if name == local.name {
                 return Some(index);
             }
         }
@@ -619,7 +1416,10 @@ impl LocalScope {
     }
 
     fn push(&mut self, name: String) {
-        self.locals.push(name);
+        self.locals.push(Local {
+            name,
+            is_captured: false,
+        });
     }
 
     fn len(&self) -> usize {
@@ -634,6 +1434,54 @@ pub enum FunctionType {
     Method,
 }
 
+/// A try statement that encloses the code being compiled
+#[derive(Debug, Clone)]
+struct TryBlock {
+    /// Whether a handler is installed while the enclosed code runs
+    has_handler: bool,
+
+    /// The block to run when leaving the try statement
+    finally: Option<Rc<SpannedAstNode>>,
+}
+
+/// A loop that encloses the code being compiled
+#[derive(Debug)]
+struct Loop {
+    label: Option<String>,
+
+    /// The number of scopes outside of the loop body
+    scope_depth: usize,
+
+    /// The number of try statements outside of the loop
+    try_depth: usize,
+
+    /// The offsets of the jumps emitted by `break` statements
+    breaks: Vec<usize>,
+
+    /// The offsets of the jumps emitted by `continue` statements
+    continues: Vec<usize>,
+}
+
+impl Loop {
+    /// Point the jumps of the loop's `continue` statements at `next_iteration`
+    /// and those of its `break` statements at the end of `bin`
+    fn patch_jumps(
+        &self,
+        bin: &mut Executable,
+        next_iteration: usize,
+        span: &Span,
+    ) -> Result<(), CompilerError> {
+        bin.assert_not_too_long(span)?;
+        for jump in self.continues.iter() {
+            bin.patch_jump(*jump, next_iteration);
+        }
+        for jump in self.breaks.iter() {
+            bin.patch_jump(*jump, bin.len());
+        }
+        Ok(())
+    }
+}
+
 /// A record of all the variables declared in a single function
 #[derive(Debug)]
 struct Frame {
@@ -641,6 +1489,12 @@ struct Frame {
     upvalues: VecDeque<(bool, usize)>,
     is_global: bool,
     function_type: FunctionType,
+
+    /// The try statements of this function enclosing the current code, innermost last
+    try_blocks: Vec<TryBlock>,
+
+    /// The loops of this function enclosing the current code, innermost last
+    loops: Vec<Loop>,
 }
 
 impl Frame {
@@ -652,6 +1506,8 @@ impl Frame {
             is_global,
             upvalues: VecDeque::new(),
             function_type,
+            try_blocks: vec![],
+            loops: vec![],
         }
     }
 
@@ -670,6 +1526,15 @@ impl Frame {
         self.upvalues.len() - 1
     }
 
+    /// Mark the local at `index` (offset from frame pointer) as captured by a closure
+    fn capture_local(&mut self, index: usize) {
+        for scope in self.scopes.iter_mut() {
+            if index >= scope.offset && index < scope.offset + scope.len() {
+                scope.locals[index - scope.offset].is_captured = true;
+            }
+        }
+    }
+
     /// Resolves a local to (offset from frame pointer, distance to scope)
     fn resolve_local(&self, name: &str) -> Option<(usize, usize)> {
         for (distance, scope) in self.scopes.iter().rev().enumerate() {
@@ -684,6 +1549,13 @@ impl Frame {
         self.is_global && self.scopes.len() == 1
     }
 
+    /// The number of locals currently on the stack for this frame
+    fn local_count(&self) -> usize {
+        self.scopes
+            .back()
+            .map_or(0, |scope| scope.offset + scope.len())
+    }
+
     fn begin_scope(&mut self) {
         let new_scope = match self.scopes.back() {
             Some(parent) => LocalScope::new(parent.offset + parent.len()),
@@ -692,10 +1564,31 @@ impl Frame {
         self.scopes.push_back(new_scope)
     }
 
+    /// Emit the instructions to discard the locals of the innermost scope,
+    /// closing any that are captured by closures, then remove the scope.
     fn end_scope(&mut self, bin: &mut Executable, end_span: Span) {
-        for _ in 0..self.scopes.back().unwrap().len() {
-            bin.push_opcode(OpCode::Pop, end_span);
+        self.exit_scopes(self.scopes.len() - 1, bin, end_span);
+        self.scopes.pop_back();
+    }
+
+    /// Emit the instructions to discard the locals of every scope beyond the
+    /// outermost `depth`, innermost first, closing any that are captured by
+    /// closures. The scopes remain in place.
+    fn exit_scopes(&self, depth: usize, bin: &mut Executable, end_span: Span) {
+        for scope in self.scopes.iter().skip(depth).rev() {
+            for local in scope.locals.iter().rev() {
+                if local.is_captured {
+                    bin.push_opcode(OpCode::CloseUpvalue, end_span);
+                } else {
+                    bin.push_opcode(OpCode::Pop, end_span);
+                }
+            }
         }
+    }
+
+    /// Remove the innermost scope without emitting any instructions. Used
+    /// when the code at the end of the scope is never reached.
+    fn discard_scope(&mut self) {
         self.scopes.pop_back();
     }
 }
@@ -3,13 +3,22 @@ use std::cmp;
 use std::io::Write;
 
 pub use crate::compiler_error::*;
+pub use crate::load_error::*;
 pub use crate::parser_error::*;
 pub use crate::vm_error::*;
 
+/// The most frames of a stack trace that will be printed
+const MAX_TRACE_FRAMES: usize = 16;
+
 /// The error trait required on any input to `ErrorReporter`.
 pub trait ReportableError {
     fn span(&self) -> Span;
     fn message(&self) -> String;
+
+    /// The calls that led to the error, innermost first
+    fn trace(&self) -> &[TraceFrame] {
+        &[]
+    }
 }
 
 /// Reports errors by writing to a stream with the `Write` Trait
@@ -31,19 +40,70 @@ impl<'a, W: 'a + Write> ErrorReporter<'a, W> {
     }
 
     /// Report an error. This outputs the message from `error` and the relevent bits of source code.
+    ///
+    /// Errors raised by the code of an imported module are shown in the source of that module.
     pub fn report<E: ReportableError>(&mut self, error: &E) {
+        let source = match error
+            .trace()
+            .first()
+            .and_then(|frame| frame.source.as_ref())
+        {
+            Some(file) => &file.text,
+            None => &self.source,
+        };
         writeln!(self.error_stream, "{}", error.message()).unwrap();
-        Self::print_underlined_source(&self.source, self.error_stream, &error.span());
+        Self::print_underlined_source(source, self.error_stream, &error.span());
+        Self::print_trace(&self.source, self.error_stream, error.trace());
+    }
+
+    /// Print one line per frame of `trace`, innermost first. Very deep traces
+    /// are abbreviated to their innermost frames and the outermost frame.
+    fn print_trace<T: Write>(source: &str, error_stream: &mut T, trace: &[TraceFrame]) {
+        for (index, frame) in trace.iter().enumerate() {
+            if trace.len() > MAX_TRACE_FRAMES && index == MAX_TRACE_FRAMES - 1 {
+                let skipped = trace.len() - MAX_TRACE_FRAMES;
+                writeln!(error_stream, "[... {} more calls]", skipped).unwrap();
+            }
+            if trace.len() > MAX_TRACE_FRAMES
+                && index >= MAX_TRACE_FRAMES - 1
+                && index < trace.len() - 1
+            {
+                continue;
+            }
+
+            let location = match &frame.source {
+                Some(file) => format!(
+                    "line {} of {}",
+                    Self::line_number(&file.text, frame.span.start),
+                    file.path
+                ),
+                None => format!("line {}", Self::line_number(source, frame.span.start)),
+            };
+            if frame.function == "script" {
+                writeln!(error_stream, "[{}] in script", location).unwrap();
+            } else {
+                writeln!(error_stream, "[{}] in {}()", location, frame.function).unwrap();
+            }
+        }
+    }
+
+    /// The 1-based number of the line in `source` that contains `index`
+    fn line_number(source: &str, index: usize) -> usize {
+        source
+            .char_indices()
+            .take_while(|(i, _)| *i < index)
+            .filter(|(_, ch)| *ch == '\n')
+            .count()
+            + 1
     }
 
     /// Print the portion of `source` that is indicated by `span` to `error_stream`, underlined.
     /// Also print all lines that contain any underlined `source`.
     fn print_underlined_source<T: Write>(source: &str, error_stream: &mut T, span: &Span) {
         let mut line_start: usize = 0;
-        let mut line_num: usize = 1;
-        for line in source.split('\n') {
+        for (line_num, line) in (1..).zip(source.split('\n')) {
             if line_start <= span.end && line_start + line.len() >= span.start {
-                let underline_start = span.start - line_start;
+                let underline_start = span.start.saturating_sub(line_start);
                 let underline_end = cmp::min(line.len() + 1, span.end - line_start);
                 Self::print_underlined(
                     error_stream,
@@ -54,7 +114,6 @@ impl<'a, W: 'a + Write> ErrorReporter<'a, W> {
                 );
             }
             line_start += line.len() + 1;
-            line_num += 1;
         }
     }
 
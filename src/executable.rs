@@ -1,38 +1,30 @@
+use crate::module::SourceFile;
 use crate::opcode::OpCode;
+use crate::symbol::GlobalSlots;
 use crate::token::Span;
 use crate::value::Value;
 use std::io::Write;
-use std::ops::Index;
-use std::ops::IndexMut;
+use std::rc::Rc;
 
 /// An Executable contains the output of compilation to be run on a VM.
 #[derive(Debug, PartialEq)]
 pub struct Executable {
-    /// The OpCodes and arguments to be executed
-    code: Vec<OpCode>,
+    /// The encoded OpCodes and arguments to be executed
+    code: Vec<u8>,
 
     /// The static Values referenced by the executable code
     constants: Vec<Value>,
 
-    /// The source line numbers associated with each OpCode.
-    /// `lines[i]` is the source line number of `code[i]`.
-    pub spans: Vec<Span>,
+    /// The offset of each instruction in `code` and the source span it was
+    /// compiled from, in order of offset
+    spans: Vec<(usize, Span)>,
 
     /// The name of the executable unit. Could be a function name or <script>
     pub name: String,
-}
 
-impl Index<usize> for Executable {
-    type Output = OpCode;
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.code[index]
-    }
-}
-
-impl IndexMut<usize> for Executable {
-    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        &mut self.code[index]
-    }
+    /// The imported module that the executable was compiled from.
+    /// None for the main script.
+    pub source: Option<Rc<SourceFile>>,
 }
 
 #[allow(clippy::len_without_is_empty)]
@@ -44,14 +36,51 @@ impl Executable {
             spans: vec![],
             constants: vec![],
             name,
+            source: None,
         }
     }
 
-    /// Append an OpCode to the Executable, returning its index
+    /// Append an OpCode to the Executable, returning its offset
     pub fn push_opcode(&mut self, code: OpCode, span: Span) -> usize {
-        self.code.push(code);
-        self.spans.push(span);
-        self.code.len() - 1
+        let offset = self.code.len();
+        code.encode(&mut self.code);
+        self.spans.push((offset, span));
+        offset
+    }
+
+    /// The span of the instruction containing the byte at `offset`
+    pub fn span_at(&self, offset: usize) -> Span {
+        let index = self.spans.partition_point(|(start, _)| *start <= offset);
+        self.spans[index.saturating_sub(1)].1
+    }
+
+    /// Decode the instruction at `offset`, returning it along with the offset
+    /// of the following instruction.
+    ///
+    /// Panics if `offset` is not the start of an instruction.
+    #[inline]
+    pub fn read_instruction(&self, offset: usize) -> (OpCode, usize) {
+        OpCode::decode(&self.code, offset).expect("Invalid instruction offset")
+    }
+
+    /// Replace the jump or handler instruction at `offset` with one of the same kind to `destination`
+    pub fn patch_jump(&mut self, offset: usize, destination: usize) {
+        let patched = match self.read_instruction(offset).0 {
+            OpCode::Jump(_) => OpCode::Jump(destination),
+            OpCode::JumpIfTrue(_) => OpCode::JumpIfTrue(destination),
+            OpCode::JumpIfFalse(_) => OpCode::JumpIfFalse(destination),
+            OpCode::PushHandler(_) => OpCode::PushHandler(destination),
+            op => panic!("Attempted to patch non-jump instruction {:?}", op),
+        };
+
+        let mut encoded = vec![];
+        patched.encode(&mut encoded);
+        self.code[offset..offset + encoded.len()].copy_from_slice(&encoded);
+    }
+
+    /// The encoded instructions
+    pub fn code(&self) -> &[u8] {
+        &self.code
     }
 
     /// Retrieve a constant by index from the Executable's constants table.
@@ -59,6 +88,11 @@ impl Executable {
         &self.constants[index]
     }
 
+    /// All of the constants referenced by the Executable
+    pub fn constants(&self) -> &[Value] {
+        &self.constants
+    }
+
     /// Add a constant and return its index
     pub fn add_constant(&mut self, value: Value) -> usize {
         self.constants.push(value);
@@ -70,22 +104,33 @@ impl Executable {
         self.code.len()
     }
 
-    /// Disassemble this Executable and print the result
-    pub fn dump<W: Write>(&self, out: &mut W) {
+    /// Disassemble this Executable and print the result, naming the globals
+    /// it references from `globals`
+    pub fn dump<W: Write>(&self, globals: &GlobalSlots, out: &mut W) {
         writeln!(out).unwrap();
         writeln!(out, "(Dumping: {})", self.name).unwrap();
         writeln!(out, "Index  OpCode              Arguments").unwrap();
         writeln!(out, "------------------------------------").unwrap();
-        for offset in 0..self.code.len() {
-            self.disassemble_instruction(offset, out);
+        let mut offset = 0;
+        while offset < self.code.len() {
+            offset = self.disassemble_instruction(offset, globals, out);
         }
         writeln!(out).unwrap();
     }
 
-    pub fn disassemble_instruction<W: Write>(&self, offset: usize, out: &mut W) {
+    /// Disassemble and print the instruction at `offset`, returning the offset
+    /// of the following instruction
+    pub fn disassemble_instruction<W: Write>(
+        &self,
+        offset: usize,
+        globals: &GlobalSlots,
+        out: &mut W,
+    ) -> usize {
         write!(out, "{:0>5}  ", offset).unwrap();
-        match self.code[offset] {
+        let (op, next) = self.read_instruction(offset);
+        match op {
             OpCode::Constant(arg) => self.constant_instruction("Constant", arg, out),
+            OpCode::ConstantLong(arg) => self.constant_instruction("ConstantLong", arg, out),
             OpCode::Return => self.simple_instruction("Return", out),
             OpCode::Add => self.simple_instruction("Add", out),
             OpCode::Subtract => self.simple_instruction("Subtract", out),
@@ -101,9 +146,11 @@ impl Executable {
             OpCode::NotEqual => self.simple_instruction("NotEqual", out),
             OpCode::Print => self.simple_instruction("Print", out),
             OpCode::Pop => self.simple_instruction("Pop", out),
-            OpCode::DeclareGlobal(arg) => self.constant_instruction("DeclareGlobal", arg, out),
-            OpCode::GetGlobal(arg) => self.constant_instruction("GetGlobal", arg, out),
-            OpCode::SetGlobal(arg) => self.constant_instruction("SetGlobal", arg, out),
+            OpCode::DeclareGlobal(arg) => {
+                self.global_instruction("DeclareGlobal", arg, globals, out)
+            }
+            OpCode::GetGlobal(arg) => self.global_instruction("GetGlobal", arg, globals, out),
+            OpCode::SetGlobal(arg) => self.global_instruction("SetGlobal", arg, globals, out),
             OpCode::GetLocal(arg) => self.single_arg_instruction("GetLocal", arg, out),
             OpCode::SetLocal(arg) => self.single_arg_instruction("SetLocal", arg, out),
             OpCode::GetSuper(arg) => self.constant_instruction("GetSuper", arg, out),
@@ -114,21 +161,55 @@ impl Executable {
             OpCode::Closure(arg) => self.constant_instruction("Closure", arg, out),
             OpCode::GetUpvalue(arg) => self.single_arg_instruction("GetUpvalue", arg, out),
             OpCode::SetUpvalue(arg) => self.single_arg_instruction("SetUpvalue", arg, out),
+            OpCode::CloseUpvalue => self.simple_instruction("CloseUpvalue", out),
             OpCode::ReadField(arg) => self.constant_instruction("ReadField", arg, out),
             OpCode::SetField(arg) => self.constant_instruction("SetField", arg, out),
             OpCode::Method => self.simple_instruction("Method", out),
             OpCode::Inherit => self.simple_instruction("Inherit", out),
             OpCode::Bool => self.simple_instruction("Bool", out),
+            OpCode::BuildList(arg) => self.single_arg_instruction("BuildList", arg, out),
+            OpCode::GetIndex => self.simple_instruction("GetIndex", out),
+            OpCode::SetIndex => self.simple_instruction("SetIndex", out),
+            OpCode::BuildMap(arg) => self.single_arg_instruction("BuildMap", arg, out),
+            OpCode::PushHandler(arg) => self.single_arg_instruction("PushHandler", arg, out),
+            OpCode::PopHandler => self.simple_instruction("PopHandler", out),
+            OpCode::Throw => self.simple_instruction("Throw", out),
+            OpCode::Rethrow => self.simple_instruction("Rethrow", out),
+            OpCode::Import(arg) => self.constant_instruction("Import", arg, out),
+            OpCode::Stringify => self.simple_instruction("Stringify", out),
+            OpCode::Modulo => self.simple_instruction("Modulo", out),
+            OpCode::Power => self.simple_instruction("Power", out),
+            OpCode::BitAnd => self.simple_instruction("BitAnd", out),
+            OpCode::BitOr => self.simple_instruction("BitOr", out),
+            OpCode::BitXor => self.simple_instruction("BitXor", out),
+            OpCode::BitNot => self.simple_instruction("BitNot", out),
+            OpCode::ShiftLeft => self.simple_instruction("ShiftLeft", out),
+            OpCode::ShiftRight => self.simple_instruction("ShiftRight", out),
+            OpCode::Duplicate(arg) => self.single_arg_instruction("Duplicate", arg, out),
+            OpCode::Swap => self.simple_instruction("Swap", out),
+            OpCode::ClassMethod => self.simple_instruction("ClassMethod", out),
+            OpCode::Getter => self.simple_instruction("Getter", out),
         }
+        next
     }
 
     fn simple_instruction<W: Write>(&self, name: &str, out: &mut W) {
         writeln!(out, "{0:<16}", name).unwrap();
     }
     fn constant_instruction<W: Write>(&self, name: &str, index: usize, out: &mut W) {
-        let value = &self.constants[index as usize];
+        let value = &self.constants[index];
         writeln!(out, "{:<16} {:>4}[{:?}]", name, index, value).unwrap();
     }
+    fn global_instruction<W: Write>(
+        &self,
+        name: &str,
+        slot: usize,
+        globals: &GlobalSlots,
+        out: &mut W,
+    ) {
+        let global = globals.name(slot).map_or(String::new(), |g| g.to_string());
+        writeln!(out, "{:<16} {:>4}[{}]", name, slot, global).unwrap();
+    }
     fn single_arg_instruction<W: Write>(&self, name: &str, arg: usize, out: &mut W) {
         writeln!(out, "{:<16} {:>4}", name, arg).unwrap();
     }
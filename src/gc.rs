@@ -0,0 +1,289 @@
+use crate::object::{ObjClosure, ObjInstance, ObjList, ObjMap, ObjUpvalue};
+use crate::value::Value;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::{Rc, Weak};
+
+/// The number of tracked objects that triggers the first automatic collection
+const INITIAL_COLLECTION_THRESHOLD: usize = 1024;
+
+/// After a collection, the next one is triggered once the number of tracked
+/// objects grows to this multiple of the number of survivors.
+const HEAP_GROW_FACTOR: usize = 2;
+
+/// Statistics describing the work done by the garbage collector
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct GcStats {
+    /// The number of collections that have run
+    pub collections: usize,
+
+    /// The number of tracked objects that survived the most recent collection
+    pub live_objects: usize,
+
+    /// The total number of unreachable objects reclaimed by all collections
+    pub objects_collected: usize,
+}
+
+/// A heap object whose interior can point back at itself.
+///
+/// Only objects with mutable references to other values are tracked. Any
+/// reference cycle must pass through at least one of them, so clearing the
+/// unreachable ones is enough for `Rc` to free the whole cycle.
+#[derive(Debug)]
+enum TrackedObject {
+    Instance(Weak<ObjInstance>),
+    List(Weak<ObjList>),
+    Map(Weak<ObjMap>),
+    Upvalue(Weak<RefCell<ObjUpvalue>>),
+}
+
+/// Tracks heap objects so that unreachable reference cycles between them can be collected.
+///
+/// Objects are reference counted, so anything that isn't part of a cycle is freed as
+/// soon as it becomes unreachable. A collection marks every object reachable from the
+/// roots, then breaks the cycles of the tracked objects that were not marked.
+#[derive(Debug)]
+pub struct Heap {
+    objects: Vec<TrackedObject>,
+    next_collection: usize,
+    stats: GcStats,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heap {
+    /// Create a new, empty Heap
+    pub fn new() -> Self {
+        Heap {
+            objects: vec![],
+            next_collection: INITIAL_COLLECTION_THRESHOLD,
+            stats: GcStats::default(),
+        }
+    }
+
+    /// Start tracking a newly allocated instance
+    pub fn track_instance(&mut self, instance: &Rc<ObjInstance>) {
+        self.objects
+            .push(TrackedObject::Instance(Rc::downgrade(instance)));
+    }
+
+    /// Start tracking a newly allocated list
+    pub fn track_list(&mut self, list: &Rc<ObjList>) {
+        self.objects.push(TrackedObject::List(Rc::downgrade(list)));
+    }
+
+    /// Start tracking a newly allocated map
+    pub fn track_map(&mut self, map: &Rc<ObjMap>) {
+        self.objects.push(TrackedObject::Map(Rc::downgrade(map)));
+    }
+
+    /// Start tracking a newly allocated upvalue
+    pub fn track_upvalue(&mut self, upvalue: &Rc<RefCell<ObjUpvalue>>) {
+        self.objects
+            .push(TrackedObject::Upvalue(Rc::downgrade(upvalue)));
+    }
+
+    /// Indicates whether enough objects have been allocated since the last
+    /// collection that another should be run
+    pub fn should_collect(&self) -> bool {
+        self.objects.len() >= self.next_collection
+    }
+
+    /// The statistics gathered so far
+    pub fn stats(&self) -> GcStats {
+        self.stats
+    }
+
+    /// Break the cycles of every tracked object that isn't reachable from `roots`.
+    pub fn collect(&mut self, roots: &Marker) {
+        let mut collected = 0;
+        for object in self.objects.iter() {
+            match object {
+                TrackedObject::Instance(weak) => {
+                    if let Some(instance) = weak.upgrade() {
+                        if !roots.is_marked(Rc::as_ptr(&instance) as *const ()) {
+                            instance.fields.borrow_mut().clear();
+                            collected += 1;
+                        }
+                    }
+                }
+                TrackedObject::List(weak) => {
+                    if let Some(list) = weak.upgrade() {
+                        if !roots.is_marked(Rc::as_ptr(&list) as *const ()) {
+                            list.items.borrow_mut().clear();
+                            collected += 1;
+                        }
+                    }
+                }
+                TrackedObject::Map(weak) => {
+                    if let Some(map) = weak.upgrade() {
+                        if !roots.is_marked(Rc::as_ptr(&map) as *const ()) {
+                            map.entries.borrow_mut().clear();
+                            collected += 1;
+                        }
+                    }
+                }
+                TrackedObject::Upvalue(weak) => {
+                    if let Some(upvalue) = weak.upgrade() {
+                        if !roots.is_marked(Rc::as_ptr(&upvalue) as *const ()) {
+                            upvalue.replace(ObjUpvalue::Closed(Value::Nil));
+                            collected += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Forget about everything that has now been freed
+        self.objects.retain(|object| match object {
+            TrackedObject::Instance(weak) => weak.strong_count() > 0,
+            TrackedObject::List(weak) => weak.strong_count() > 0,
+            TrackedObject::Map(weak) => weak.strong_count() > 0,
+            TrackedObject::Upvalue(weak) => weak.strong_count() > 0,
+        });
+
+        self.stats.collections += 1;
+        self.stats.live_objects = self.objects.len();
+        self.stats.objects_collected += collected;
+        self.next_collection = std::cmp::max(
+            self.objects.len() * HEAP_GROW_FACTOR,
+            INITIAL_COLLECTION_THRESHOLD,
+        );
+    }
+}
+
+/// Records every object that is reachable from a set of roots.
+///
+/// Marking is iterative: each newly marked object's references are pushed onto
+/// a worklist of gray values instead of being marked recursively, so long chains
+/// of objects cannot overflow the stack.
+#[derive(Debug, Default)]
+pub struct Marker {
+    marked: HashSet<*const ()>,
+
+    /// Values that are reachable but whose references have not been marked yet
+    gray: Vec<Value>,
+}
+
+impl Marker {
+    /// Create a new Marker with nothing marked
+    pub fn new() -> Self {
+        Marker {
+            marked: HashSet::new(),
+            gray: vec![],
+        }
+    }
+
+    /// Indicates whether the object at `address` has been marked
+    fn is_marked(&self, address: *const ()) -> bool {
+        self.marked.contains(&address)
+    }
+
+    /// Marks the object at `address`, returning false if it was already marked
+    fn mark(&mut self, address: *const ()) -> bool {
+        self.marked.insert(address)
+    }
+
+    /// Mark a value and everything reachable from it
+    pub fn mark_value(&mut self, value: &Value) {
+        self.gray.push(value.clone());
+        self.trace();
+    }
+
+    /// Mark a closure, its function, and its upvalues
+    pub fn mark_closure(&mut self, closure: &Rc<ObjClosure>) {
+        self.mark_value(&Value::Closure(closure.clone()));
+    }
+
+    /// Mark an upvalue and the value it holds, if it is closed
+    pub fn mark_upvalue(&mut self, upvalue: &Rc<RefCell<ObjUpvalue>>) {
+        self.gray_upvalue(upvalue);
+        self.trace();
+    }
+
+    /// Mark the gray values until there are none left
+    fn trace(&mut self) {
+        while let Some(value) = self.gray.pop() {
+            self.blacken(&value);
+        }
+    }
+
+    /// Mark the object held by `value`, if it has not been marked yet, and
+    /// add the values it refers to to the gray values
+    fn blacken(&mut self, value: &Value) {
+        match value {
+            Value::Function(function) => {
+                if self.mark(Rc::as_ptr(function) as *const ()) {
+                    self.gray.extend(function.bin.constants().iter().cloned());
+                }
+            }
+            Value::Closure(closure) => {
+                if self.mark(Rc::as_ptr(closure) as *const ()) {
+                    self.gray.push(Value::Function(closure.function.clone()));
+                    for upvalue in closure.upvalues.iter() {
+                        self.gray_upvalue(upvalue);
+                    }
+                }
+            }
+            Value::Class(class) => {
+                if self.mark(Rc::as_ptr(class) as *const ()) {
+                    let methods = [&class.methods, &class.class_methods, &class.getters];
+                    for methods in methods.iter() {
+                        let closures = methods.borrow();
+                        self.gray
+                            .extend(closures.values().cloned().map(Value::Closure));
+                    }
+                }
+            }
+            Value::Instance(instance) => {
+                if self.mark(Rc::as_ptr(instance) as *const ()) {
+                    self.gray.push(Value::Class(instance.class.clone()));
+                    self.gray.extend(instance.fields.borrow().values().cloned());
+                }
+            }
+            Value::BoundMethod(method) => {
+                if self.mark(Rc::as_ptr(method) as *const ()) {
+                    self.gray.push(Value::Instance(method.receiver.clone()));
+                    self.gray.push(Value::Closure(method.method.clone()));
+                }
+            }
+            Value::NativeMethod(method) => {
+                if self.mark(Rc::as_ptr(method) as *const ()) {
+                    self.gray.push(method.receiver.clone());
+                }
+            }
+            Value::List(list) => {
+                if self.mark(Rc::as_ptr(list) as *const ()) {
+                    self.gray.extend(list.items.borrow().iter().cloned());
+                }
+            }
+            Value::Map(map) => {
+                if self.mark(Rc::as_ptr(map) as *const ()) {
+                    for (key, value) in map.entries.borrow().iter() {
+                        self.gray.push(key.clone());
+                        self.gray.push(value.clone());
+                    }
+                }
+            }
+            Value::Number(_)
+            | Value::Bool(_)
+            | Value::Nil
+            | Value::String(_)
+            | Value::Native(_)
+            | Value::Module(_) => {}
+        }
+    }
+
+    /// Mark an upvalue, adding the value it holds to the gray values if it is closed
+    fn gray_upvalue(&mut self, upvalue: &Rc<RefCell<ObjUpvalue>>) {
+        if self.mark(Rc::as_ptr(upvalue) as *const ()) {
+            if let ObjUpvalue::Closed(value) = &*upvalue.borrow() {
+                self.gray.push(value.clone());
+            }
+        }
+    }
+}
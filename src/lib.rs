@@ -2,14 +2,20 @@ pub mod ast;
 pub mod compiler;
 pub mod error;
 pub mod executable;
+pub mod gc;
+pub mod module;
+pub mod native;
 pub mod object;
 pub mod opcode;
 pub mod parser;
 pub mod scanner;
+pub mod serialize;
+pub mod symbol;
 pub mod token;
 pub mod value;
 pub mod vm;
 
 pub mod compiler_error;
+pub mod load_error;
 pub mod parser_error;
 pub mod vm_error;
@@ -0,0 +1,23 @@
+use std::fmt;
+
+/// An error encountered while loading a compiled Lox file.
+///
+/// Unlike the other errors, a `LoadError` does not refer to any source
+/// code, so it is displayed directly rather than through an `ErrorReporter`.
+#[derive(Debug)]
+pub struct LoadError {
+    pub message: String,
+}
+
+impl LoadError {
+    /// Create a new LoadError with the given message
+    pub fn new(message: String) -> Self {
+        LoadError { message }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Load Error - {}", self.message)
+    }
+}
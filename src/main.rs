@@ -2,54 +2,98 @@ extern crate rlox;
 
 use rlox::compiler;
 use rlox::error::ErrorReporter;
+use rlox::object::ObjClosure;
 use rlox::parser::Parser;
+use rlox::serialize;
+use rlox::symbol::GlobalSlots;
 use rlox::vm::VM;
 use std::env;
 use std::fs;
 use std::io::{self, Write};
+use std::path::Path;
 
-fn run(source: String, vm: &mut VM) {
+const USAGE: &str = "Usage: clox [path]\n       clox --compile-only [-o out.loxc] path";
+
+/// Parse and compile `source` against `globals`, reporting any errors. Returns None
+/// if there were errors.
+fn compile(source: &str, globals: &mut GlobalSlots) -> Option<ObjClosure> {
     let mut stderr = std::io::stderr();
-    let mut reporter = ErrorReporter::new(&source, &mut stderr);
+    let mut reporter = ErrorReporter::new(source, &mut stderr);
 
     // Parse
-    let mut parser = Parser::new(&source);
+    let mut parser = Parser::new(source);
     let ast = match parser.parse_program() {
         Ok(ast) => ast,
         Err(errors) => {
             errors.iter().for_each(|e| reporter.report(e));
-            return;
+            return None;
         }
     };
 
     // Compile
-    let script = match compiler::compile(ast) {
-        Ok(bin) => bin,
-        Err(e) => {
-            reporter.report(&e);
-            return;
+    match compiler::compile(ast, globals) {
+        Ok(script) => Some(script),
+        Err(errors) => {
+            errors.iter().for_each(|e| reporter.report(e));
+            None
         }
-    };
+    }
+}
 
+/// Execute the compiled `script`, reporting any errors against `source`
+fn execute(script: &ObjClosure, source: &str, vm: &mut VM) {
     if cfg!(feature = "disassemble") {
-        script.function.bin.dump(&mut std::io::stdout());
+        script
+            .function
+            .bin
+            .dump(vm.global_slots(), &mut std::io::stdout());
     }
 
-    // Execute
     vm.reset();
-    match vm.execute(&script, &mut std::io::stdout()) {
+    match vm.execute(script, &mut std::io::stdout()) {
         Ok(_) => {}
         Err(e) => {
-            reporter.report(&e);
+            let mut stderr = std::io::stderr();
+            ErrorReporter::new(source, &mut stderr).report(&e);
         }
     }
 }
 
+fn run(source: String, vm: &mut VM) {
+    if let Some(script) = compile(&source, vm.global_slots_mut()) {
+        execute(&script, &source, vm);
+    }
+}
+
+/// Run the file at `filename`, which may contain either source code or a compiled script.
+/// Imports are resolved relative to the directory containing the file.
 fn run_file(filename: &str) {
-    let source = fs::read_to_string(&filename)
-        .unwrap_or_else(|_| panic!("Failed to read source file {}", filename));
+    let bytes =
+        fs::read(filename).unwrap_or_else(|_| panic!("Failed to read source file {}", filename));
     let mut vm = VM::new();
-    run(source, &mut vm);
+    vm.set_script_path(Path::new(filename));
+
+    if serialize::is_compiled(&bytes) {
+        match serialize::deserialize(&bytes, vm.global_slots_mut()) {
+            Ok((script, source)) => execute(&script, &source, &mut vm),
+            Err(e) => eprintln!("{}", e),
+        }
+    } else {
+        let source = String::from_utf8(bytes)
+            .unwrap_or_else(|_| panic!("Source file {} is not valid UTF-8", filename));
+        run(source, &mut vm);
+    }
+}
+
+/// Compile the source file at `filename` and write the compiled script to `output`
+fn compile_file(filename: &str, output: &str) {
+    let source = fs::read_to_string(filename)
+        .unwrap_or_else(|_| panic!("Failed to read source file {}", filename));
+    let mut globals = GlobalSlots::default();
+    if let Some(script) = compile(&source, &mut globals) {
+        fs::write(output, serialize::serialize(&script, &source, &globals))
+            .unwrap_or_else(|_| panic!("Failed to write compiled file {}", output));
+    }
 }
 
 fn repl() {
@@ -77,11 +121,18 @@ fn repl() {
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() == 2 {
-        run_file(&args[1]);
-    } else if args.len() == 1 {
-        repl();
-    } else {
-        eprintln!("Usage: clox [path]");
+    match &args[1..] {
+        [] => repl(),
+        [flag, path] if flag == "--compile-only" => {
+            let output = Path::new(path).with_extension("loxc");
+            compile_file(path, &output.to_string_lossy());
+        }
+        [flag, o, output, path] | [flag, path, o, output]
+            if flag == "--compile-only" && o == "-o" =>
+        {
+            compile_file(path, output)
+        }
+        [path] => run_file(path),
+        _ => eprintln!("{}", USAGE),
     }
 }
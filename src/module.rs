@@ -0,0 +1,56 @@
+use crate::compiler;
+use crate::error::ErrorReporter;
+use crate::object::ObjClosure;
+use crate::parser::Parser;
+use crate::symbol::GlobalSlots;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+/// The source code of an imported module, kept so that errors raised by
+/// the module's code can be reported against it
+#[derive(Debug, PartialEq)]
+pub struct SourceFile {
+    /// The path the module was imported from
+    pub path: String,
+
+    /// The contents of the file
+    pub text: String,
+}
+
+/// Read, parse and compile the module at `path`, assigning its globals to
+/// slots in `globals`.
+///
+/// Returns a closure which runs the module's top-level code and returns its
+/// namespace. If the module cannot be read or has errors, the returned
+/// message contains every error, reported against the module's source.
+pub fn load(path: &str, globals: &mut GlobalSlots) -> Result<ObjClosure, String> {
+    let text = fs::read_to_string(Path::new(path))
+        .map_err(|e| format!("Cannot read module '{}': {}", path, e))?;
+
+    let mut report = vec![];
+    let mut reporter = ErrorReporter::new(&text, &mut report);
+    let result = match Parser::new(&text).parse_program() {
+        Ok(program) => {
+            let source = Rc::new(SourceFile {
+                path: path.to_string(),
+                text: text.clone(),
+            });
+            compiler::compile_module(program, source, globals).map_err(|errors| {
+                errors.iter().for_each(|e| reporter.report(e));
+            })
+        }
+        Err(errors) => {
+            errors.iter().for_each(|e| reporter.report(e));
+            Err(())
+        }
+    };
+
+    result.map_err(|_| {
+        format!(
+            "Failed to compile module '{}'\n{}",
+            path,
+            String::from_utf8_lossy(&report).trim_end()
+        )
+    })
+}
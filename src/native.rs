@@ -0,0 +1,152 @@
+use crate::error::RuntimeError;
+use crate::object::{MapKey, ObjList, ObjMap};
+use crate::token::Span;
+use crate::value::Value;
+use crate::vm::VM;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Define the native functions that are available to every Lox program
+pub fn define_standard_library(vm: &mut VM) {
+    vm.define_native("clock", 0, clock);
+
+    vm.define_list_method("push", 1, list_push);
+    vm.define_list_method("pop", 0, list_pop);
+    vm.define_list_method("len", 0, list_len);
+    vm.define_list_method("insert", 2, list_insert);
+    vm.define_list_method("remove", 1, list_remove);
+
+    vm.define_map_method("keys", 0, map_keys);
+    vm.define_map_method("values", 0, map_values);
+    vm.define_map_method("has", 1, map_has);
+    vm.define_map_method("remove", 1, map_remove);
+    vm.define_map_method("len", 0, map_len);
+}
+
+/// Returns the number of seconds since the Unix epoch
+pub fn clock(_vm: &mut VM, _args: &[Value]) -> Result<Value, RuntimeError> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|e| {
+        RuntimeError::new(
+            format!("Failed to read the system clock: {}", e),
+            Span::new(0, 0),
+        )
+    })?;
+    Ok(Value::from(now.as_secs_f64()))
+}
+
+/// Appends `args[1]` to the end of the list
+pub fn list_push(_vm: &mut VM, args: &[Value]) -> Result<Value, RuntimeError> {
+    list_receiver(args)?
+        .items
+        .borrow_mut()
+        .push(args[1].clone());
+    Ok(Value::Nil)
+}
+
+/// Removes and returns the last item of the list
+pub fn list_pop(_vm: &mut VM, args: &[Value]) -> Result<Value, RuntimeError> {
+    list_receiver(args)?
+        .items
+        .borrow_mut()
+        .pop()
+        .ok_or_else(|| {
+            RuntimeError::new("Cannot pop from an empty list".to_string(), Span::new(0, 0))
+        })
+}
+
+/// Returns the number of items in the list
+pub fn list_len(_vm: &mut VM, args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::from(list_receiver(args)?.items.borrow().len() as f64))
+}
+
+/// Inserts `args[2]` into the list at the index `args[1]`, shifting later items up.
+/// The index may be the length of the list, which appends the item.
+pub fn list_insert(_vm: &mut VM, args: &[Value]) -> Result<Value, RuntimeError> {
+    let mut items = list_receiver(args)?.items.borrow_mut();
+    let index = ObjList::check_index(&args[1], items.len() + 1)
+        .map_err(|message| RuntimeError::new(message, Span::new(0, 0)))?;
+    items.insert(index, args[2].clone());
+    Ok(Value::Nil)
+}
+
+/// Removes and returns the item of the list at the index `args[1]`, shifting later items down
+pub fn list_remove(_vm: &mut VM, args: &[Value]) -> Result<Value, RuntimeError> {
+    let mut items = list_receiver(args)?.items.borrow_mut();
+    let index = ObjList::check_index(&args[1], items.len())
+        .map_err(|message| RuntimeError::new(message, Span::new(0, 0)))?;
+    Ok(items.remove(index))
+}
+
+/// The list that a list method was called on
+fn list_receiver(args: &[Value]) -> Result<&Rc<ObjList>, RuntimeError> {
+    match args.first() {
+        Some(Value::List(list)) => Ok(list),
+        _ => Err(RuntimeError::new(
+            "Expected a list receiver".to_string(),
+            Span::new(0, 0),
+        )),
+    }
+}
+
+/// Returns a list of the map's keys, in insertion order
+pub fn map_keys(vm: &mut VM, args: &[Value]) -> Result<Value, RuntimeError> {
+    let keys = map_receiver(args)?
+        .entries
+        .borrow()
+        .iter()
+        .map(|(key, _)| key.clone())
+        .collect();
+    Ok(vm.allocate_list(keys))
+}
+
+/// Returns a list of the map's values, in the insertion order of their keys
+pub fn map_values(vm: &mut VM, args: &[Value]) -> Result<Value, RuntimeError> {
+    let values = map_receiver(args)?
+        .entries
+        .borrow()
+        .iter()
+        .map(|(_, value)| value.clone())
+        .collect();
+    Ok(vm.allocate_list(values))
+}
+
+/// Returns whether the map contains the key `args[1]`
+pub fn map_has(_vm: &mut VM, args: &[Value]) -> Result<Value, RuntimeError> {
+    let key = map_key(&args[1])?;
+    Ok(Value::from(
+        map_receiver(args)?.entries.borrow().get(&key).is_some(),
+    ))
+}
+
+/// Removes the key `args[1]` from the map and returns its value
+pub fn map_remove(_vm: &mut VM, args: &[Value]) -> Result<Value, RuntimeError> {
+    let key = map_key(&args[1])?;
+    map_receiver(args)?
+        .entries
+        .borrow_mut()
+        .remove(&key)
+        .ok_or_else(|| RuntimeError::new(format!("Map has no key {}", key), Span::new(0, 0)))
+}
+
+/// Returns the number of entries in the map
+pub fn map_len(_vm: &mut VM, args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::from(
+        map_receiver(args)?.entries.borrow().len() as f64
+    ))
+}
+
+/// The map that a map method was called on
+fn map_receiver(args: &[Value]) -> Result<&Rc<ObjMap>, RuntimeError> {
+    match args.first() {
+        Some(Value::Map(map)) => Ok(map),
+        _ => Err(RuntimeError::new(
+            "Expected a map receiver".to_string(),
+            Span::new(0, 0),
+        )),
+    }
+}
+
+/// Converts `value` to a map key
+fn map_key(value: &Value) -> Result<MapKey, RuntimeError> {
+    MapKey::new(value.clone()).map_err(|message| RuntimeError::new(message, Span::new(0, 0)))
+}
@@ -1,8 +1,12 @@
+use crate::error::RuntimeError;
 use crate::executable::Executable;
+use crate::symbol::{self, Symbol};
 use crate::value::Value;
+use crate::vm::VM;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 
 pub struct ObjFunction {
@@ -37,10 +41,40 @@ impl Drop for ObjFunction {
     }
 }
 
-#[derive(PartialEq)]
+/// The signature of a function implemented by the host and callable from Lox.
+///
+/// The arguments are passed in order, not including the callee. Native methods
+/// of built-in types receive their receiver as the first argument.
+pub type NativeFn = fn(&mut VM, &[Value]) -> Result<Value, RuntimeError>;
+
+pub struct ObjNative {
+    pub arity: u8,
+    pub name: Box<ObjString>,
+    pub function: NativeFn,
+}
+
+impl fmt::Display for ObjNative {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn: {}>", self.name)
+    }
+}
+
+impl fmt::Debug for ObjNative {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn: {}>", self.name)
+    }
+}
+
+#[cfg(feature = "trace_drops")]
+impl Drop for ObjNative {
+    fn drop(&mut self) {
+        println!("**Dropped [{:?}]**", self)
+    }
+}
+
 pub struct ObjClosure {
     pub function: Rc<ObjFunction>,
-    pub upvalues: RefCell<Vec<ObjUpvalue>>,
+    pub upvalues: Vec<Rc<RefCell<ObjUpvalue>>>,
 }
 
 impl fmt::Display for ObjClosure {
@@ -62,20 +96,30 @@ impl Drop for ObjClosure {
     }
 }
 
-#[derive(PartialEq)]
-pub struct ObjUpvalue {
-    pub value: Value,
+/// A variable captured by a closure. While the variable is still on the
+/// stack the upvalue is `Open` and refers to the variable's stack slot.
+/// When the variable goes out of scope the upvalue is `Closed` and holds
+/// the value itself.
+pub enum ObjUpvalue {
+    Open(usize),
+    Closed(Value),
 }
 
 impl fmt::Display for ObjUpvalue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.value)
+        match self {
+            ObjUpvalue::Open(slot) => write!(f, "<open upvalue: {}>", slot),
+            ObjUpvalue::Closed(value) => write!(f, "{}", value),
+        }
     }
 }
 
 impl fmt::Debug for ObjUpvalue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "(upvalue {:?})", self.value)
+        match self {
+            ObjUpvalue::Open(slot) => write!(f, "(open upvalue {})", slot),
+            ObjUpvalue::Closed(value) => write!(f, "(upvalue {:?})", value),
+        }
     }
 }
 
@@ -86,13 +130,6 @@ impl Drop for ObjUpvalue {
     }
 }
 
-impl From<Value> for ObjUpvalue {
-    fn from(value: Value) -> Self {
-        ObjUpvalue { value }
-    }
-}
-
-#[derive(PartialEq)]
 pub struct ObjBoundMethod {
     pub receiver: Rc<ObjInstance>,
     pub method: Rc<ObjClosure>,
@@ -134,10 +171,12 @@ impl fmt::Debug for ObjString {
     }
 }
 
-#[cfg(feature = "trace_drops")]
 impl Drop for ObjString {
     fn drop(&mut self) {
-        println!("**Dropped [{:?}]**", self)
+        if cfg!(feature = "trace_drops") {
+            println!("**Dropped [{:?}]**", self)
+        }
+        symbol::release(&self.string);
     }
 }
 
@@ -155,10 +194,16 @@ impl From<&str> for ObjString {
     }
 }
 
-#[derive(PartialEq)]
 pub struct ObjClass {
     pub name: Box<ObjString>,
-    pub methods: RefCell<HashMap<String, Rc<ObjClosure>>>,
+    pub methods: RefCell<HashMap<Symbol, Rc<ObjClosure>>>,
+
+    /// Methods declared with `class`, which are called on the class itself
+    pub class_methods: RefCell<HashMap<Symbol, Rc<ObjClosure>>>,
+
+    /// Methods without a parameter list, which are called when the field
+    /// with their name is read from an instance
+    pub getters: RefCell<HashMap<Symbol, Rc<ObjClosure>>>,
 }
 
 impl fmt::Display for ObjClass {
@@ -180,10 +225,9 @@ impl Drop for ObjClass {
     }
 }
 
-#[derive(PartialEq)]
 pub struct ObjInstance {
     pub class: Rc<ObjClass>,
-    pub fields: RefCell<HashMap<String, Value>>,
+    pub fields: RefCell<HashMap<Symbol, Value>>,
 }
 
 impl fmt::Display for ObjInstance {
@@ -198,10 +242,12 @@ impl fmt::Debug for ObjInstance {
     }
 }
 
-#[cfg(feature = "trace_drops")]
 impl Drop for ObjInstance {
     fn drop(&mut self) {
-        println!("**Dropped [{:?}]**", self)
+        if cfg!(feature = "trace_drops") {
+            println!("**Dropped [{:?}]**", self)
+        }
+        drop_values(self.fields.take().into_values().collect());
     }
 }
 
@@ -213,3 +259,348 @@ impl From<&Rc<ObjClass>> for ObjInstance {
         }
     }
 }
+
+/// A native function bound to the built-in value it was accessed from,
+/// such as `list.push`
+pub struct ObjNativeMethod {
+    pub receiver: Value,
+    pub method: Rc<ObjNative>,
+}
+
+impl fmt::Display for ObjNativeMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.method)
+    }
+}
+
+impl fmt::Debug for ObjNativeMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(bound {:?})", self.method)
+    }
+}
+
+#[cfg(feature = "trace_drops")]
+impl Drop for ObjNativeMethod {
+    fn drop(&mut self) {
+        println!("**Dropped [{:?}]**", self)
+    }
+}
+
+pub struct ObjList {
+    pub items: RefCell<Vec<Value>>,
+}
+
+impl ObjList {
+    /// Converts `index` to a position in a list, requiring that it is an
+    /// integer in `0..limit`
+    pub fn check_index(index: &Value, limit: usize) -> Result<usize, String> {
+        match index {
+            Value::Number(n) if n.fract() == 0.0 => {
+                if *n >= 0.0 && (*n as usize) < limit {
+                    Ok(*n as usize)
+                } else {
+                    Err(format!("List index {} out of range", n))
+                }
+            }
+            _ => Err(format!("List index must be an integer, not {}", index)),
+        }
+    }
+
+    /// Write each item, separated by commas, using `write_item`. A list that
+    /// contains itself is written as `[...]` where it is nested.
+    fn fmt_items(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        write_item: fn(&Value, &mut fmt::Formatter<'_>) -> fmt::Result,
+    ) -> fmt::Result {
+        // The items are mutably borrowed while they are written, so a nested
+        // reference to this list will fail to borrow them
+        let items = match self.items.try_borrow_mut() {
+            Ok(items) => items,
+            Err(_) => return write!(f, "[...]"),
+        };
+
+        write!(f, "[")?;
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write_item(item, f)?;
+        }
+        write!(f, "]")
+    }
+}
+
+impl fmt::Display for ObjList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_items(f, fmt_element)
+    }
+}
+
+/// Write a value contained in a list or map. Strings are quoted so that
+/// they can be told apart from other values.
+fn fmt_element(value: &Value, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match value {
+        Value::String(s) => write!(f, "{:?}", s),
+        _ => write!(f, "{}", value),
+    }
+}
+
+impl fmt::Debug for ObjList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_items(f, |item, f| write!(f, "{:?}", item))
+    }
+}
+
+impl Drop for ObjList {
+    fn drop(&mut self) {
+        if cfg!(feature = "trace_drops") {
+            println!("**Dropped [{:?}]**", self)
+        }
+        drop_values(self.items.take());
+    }
+}
+
+impl From<Vec<Value>> for ObjList {
+    fn from(items: Vec<Value>) -> Self {
+        ObjList {
+            items: RefCell::new(items),
+        }
+    }
+}
+
+/// A Value used as the key of a map.
+///
+/// Keys are compared the same way as Values: numbers, booleans and nil by
+/// value, and strings and other objects by identity.
+#[derive(Clone)]
+pub struct MapKey(Value);
+
+impl MapKey {
+    /// Wraps `value` as a key, failing if it cannot be equal to itself
+    pub fn new(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Number(n) if n.is_nan() => Err("Cannot use NaN as a map key".to_string()),
+            _ => Ok(MapKey(value)),
+        }
+    }
+
+    /// The wrapped value
+    pub fn value(&self) -> &Value {
+        &self.0
+    }
+}
+
+impl PartialEq for MapKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for MapKey {}
+
+impl fmt::Display for MapKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_element(&self.0, f)
+    }
+}
+
+impl Hash for MapKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(&self.0).hash(state);
+        match &self.0 {
+            // 0 and -0 are equal, so they must hash the same
+            Value::Number(n) if *n == 0.0 => 0u64.hash(state),
+            Value::Number(n) => n.to_bits().hash(state),
+            Value::Bool(b) => b.hash(state),
+            Value::Nil => {}
+            Value::String(o) => Rc::as_ptr(o).hash(state),
+            Value::Function(o) => Rc::as_ptr(o).hash(state),
+            Value::Closure(o) => Rc::as_ptr(o).hash(state),
+            Value::Class(o) => Rc::as_ptr(o).hash(state),
+            Value::Instance(o) => Rc::as_ptr(o).hash(state),
+            Value::BoundMethod(o) => Rc::as_ptr(o).hash(state),
+            Value::Native(o) => Rc::as_ptr(o).hash(state),
+            Value::NativeMethod(o) => Rc::as_ptr(o).hash(state),
+            Value::List(o) => Rc::as_ptr(o).hash(state),
+            Value::Map(o) => Rc::as_ptr(o).hash(state),
+            Value::Module(o) => Rc::as_ptr(o).hash(state),
+        }
+    }
+}
+
+/// The entries of a map, kept in the order their keys were first inserted
+#[derive(Default)]
+pub struct MapEntries {
+    /// The position of each key in `entries`
+    indices: HashMap<MapKey, usize>,
+    entries: Vec<(MapKey, Value)>,
+}
+
+impl MapEntries {
+    /// The value associated with `key`, if there is one
+    pub fn get(&self, key: &MapKey) -> Option<&Value> {
+        self.indices.get(key).map(|&index| &self.entries[index].1)
+    }
+
+    /// Associate `value` with `key`, replacing any existing value
+    pub fn insert(&mut self, key: MapKey, value: Value) {
+        if let Some(&index) = self.indices.get(&key) {
+            self.entries[index].1 = value;
+        } else {
+            self.indices.insert(key.clone(), self.entries.len());
+            self.entries.push((key, value));
+        }
+    }
+
+    /// Remove the entry for `key`, returning its value if there was one
+    pub fn remove(&mut self, key: &MapKey) -> Option<Value> {
+        let index = self.indices.remove(key)?;
+        let (_, value) = self.entries.remove(index);
+        for (key, _) in self.entries[index..].iter() {
+            *self.indices.get_mut(key).unwrap() -= 1;
+        }
+        Some(value)
+    }
+
+    /// Remove every entry
+    pub fn clear(&mut self) {
+        self.indices.clear();
+        self.entries.clear();
+    }
+
+    /// The number of entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Indicates whether there are no entries
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The keys and values, in insertion order
+    pub fn iter(&self) -> impl Iterator<Item = (&Value, &Value)> {
+        self.entries.iter().map(|(key, value)| (key.value(), value))
+    }
+
+    /// Consume the entries, returning every key and value
+    fn into_values(self) -> Vec<Value> {
+        self.entries
+            .into_iter()
+            .flat_map(|(key, value)| vec![key.0, value])
+            .collect()
+    }
+}
+
+pub struct ObjMap {
+    pub entries: RefCell<MapEntries>,
+}
+
+impl ObjMap {
+    /// Write each entry, separated by commas, using `write_item`. A map that
+    /// contains itself is written as `{...}` where it is nested.
+    fn fmt_entries(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        write_item: fn(&Value, &mut fmt::Formatter<'_>) -> fmt::Result,
+    ) -> fmt::Result {
+        // As with lists, a nested reference to this map will fail to borrow it
+        let entries = match self.entries.try_borrow_mut() {
+            Ok(entries) => entries,
+            Err(_) => return write!(f, "{{...}}"),
+        };
+
+        write!(f, "{{")?;
+        for (i, (key, value)) in entries.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write_item(key, f)?;
+            write!(f, ": ")?;
+            write_item(value, f)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl fmt::Display for ObjMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_entries(f, fmt_element)
+    }
+}
+
+impl fmt::Debug for ObjMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_entries(f, |item, f| write!(f, "{:?}", item))
+    }
+}
+
+impl Drop for ObjMap {
+    fn drop(&mut self) {
+        if cfg!(feature = "trace_drops") {
+            println!("**Dropped [{:?}]**", self)
+        }
+        drop_values(self.entries.take().into_values());
+    }
+}
+
+impl From<MapEntries> for ObjMap {
+    fn from(entries: MapEntries) -> Self {
+        ObjMap {
+            entries: RefCell::new(entries),
+        }
+    }
+}
+
+/// The namespace produced by importing a module. Each member is one of the
+/// module's top-level definitions, stored in the global slot given here.
+pub struct ObjModule {
+    pub name: Box<ObjString>,
+    pub members: HashMap<Symbol, usize>,
+}
+
+impl fmt::Display for ObjModule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<module {}>", self.name)
+    }
+}
+
+impl fmt::Debug for ObjModule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<Module {}>", self.name)
+    }
+}
+
+#[cfg(feature = "trace_drops")]
+impl Drop for ObjModule {
+    fn drop(&mut self) {
+        println!("**Dropped [{:?}]**", self)
+    }
+}
+
+/// Drop `values`, releasing the instances, lists and maps that only they refer
+/// to one at a time instead of recursively, so that dropping a long chain of
+/// objects cannot overflow the stack
+fn drop_values(mut values: Vec<Value>) {
+    while let Some(value) = values.pop() {
+        match value {
+            Value::Instance(instance) => {
+                if let Ok(instance) = Rc::try_unwrap(instance) {
+                    values.extend(instance.fields.take().into_values());
+                }
+            }
+            Value::List(list) => {
+                if let Ok(list) = Rc::try_unwrap(list) {
+                    values.extend(list.items.take());
+                }
+            }
+            Value::Map(map) => {
+                if let Ok(map) = Rc::try_unwrap(map) {
+                    values.extend(map.entries.take().into_values());
+                }
+            }
+            _ => {}
+        }
+    }
+}
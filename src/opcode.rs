@@ -1,10 +1,24 @@
+/// Instructions are encoded as a single opcode byte followed by the
+/// instruction's argument, if any. Arguments are little endian and are
+/// as wide as `OpCode::argument_width` says.
+///
+/// * constant indices are 1 byte for `Constant`, 3 bytes for
+///   `ConstantLong`, and 2 bytes for all other instructions
+/// * jump and handler destinations are 3 bytes
+/// * global slots and list and map lengths are 2 bytes
+/// * local and upvalue indices and argument counts are 1 byte
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum OpCode {
     /// Loads the constant at `constants[arg1]` and leaves it at
     /// the top of the stack.
     Constant(usize),
 
-    /// Sets the value at `stack[base]` to the value at `stack[top]`
+    /// Identical to `Constant`, but with a 3-byte argument for
+    /// executables with more than 256 constants.
+    ConstantLong(usize),
+
+    /// Sets the value at `stack[base]` to the value at `stack[top]`,
+    /// closes any upvalues referring to the current frame's locals,
     /// then ends execution of the current closure
     Return,
 
@@ -70,17 +84,17 @@ pub enum OpCode {
     /// Pops a single value from the stack and discards it
     Pop,
 
-    /// Declares a new global variable with name `constants[arg1]`
-    /// and value Nil
+    /// Declares the global variable in slot `arg1` with value Nil.
+    /// See `symbol::GlobalSlots`.
     DeclareGlobal(usize),
 
-    /// Loads the value of the global with name `constants[arg1]`
-    /// and leaves it on the stack
+    /// Loads the value of the global in slot `arg1` and leaves
+    /// it on the stack
     GetGlobal(usize),
 
-    /// Sets the value of the global with name `constants[arg1]`
-    /// to the value at the top of the stack. Does not consume
-    /// the value from the stack.
+    /// Sets the value of the global in slot `arg1` to the value
+    /// at the top of the stack. Does not consume the value from
+    /// the stack.
     SetGlobal(usize),
 
     /// Loads the value of the local at index `arg1` and leaves
@@ -94,7 +108,9 @@ pub enum OpCode {
     /// Looks up the method named `constants[arg1]` in the class
     /// at `stack[top]`, binds that method to the receiver at
     /// `stack[top -1]`, consumes the top 2 values on the stack,
-    /// and leaves the bound method in their place.
+    /// and leaves the bound method in their place. If the class
+    /// has a getter by that name instead, the getter is called
+    /// with the receiver and leaves its return value.
     GetSuper(usize),
 
     /// Sets the `IP` to the `arg1`
@@ -123,8 +139,8 @@ pub enum OpCode {
     Invoke(usize),
 
     /// Looks up the function `constants[arg1]`, then creates a closure
-    /// from that function and the current values of all of its captured
-    /// variables. The closure is left at the top of the stack.
+    /// from that function which captures (shares) each of its upvalues.
+    /// The closure is left at the top of the stack.
     Closure(usize),
 
     /// Loads and pushes the upvalue at index `arg1` in the currently
@@ -136,6 +152,10 @@ pub enum OpCode {
     /// not consume the value at the top of the stack.
     SetUpvalue(usize),
 
+    /// Closes any open upvalues that refer to the local at the top
+    /// of the stack, then pops it from the stack.
+    CloseUpvalue,
+
     /// Loads the value of the field with the name `constants[arg1]`
     /// from the instance at the top of the stack. The instance is
     /// consumed and the field value is left in its place.
@@ -158,4 +178,313 @@ pub enum OpCode {
     /// Consume the value at the top of the stack and leave in its
     /// place a Value::Bool representing its truthiness
     Bool,
+
+    /// Consume the `arg1` values at the top of the stack and leave
+    /// a new list containing them, in order, in their place
+    BuildList(usize),
+
+    /// Consume the list or map at `stack[top - 1]` and the index
+    /// at `stack[top]`, and leave the item at that index in their
+    /// place. A list index must be an integer within the bounds of
+    /// the list, and a map index must be a key of the map.
+    GetIndex,
+
+    /// Sets the item of the list or map at `stack[top - 2]` at the
+    /// index `stack[top - 1]` to the value at the top of the stack.
+    /// The list or map and index are removed from the stack, but the
+    /// value remains.
+    SetIndex,
+
+    /// Consume the `2 * arg1` values at the top of the stack and leave
+    /// a new map in their place. The values alternate between a key
+    /// and the value associated with it.
+    BuildMap(usize),
+
+    /// Installs an exception handler at `arg1`. If an error is thrown
+    /// before the handler is removed, the frames and stack are unwound
+    /// to where they were when the handler was installed, the thrown
+    /// value is pushed, and the `IP` is set to `arg1`.
+    PushHandler(usize),
+
+    /// Removes the most recently installed exception handler
+    PopHandler,
+
+    /// Consume the value at the top of the stack and throw it
+    Throw,
+
+    /// Consume the value at the top of the stack and throw it again,
+    /// keeping the message and span it was last thrown with
+    Rethrow,
+
+    /// Loads the module at the path `constants[arg1]` and leaves its
+    /// namespace at the top of the stack. A module that has not been
+    /// imported before is compiled and executed first.
+    Import(usize),
+
+    /// Replaces the value at the top of the stack with the string
+    /// that `print` would output for it
+    Stringify,
+
+    /// Consume the two values at the top of the stack and leave
+    /// `stack[top - 1]` % `stack[top]` in their place. Requires
+    /// that both values are numbers.
+    Modulo,
+
+    /// Consume the two values at the top of the stack and leave
+    /// `stack[top - 1]` raised to the power `stack[top]` in their
+    /// place. Requires that both values are numbers.
+    Power,
+
+    /// Consume the two values at the top of the stack and leave
+    /// `stack[top - 1]` & `stack[top]` in their place. Requires
+    /// that both values are integers.
+    BitAnd,
+
+    /// Consume the two values at the top of the stack and leave
+    /// `stack[top - 1]` | `stack[top]` in their place. Requires
+    /// that both values are integers.
+    BitOr,
+
+    /// Consume the two values at the top of the stack and leave
+    /// `stack[top - 1]` ^ `stack[top]` in their place. Requires
+    /// that both values are integers.
+    BitXor,
+
+    /// Consume the value at the top of the stack and leave its
+    /// bitwise complement in its place. Requires that the value
+    /// is an integer.
+    BitNot,
+
+    /// Consume the two values at the top of the stack and leave
+    /// `stack[top - 1]` << `stack[top]` in their place. Requires
+    /// that both values are integers.
+    ShiftLeft,
+
+    /// Consume the two values at the top of the stack and leave
+    /// `stack[top - 1]` >> `stack[top]` in their place. Requires
+    /// that both values are integers.
+    ShiftRight,
+
+    /// Push copies of the top `arg1` values of the stack, keeping their order
+    Duplicate(usize),
+
+    /// Exchange the two values at the top of the stack
+    Swap,
+
+    /// Consume the closure at `stack[top]` and add it to the class
+    /// at `stack[top - 1]` as a class method
+    ClassMethod,
+
+    /// Consume the closure at `stack[top]` and add it to the class
+    /// at `stack[top - 1]` as a getter
+    Getter,
+}
+
+/// The width of the argument of each instruction, indexed by tag
+const ARGUMENT_WIDTHS: [u8; 58] = [
+    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // Constant .. Pop
+    2, 2, 2, 1, 1, 2, 3, 3, 3, 1, 2, 1, 1, 0, 2, 2, // DeclareGlobal .. SetField
+    0, 0, 0, 3, 2, 0, 0, 2, 3, 0, 0, 0, 2, 0, 0, 0, // Method .. Power
+    0, 0, 0, 0, 0, 0, 1, 0, 0, 0, // BitAnd .. Getter
+];
+
+impl OpCode {
+    /// The byte identifying this instruction in an encoded executable
+    pub fn tag(&self) -> u8 {
+        match self {
+            OpCode::Constant(_) => 0,
+            OpCode::Return => 1,
+            OpCode::Add => 2,
+            OpCode::Subtract => 3,
+            OpCode::Multiply => 4,
+            OpCode::Divide => 5,
+            OpCode::Negate => 6,
+            OpCode::Less => 7,
+            OpCode::Greater => 8,
+            OpCode::LessEqual => 9,
+            OpCode::GreaterEqual => 10,
+            OpCode::Not => 11,
+            OpCode::Equal => 12,
+            OpCode::NotEqual => 13,
+            OpCode::Print => 14,
+            OpCode::Pop => 15,
+            OpCode::DeclareGlobal(_) => 16,
+            OpCode::GetGlobal(_) => 17,
+            OpCode::SetGlobal(_) => 18,
+            OpCode::GetLocal(_) => 19,
+            OpCode::SetLocal(_) => 20,
+            OpCode::GetSuper(_) => 21,
+            OpCode::Jump(_) => 22,
+            OpCode::JumpIfTrue(_) => 23,
+            OpCode::JumpIfFalse(_) => 24,
+            OpCode::Invoke(_) => 25,
+            OpCode::Closure(_) => 26,
+            OpCode::GetUpvalue(_) => 27,
+            OpCode::SetUpvalue(_) => 28,
+            OpCode::CloseUpvalue => 29,
+            OpCode::ReadField(_) => 30,
+            OpCode::SetField(_) => 31,
+            OpCode::Method => 32,
+            OpCode::Inherit => 33,
+            OpCode::Bool => 34,
+            OpCode::ConstantLong(_) => 35,
+            OpCode::BuildList(_) => 36,
+            OpCode::GetIndex => 37,
+            OpCode::SetIndex => 38,
+            OpCode::BuildMap(_) => 39,
+            OpCode::PushHandler(_) => 40,
+            OpCode::PopHandler => 41,
+            OpCode::Throw => 42,
+            OpCode::Rethrow => 43,
+            OpCode::Import(_) => 44,
+            OpCode::Stringify => 45,
+            OpCode::Modulo => 46,
+            OpCode::Power => 47,
+            OpCode::BitAnd => 48,
+            OpCode::BitOr => 49,
+            OpCode::BitXor => 50,
+            OpCode::BitNot => 51,
+            OpCode::ShiftLeft => 52,
+            OpCode::ShiftRight => 53,
+            OpCode::Duplicate(_) => 54,
+            OpCode::Swap => 55,
+            OpCode::ClassMethod => 56,
+            OpCode::Getter => 57,
+        }
+    }
+
+    /// The argument of this instruction, if it has one
+    pub fn argument(&self) -> Option<usize> {
+        match *self {
+            OpCode::Constant(arg)
+            | OpCode::ConstantLong(arg)
+            | OpCode::DeclareGlobal(arg)
+            | OpCode::GetGlobal(arg)
+            | OpCode::SetGlobal(arg)
+            | OpCode::GetLocal(arg)
+            | OpCode::SetLocal(arg)
+            | OpCode::GetSuper(arg)
+            | OpCode::Jump(arg)
+            | OpCode::JumpIfTrue(arg)
+            | OpCode::JumpIfFalse(arg)
+            | OpCode::Invoke(arg)
+            | OpCode::Closure(arg)
+            | OpCode::GetUpvalue(arg)
+            | OpCode::SetUpvalue(arg)
+            | OpCode::ReadField(arg)
+            | OpCode::SetField(arg)
+            | OpCode::BuildList(arg)
+            | OpCode::BuildMap(arg)
+            | OpCode::PushHandler(arg)
+            | OpCode::Import(arg)
+            | OpCode::Duplicate(arg) => Some(arg),
+            _ => None,
+        }
+    }
+
+    /// The number of bytes used to encode the argument of the instruction with `tag`
+    #[inline]
+    pub fn argument_width(tag: u8) -> usize {
+        ARGUMENT_WIDTHS.get(tag as usize).copied().unwrap_or(0) as usize
+    }
+
+    /// The largest argument that can be encoded for this instruction
+    pub fn max_argument(&self) -> usize {
+        (1 << (8 * OpCode::argument_width(self.tag()))) - 1
+    }
+
+    /// Append the encoded instruction to `code`.
+    ///
+    /// Panics if the argument is too large to be encoded.
+    pub fn encode(&self, code: &mut Vec<u8>) {
+        code.push(self.tag());
+        if let Some(arg) = self.argument() {
+            assert!(
+                arg <= self.max_argument(),
+                "Argument of {:?} is too large to encode",
+                self
+            );
+            let width = OpCode::argument_width(self.tag());
+            code.extend_from_slice(&arg.to_le_bytes()[..width]);
+        }
+    }
+
+    /// Decode the instruction that starts at `code[offset]`, returning it
+    /// along with the offset of the following instruction.
+    ///
+    /// Returns None if the tag is unknown or the instruction is truncated.
+    #[inline]
+    pub fn decode(code: &[u8], offset: usize) -> Option<(OpCode, usize)> {
+        let tag = *code.get(offset)?;
+        if offset + OpCode::argument_width(tag) >= code.len() {
+            return None;
+        }
+
+        let byte = |i: usize| code[offset + i] as usize;
+        let short = || byte(1) | byte(2) << 8;
+        let long = || byte(1) | byte(2) << 8 | byte(3) << 16;
+
+        let (op, width) = match tag {
+            0 => (OpCode::Constant(byte(1)), 1),
+            1 => (OpCode::Return, 0),
+            2 => (OpCode::Add, 0),
+            3 => (OpCode::Subtract, 0),
+            4 => (OpCode::Multiply, 0),
+            5 => (OpCode::Divide, 0),
+            6 => (OpCode::Negate, 0),
+            7 => (OpCode::Less, 0),
+            8 => (OpCode::Greater, 0),
+            9 => (OpCode::LessEqual, 0),
+            10 => (OpCode::GreaterEqual, 0),
+            11 => (OpCode::Not, 0),
+            12 => (OpCode::Equal, 0),
+            13 => (OpCode::NotEqual, 0),
+            14 => (OpCode::Print, 0),
+            15 => (OpCode::Pop, 0),
+            16 => (OpCode::DeclareGlobal(short()), 2),
+            17 => (OpCode::GetGlobal(short()), 2),
+            18 => (OpCode::SetGlobal(short()), 2),
+            19 => (OpCode::GetLocal(byte(1)), 1),
+            20 => (OpCode::SetLocal(byte(1)), 1),
+            21 => (OpCode::GetSuper(short()), 2),
+            22 => (OpCode::Jump(long()), 3),
+            23 => (OpCode::JumpIfTrue(long()), 3),
+            24 => (OpCode::JumpIfFalse(long()), 3),
+            25 => (OpCode::Invoke(byte(1)), 1),
+            26 => (OpCode::Closure(short()), 2),
+            27 => (OpCode::GetUpvalue(byte(1)), 1),
+            28 => (OpCode::SetUpvalue(byte(1)), 1),
+            29 => (OpCode::CloseUpvalue, 0),
+            30 => (OpCode::ReadField(short()), 2),
+            31 => (OpCode::SetField(short()), 2),
+            32 => (OpCode::Method, 0),
+            33 => (OpCode::Inherit, 0),
+            34 => (OpCode::Bool, 0),
+            35 => (OpCode::ConstantLong(long()), 3),
+            36 => (OpCode::BuildList(short()), 2),
+            37 => (OpCode::GetIndex, 0),
+            38 => (OpCode::SetIndex, 0),
+            39 => (OpCode::BuildMap(short()), 2),
+            40 => (OpCode::PushHandler(long()), 3),
+            41 => (OpCode::PopHandler, 0),
+            42 => (OpCode::Throw, 0),
+            43 => (OpCode::Rethrow, 0),
+            44 => (OpCode::Import(short()), 2),
+            45 => (OpCode::Stringify, 0),
+            46 => (OpCode::Modulo, 0),
+            47 => (OpCode::Power, 0),
+            48 => (OpCode::BitAnd, 0),
+            49 => (OpCode::BitOr, 0),
+            50 => (OpCode::BitXor, 0),
+            51 => (OpCode::BitNot, 0),
+            52 => (OpCode::ShiftLeft, 0),
+            53 => (OpCode::ShiftRight, 0),
+            54 => (OpCode::Duplicate(byte(1)), 1),
+            55 => (OpCode::Swap, 0),
+            56 => (OpCode::ClassMethod, 0),
+            57 => (OpCode::Getter, 0),
+            _ => return None,
+        };
+        Some((op, offset + 1 + width))
+    }
 }
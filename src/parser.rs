@@ -3,17 +3,22 @@ use crate::error::ParsingError;
 use crate::scanner::Scanner;
 use crate::token::{Kind, Span, Token};
 use crate::value::Value;
-use std::iter::Peekable;
+use std::collections::VecDeque;
 
 #[derive(Debug)]
 pub struct Parser<'a> {
-    scanner: Peekable<Scanner<'a>>,
+    scanner: Scanner<'a>,
+
+    /// Tokens that have been scanned ahead of the parser but not yet consumed
+    lookahead: VecDeque<Token>,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(source: &'a str) -> Self {
-        let scanner = Scanner::new(&source).peekable();
-        Parser { scanner }
+        Parser {
+            scanner: Scanner::new(source),
+            lookahead: VecDeque::new(),
+        }
     }
 
     /// Parse the source into a program - a list of declaration `AstNode`s
@@ -34,13 +39,19 @@ impl<'a> Parser<'a> {
         if errors.is_empty() {
             Ok(program)
         } else {
-            Err(errors.drain(0..).collect())
+            Err(std::mem::take(&mut errors))
         }
     }
 
     fn declaration(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        // `fun (` begins an anonymous function, which is an expression
+        if self.check(Kind::Fun) && self.peek_kind(1) == Some(&Kind::LeftParen) {
+            return self.statement();
+        }
+
         match self.next().kind {
             Kind::Var => self.var_declaration(),
+            Kind::Import => self.import_declaration(),
             Kind::Class => self.class_declaration(),
             Kind::Fun => {
                 self.advance();
@@ -70,6 +81,25 @@ impl<'a> Parser<'a> {
         ))
     }
 
+    fn import_declaration(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        let keyword = self.advance();
+        let token = self.advance();
+        let path = if let Kind::StringLiteral(path) = token.kind {
+            path
+        } else {
+            return Err(ParsingError::UnexpectedToken {
+                expected: "module path".to_string(),
+                actual: token,
+            });
+        };
+        self.eat(Kind::As)?;
+        let (name, _) = self.id_token()?;
+
+        let semi = self.eat(Kind::Semicolon)?;
+        let span = Span::merge(vec![&keyword.span, &semi.span]);
+        Ok(SpannedAstNode::new(AstNode::Import { path, name }, span))
+    }
+
     fn class_declaration(&mut self) -> Result<SpannedAstNode, ParsingError> {
         let keyword = self.eat(Kind::Class)?;
         let (name, _) = self.id_token()?;
@@ -87,11 +117,21 @@ impl<'a> Parser<'a> {
             None
         };
 
-        // Parse the class body (methods)
+        // Parse the class body: methods, class methods prefixed by 'class',
+        // and getters, which have no parameter list
         self.eat(Kind::LeftBrace)?;
         let mut methods = vec![];
+        let mut class_methods = vec![];
+        let mut getters = vec![];
         while self.next().kind != Kind::RightBrace {
-            methods.push(self.function_declaration()?);
+            if self.check(Kind::Class) {
+                self.advance();
+                class_methods.push(self.function_declaration()?);
+            } else if self.peek_kind(1) == Some(&Kind::LeftBrace) {
+                getters.push(self.getter_declaration()?);
+            } else {
+                methods.push(self.function_declaration()?);
+            }
         }
         let end_brace = self.eat(Kind::RightBrace)?;
 
@@ -100,28 +140,33 @@ impl<'a> Parser<'a> {
             AstNode::ClassDeclaration {
                 name,
                 methods,
+                class_methods,
+                getters,
                 superclass,
             },
             span,
         ))
     }
 
-    fn function_declaration(&mut self) -> Result<SpannedAstNode, ParsingError> {
+    fn getter_declaration(&mut self) -> Result<SpannedAstNode, ParsingError> {
         let (name, name_span) = self.id_token()?;
-        self.eat(Kind::LeftParen)?;
+        let body = self.block_statement()?;
+        let span = Span::merge(vec![&name_span, &body.span]);
 
-        let parameters = match self.next().kind {
-            Kind::RightParen => vec![],
-            Kind::IdentifierLiteral(_) => self.parameter_list()?,
-            _ => {
-                return Err(ParsingError::UnexpectedToken {
-                    expected: "parameter list or ')'.".to_string(),
-                    actual: self.advance(),
-                })
-            }
-        };
+        Ok(SpannedAstNode::new(
+            AstNode::FunDeclaration {
+                name,
+                parameters: vec![],
+                body: Box::new(body),
+            },
+            span,
+        ))
+    }
 
-        self.eat(Kind::RightParen)?;
+    fn function_declaration(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        let (name, name_span) = self.id_token()?;
+        self.eat(Kind::LeftParen)?;
+        let parameters = self.parameters()?;
         let body = self.block_statement()?;
         let span = Span::merge(vec![&name_span, &body.span]);
 
@@ -136,13 +181,21 @@ impl<'a> Parser<'a> {
     }
 
     fn statement(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        if self.at_label() {
+            return self.labeled_statement();
+        }
+
         match self.next().kind {
             Kind::Print => self.print_statement(),
             Kind::LeftBrace => self.block_statement(),
             Kind::If => self.if_statement(),
-            Kind::While => self.while_statement(),
-            Kind::For => self.for_statement(),
+            Kind::Switch => self.switch_statement(),
+            Kind::While => self.while_statement(None),
+            Kind::For => self.for_statement(None),
             Kind::Return => self.return_statement(),
+            Kind::Break | Kind::Continue => self.jump_statement(),
+            Kind::Throw => self.throw_statement(),
+            Kind::Try => self.try_statement(),
             _ => self.expression_statement(),
         }
     }
@@ -159,6 +212,48 @@ impl<'a> Parser<'a> {
         ))
     }
 
+    /// Whether the next tokens are a loop label: an identifier followed by ':'
+    fn at_label(&mut self) -> bool {
+        matches!(self.peek_kind(0), Some(Kind::IdentifierLiteral(_)))
+            && self.peek_kind(1) == Some(&Kind::Colon)
+    }
+
+    fn labeled_statement(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        let (label, label_span) = self.id_token()?;
+        self.eat(Kind::Colon)?;
+
+        let statement = match self.next().kind {
+            Kind::While => self.while_statement(Some(label))?,
+            Kind::For => self.for_statement(Some(label))?,
+            _ => {
+                return Err(ParsingError::UnexpectedToken {
+                    expected: "loop after label".to_string(),
+                    actual: self.advance(),
+                })
+            }
+        };
+        let span = Span::merge(vec![&label_span, &statement.span]);
+        Ok(SpannedAstNode::respan(statement, span))
+    }
+
+    /// Parse a `break` or `continue` statement, with an optional label
+    fn jump_statement(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        let keyword = self.advance();
+        let label = match self.next().kind {
+            Kind::IdentifierLiteral(_) => Some(self.id_token()?.0),
+            _ => None,
+        };
+        let semi = self.eat(Kind::Semicolon)?;
+        let span = Span::merge(vec![&keyword.span, &semi.span]);
+
+        let node = if keyword.kind == Kind::Break {
+            AstNode::Break { label }
+        } else {
+            AstNode::Continue { label }
+        };
+        Ok(SpannedAstNode::new(node, span))
+    }
+
     fn return_statement(&mut self) -> Result<SpannedAstNode, ParsingError> {
         let keyword = self.advance();
 
@@ -175,7 +270,59 @@ impl<'a> Parser<'a> {
         Ok(SpannedAstNode::new(AstNode::Return { value }, span))
     }
 
-    fn for_statement(&mut self) -> Result<SpannedAstNode, ParsingError> {
+    fn throw_statement(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        let keyword = self.advance();
+        let value = self.expression()?;
+        let semi = self.eat(Kind::Semicolon)?;
+        let span = Span::merge(vec![&keyword.span, &semi.span]);
+        Ok(SpannedAstNode::new(
+            AstNode::Throw {
+                value: Box::new(value),
+            },
+            span,
+        ))
+    }
+
+    fn try_statement(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        let keyword = self.advance();
+        let body = self.block_statement()?;
+        let mut span = Span::merge(vec![&keyword.span, &body.span]);
+
+        let catch = if self.check(Kind::Catch) {
+            self.advance();
+            self.eat(Kind::LeftParen)?;
+            let (name, _) = self.id_token()?;
+            self.eat(Kind::RightParen)?;
+            let block = self.block_statement()?;
+            span = Span::merge(vec![&span, &block.span]);
+            Some((name, Box::new(block)))
+        } else {
+            None
+        };
+
+        let finally = if catch.is_none() || self.check(Kind::Finally) {
+            if !self.has_next() {
+                return Err(ParsingError::UnexpectedEof { index: span.end });
+            }
+            self.eat(Kind::Finally)?;
+            let block = self.block_statement()?;
+            span = Span::merge(vec![&span, &block.span]);
+            Some(Box::new(block))
+        } else {
+            None
+        };
+
+        Ok(SpannedAstNode::new(
+            AstNode::Try {
+                body: Box::new(body),
+                catch,
+                finally,
+            },
+            span,
+        ))
+    }
+
+    fn for_statement(&mut self, label: Option<String>) -> Result<SpannedAstNode, ParsingError> {
         let keyword = self.advance();
         self.eat(Kind::LeftParen)?;
 
@@ -207,6 +354,7 @@ impl<'a> Parser<'a> {
 
         Ok(SpannedAstNode::new(
             AstNode::For {
+                label,
                 initializer,
                 condition,
                 update,
@@ -216,7 +364,7 @@ impl<'a> Parser<'a> {
         ))
     }
 
-    fn while_statement(&mut self) -> Result<SpannedAstNode, ParsingError> {
+    fn while_statement(&mut self, label: Option<String>) -> Result<SpannedAstNode, ParsingError> {
         let keyword = self.advance();
         self.eat(Kind::LeftParen)?;
 
@@ -228,6 +376,7 @@ impl<'a> Parser<'a> {
 
         Ok(SpannedAstNode::new(
             AstNode::While {
+                label,
                 condition: Box::new(condition),
                 block: Box::new(block),
             },
@@ -264,8 +413,71 @@ impl<'a> Parser<'a> {
         ))
     }
 
+    /// Parse a switch statement. Each case has one or more values and its own
+    /// block of declarations, and the optional default case must come last.
+    fn switch_statement(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        let keyword = self.advance();
+        self.eat(Kind::LeftParen)?;
+        let scrutinee = self.expression()?;
+        self.eat(Kind::RightParen)?;
+        self.eat(Kind::LeftBrace)?;
+
+        let mut cases = vec![];
+        let mut default = None;
+        loop {
+            if !self.has_next() {
+                return Err(ParsingError::UnexpectedEof {
+                    index: scrutinee.span.end,
+                });
+            }
+            match self.next().kind {
+                Kind::Case => {
+                    let case = self.advance();
+                    let values = self.argument_list()?;
+                    self.eat(Kind::Colon)?;
+                    cases.push((values, self.case_body(case.span)?));
+                }
+                Kind::Default => {
+                    let case = self.advance();
+                    self.eat(Kind::Colon)?;
+                    default = Some(Box::new(self.case_body(case.span)?));
+                    break;
+                }
+                _ => break,
+            }
+        }
+
+        let rbrace = self.eat(Kind::RightBrace)?;
+        let new_span = Span::merge(vec![&keyword.span, &rbrace.span]);
+        Ok(SpannedAstNode::new(
+            AstNode::Switch {
+                scrutinee: Box::new(scrutinee),
+                cases,
+                default,
+            },
+            new_span,
+        ))
+    }
+
+    /// Parse the declarations of a switch case up to the next case or the end of the switch
+    fn case_body(&mut self, start: Span) -> Result<SpannedAstNode, ParsingError> {
+        let mut declarations = vec![];
+        let mut span = start;
+        while self.has_next()
+            && !self.check(Kind::Case)
+            && !self.check(Kind::Default)
+            && !self.check(Kind::RightBrace)
+        {
+            let declaration = self.declaration()?;
+            span = Span::merge(vec![&span, &declaration.span]);
+            declarations.push(declaration);
+        }
+
+        Ok(SpannedAstNode::new(AstNode::Block { declarations }, span))
+    }
+
     fn block_statement(&mut self) -> Result<SpannedAstNode, ParsingError> {
-        let lbrace = self.advance();
+        let lbrace = self.eat(Kind::LeftBrace)?;
 
         let mut declarations = vec![];
         loop {
@@ -301,7 +513,7 @@ impl<'a> Parser<'a> {
     }
 
     fn assignment(&mut self) -> Result<SpannedAstNode, ParsingError> {
-        let node = self.or()?;
+        let node = self.conditional()?;
 
         if self.next().kind == Kind::Equal {
             self.advance();
@@ -315,11 +527,70 @@ impl<'a> Parser<'a> {
                 },
                 new_span,
             ))
+        } else if let Some(kind) = compound_operator(&self.next().kind) {
+            let token = self.advance();
+            let operator = Token {
+                kind,
+                span: token.span,
+            };
+            let rvalue = self.assignment()?;
+            let new_span = Span::merge(vec![&node.span, &rvalue.span]);
+
+            Ok(SpannedAstNode::new(
+                AstNode::CompoundAssignment {
+                    lvalue: Box::new(node),
+                    operator,
+                    rvalue: Box::new(rvalue),
+                },
+                new_span,
+            ))
         } else {
             Ok(node)
         }
     }
 
+    /// Parse a `condition ? then : else` expression, which is right-associative
+    fn conditional(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        let condition = self.nil_coalesce()?;
+        if !self.check(Kind::Question) {
+            return Ok(condition);
+        }
+
+        self.advance();
+        let then_branch = self.expression()?;
+        self.eat(Kind::Colon)?;
+        let else_branch = self.conditional()?;
+        let new_span = Span::merge(vec![&condition.span, &else_branch.span]);
+
+        Ok(SpannedAstNode::new(
+            AstNode::Conditional {
+                condition: Box::new(condition),
+                then_branch: Box::new(then_branch),
+                else_branch: Box::new(else_branch),
+            },
+            new_span,
+        ))
+    }
+
+    fn nil_coalesce(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        let mut node = self.or()?;
+        while self.check(Kind::QuestionQuestion) {
+            self.advance();
+            let right = self.or()?;
+            let new_span = Span::merge(vec![&node.span, &right.span]);
+
+            node = SpannedAstNode::new(
+                AstNode::NilCoalesce {
+                    left: Box::new(node),
+                    right: Box::new(right),
+                },
+                new_span,
+            );
+        }
+
+        Ok(node)
+    }
+
     fn or(&mut self) -> Result<SpannedAstNode, ParsingError> {
         let mut node = self.and()?;
         while self.next().kind == Kind::Or {
@@ -378,10 +649,93 @@ impl<'a> Parser<'a> {
     }
 
     fn comparison(&mut self) -> Result<SpannedAstNode, ParsingError> {
-        let mut node = self.addition()?;
+        let mut node = self.bitwise_or()?;
         while let Kind::Less | Kind::LessEqual | Kind::Greater | Kind::GreaterEqual =
             self.next().kind
         {
+            let operator = self.advance();
+            let right = self.bitwise_or()?;
+            let new_span = Span::merge(vec![&node.span, &operator.span, &right.span]);
+
+            node = SpannedAstNode::new(
+                AstNode::Binary {
+                    left: Box::new(node),
+                    operator,
+                    right: Box::new(right),
+                },
+                new_span,
+            );
+        }
+        Ok(node)
+    }
+
+    fn bitwise_or(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        let mut node = self.bitwise_xor()?;
+
+        while self.next().kind == Kind::Pipe {
+            let operator = self.advance();
+            let right = self.bitwise_xor()?;
+            let new_span = Span::merge(vec![&node.span, &operator.span, &right.span]);
+
+            node = SpannedAstNode::new(
+                AstNode::Binary {
+                    left: Box::new(node),
+                    operator,
+                    right: Box::new(right),
+                },
+                new_span,
+            );
+        }
+
+        Ok(node)
+    }
+
+    fn bitwise_xor(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        let mut node = self.bitwise_and()?;
+
+        while self.next().kind == Kind::Caret {
+            let operator = self.advance();
+            let right = self.bitwise_and()?;
+            let new_span = Span::merge(vec![&node.span, &operator.span, &right.span]);
+
+            node = SpannedAstNode::new(
+                AstNode::Binary {
+                    left: Box::new(node),
+                    operator,
+                    right: Box::new(right),
+                },
+                new_span,
+            );
+        }
+
+        Ok(node)
+    }
+
+    fn bitwise_and(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        let mut node = self.shift()?;
+
+        while self.next().kind == Kind::Ampersand {
+            let operator = self.advance();
+            let right = self.shift()?;
+            let new_span = Span::merge(vec![&node.span, &operator.span, &right.span]);
+
+            node = SpannedAstNode::new(
+                AstNode::Binary {
+                    left: Box::new(node),
+                    operator,
+                    right: Box::new(right),
+                },
+                new_span,
+            );
+        }
+
+        Ok(node)
+    }
+
+    fn shift(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        let mut node = self.addition()?;
+
+        while let Kind::LessLess | Kind::GreaterGreater = self.next().kind {
             let operator = self.advance();
             let right = self.addition()?;
             let new_span = Span::merge(vec![&node.span, &operator.span, &right.span]);
@@ -395,6 +749,7 @@ impl<'a> Parser<'a> {
                 new_span,
             );
         }
+
         Ok(node)
     }
 
@@ -422,7 +777,7 @@ impl<'a> Parser<'a> {
     fn multiplication(&mut self) -> Result<SpannedAstNode, ParsingError> {
         let mut node = self.unary()?;
 
-        while self.next().kind == Kind::Star || self.next().kind == Kind::Slash {
+        while let Kind::Star | Kind::Slash | Kind::Percent = self.next().kind {
             let operator = self.advance();
             let right = self.unary()?;
             let new_span = Span::merge(vec![&node.span, &operator.span, &right.span]);
@@ -442,7 +797,7 @@ impl<'a> Parser<'a> {
 
     fn unary(&mut self) -> Result<SpannedAstNode, ParsingError> {
         match self.next().kind {
-            Kind::Minus | Kind::Bang => {
+            Kind::Minus | Kind::Bang | Kind::Tilde => {
                 let operator = self.advance();
                 let expression = self.unary()?;
                 let new_span = Span::new(expression.span.start - 1, expression.span.end);
@@ -455,10 +810,78 @@ impl<'a> Parser<'a> {
                     new_span,
                 ))
             }
-            _ => self.call(),
+            // '++x' and '--x' are shorthand for 'x += 1' and 'x -= 1'
+            Kind::PlusPlus | Kind::MinusMinus => {
+                let token = self.advance();
+                let lvalue = self.unary()?;
+                let new_span = Span::merge(vec![&token.span, &lvalue.span]);
+                let operator = Token {
+                    kind: increment_operator(&token.kind),
+                    span: token.span,
+                };
+
+                Ok(SpannedAstNode::new(
+                    AstNode::CompoundAssignment {
+                        lvalue: Box::new(lvalue),
+                        operator,
+                        rvalue: Box::new(SpannedAstNode::new(
+                            AstNode::Constant {
+                                value: Value::Number(1f64),
+                            },
+                            token.span,
+                        )),
+                    },
+                    new_span,
+                ))
+            }
+            _ => self.exponent(),
         }
     }
 
+    /// Parse a '**' expression, which is right-associative and binds more tightly
+    /// than a unary operator on its left, so that `-2 ** 2` is `-(2 ** 2)`
+    fn exponent(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        let node = self.postfix()?;
+        if !self.check(Kind::StarStar) {
+            return Ok(node);
+        }
+
+        let operator = self.advance();
+        let right = self.unary()?;
+        let new_span = Span::merge(vec![&node.span, &operator.span, &right.span]);
+        Ok(SpannedAstNode::new(
+            AstNode::Binary {
+                left: Box::new(node),
+                operator,
+                right: Box::new(right),
+            },
+            new_span,
+        ))
+    }
+
+    /// Parse an expression that may be followed by '++' or '--', which update
+    /// the expression but evaluate to its value from before the update
+    fn postfix(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        let node = self.call()?;
+        if !self.check(Kind::PlusPlus) && !self.check(Kind::MinusMinus) {
+            return Ok(node);
+        }
+
+        let token = self.advance();
+        let new_span = Span::merge(vec![&node.span, &token.span]);
+        let operator = Token {
+            kind: increment_operator(&token.kind),
+            span: token.span,
+        };
+        Ok(SpannedAstNode::new(
+            AstNode::PostfixUpdate {
+                lvalue: Box::new(node),
+                operator,
+            },
+            new_span,
+        ))
+    }
+
     fn argument_list(&mut self) -> Result<Vec<SpannedAstNode>, ParsingError> {
         let mut args = vec![];
         args.push(self.expression()?);
@@ -470,8 +893,23 @@ impl<'a> Parser<'a> {
         Ok(args)
     }
 
+    fn entry_list(&mut self) -> Result<Vec<(SpannedAstNode, SpannedAstNode)>, ParsingError> {
+        let mut entries = vec![];
+        loop {
+            let key = self.expression()?;
+            self.eat(Kind::Colon)?;
+            entries.push((key, self.expression()?));
+
+            if self.next().kind != Kind::Comma {
+                return Ok(entries);
+            }
+            self.advance();
+        }
+    }
+
     fn call(&mut self) -> Result<SpannedAstNode, ParsingError> {
         let mut node = self.primary()?;
+        let mut is_optional_chain = false;
 
         loop {
             match self.next().kind {
@@ -495,6 +933,21 @@ impl<'a> Parser<'a> {
                         new_span,
                     )
                 }
+                Kind::LeftBracket => {
+                    self.advance();
+                    let index = self.expression()?;
+                    let rbracket = self.eat(Kind::RightBracket)?;
+
+                    let new_span = Span::merge(vec![&node.span, &rbracket.span]);
+
+                    node = SpannedAstNode::new(
+                        AstNode::Index {
+                            target: Box::new(node),
+                            index: Box::new(index),
+                        },
+                        new_span,
+                    )
+                }
                 Kind::Dot => {
                     self.advance();
                     let (field_name, field_span) = self.id_token()?;
@@ -506,15 +959,43 @@ impl<'a> Parser<'a> {
                         field_span,
                     )
                 }
+                Kind::QuestionDot => {
+                    self.advance();
+                    is_optional_chain = true;
+                    let (field_name, field_span) = self.id_token()?;
+                    node = SpannedAstNode::new(
+                        AstNode::OptionalFieldAccess {
+                            target: Box::new(node),
+                            name: field_name,
+                        },
+                        field_span,
+                    )
+                }
                 _ => break,
             }
         }
 
+        // A nil target of '?.' skips the rest of the chain of calls, indexes and fields
+        if is_optional_chain {
+            let span = node.span;
+            node = SpannedAstNode::new(
+                AstNode::OptionalChain {
+                    expression: Box::new(node),
+                },
+                span,
+            );
+        }
+
         Ok(node)
     }
 
     fn primary(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        if self.at_arrow_function() {
+            return self.arrow_function();
+        }
+
         match &self.next().kind {
+            Kind::Fun => self.lambda(),
             Kind::LeftParen => {
                 let lparen = self.advance();
                 let expression = self.expression()?;
@@ -522,6 +1003,30 @@ impl<'a> Parser<'a> {
                 let new_span = Span::merge(vec![&lparen.span, &rparen.span]);
                 Ok(SpannedAstNode::respan(expression, new_span))
             }
+            Kind::LeftBracket => {
+                let lbracket = self.advance();
+                let elements = match self.next().kind {
+                    Kind::RightBracket => vec![],
+                    _ => self.argument_list()?,
+                };
+                let rbracket = self.eat(Kind::RightBracket)?;
+                Ok(SpannedAstNode::new(
+                    AstNode::ListLiteral { elements },
+                    Span::merge(vec![&lbracket.span, &rbracket.span]),
+                ))
+            }
+            Kind::LeftBrace => {
+                let lbrace = self.advance();
+                let entries = match self.next().kind {
+                    Kind::RightBrace => vec![],
+                    _ => self.entry_list()?,
+                };
+                let rbrace = self.eat(Kind::RightBrace)?;
+                Ok(SpannedAstNode::new(
+                    AstNode::MapLiteral { entries },
+                    Span::merge(vec![&lbrace.span, &rbrace.span]),
+                ))
+            }
             Kind::IdentifierLiteral(name) => Ok(SpannedAstNode::new(
                 AstNode::Variable {
                     name: name.to_string(),
@@ -530,6 +1035,7 @@ impl<'a> Parser<'a> {
             )),
             Kind::NumberLiteral(_) => self.number_literal(),
             Kind::StringLiteral(_) => self.string_literal(),
+            Kind::Interpolation(_) => self.interpolation(),
             Kind::True => Ok(SpannedAstNode::new(
                 AstNode::Constant {
                     value: Value::Bool(true),
@@ -607,6 +1113,147 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Whether the next tokens begin an arrow function: a single parameter
+    /// or a parenthesized parameter list, followed by '=>'
+    fn at_arrow_function(&mut self) -> bool {
+        match self.peek_kind(0) {
+            Some(Kind::IdentifierLiteral(_)) => self.peek_kind(1) == Some(&Kind::Arrow),
+            Some(Kind::LeftParen) => {
+                // Skip the parameters to find the token after the ')'
+                let mut n = 1;
+                while matches!(
+                    self.peek_kind(n),
+                    Some(Kind::IdentifierLiteral(_)) | Some(Kind::Comma)
+                ) {
+                    n += 1;
+                }
+                self.peek_kind(n) == Some(&Kind::RightParen)
+                    && self.peek_kind(n + 1) == Some(&Kind::Arrow)
+            }
+            _ => false,
+        }
+    }
+
+    /// Parse an anonymous function: `fun (a, b) { ... }`
+    fn lambda(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        let keyword = self.advance();
+        self.eat(Kind::LeftParen)?;
+        let parameters = self.parameters()?;
+        let body = self.block_statement()?;
+        let span = Span::merge(vec![&keyword.span, &body.span]);
+
+        Ok(SpannedAstNode::new(
+            AstNode::Lambda {
+                parameters,
+                body: Box::new(body),
+            },
+            span,
+        ))
+    }
+
+    /// Parse an arrow function, which either returns the value of a single
+    /// expression, `(a, b) => a + b` or `a => a + 1`, or runs a block like
+    /// any other function, `a => { print a; }`
+    fn arrow_function(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        let start = self.next().span;
+        let parameters = if self.next().kind == Kind::LeftParen {
+            self.advance();
+            self.parameters()?
+        } else {
+            vec![self.advance()]
+        };
+        self.eat(Kind::Arrow)?;
+
+        let body = if self.check(Kind::LeftBrace) {
+            self.block_statement()?
+        } else {
+            let value = self.expression()?;
+            let value_span = value.span;
+            SpannedAstNode::new(
+                AstNode::Return {
+                    value: Some(Box::new(value)),
+                },
+                value_span,
+            )
+        };
+        let span = Span::merge(vec![&start, &body.span]);
+
+        Ok(SpannedAstNode::new(
+            AstNode::Lambda {
+                parameters,
+                body: Box::new(body),
+            },
+            span,
+        ))
+    }
+
+    /// Parse a string literal containing interpolated expressions. The parts
+    /// of the resulting node alternate between the text of the string and
+    /// the expressions embedded in it, starting and ending with text.
+    fn interpolation(&mut self) -> Result<SpannedAstNode, ParsingError> {
+        let start = self.next().span;
+        let mut parts = vec![];
+        loop {
+            if !self.has_next() {
+                let end = parts
+                    .last()
+                    .map_or(start.end, |part: &SpannedAstNode| part.span.end);
+                return Err(ParsingError::UnexpectedEof { index: end });
+            }
+            let token = self.advance();
+            match token.kind {
+                Kind::Interpolation(text) => {
+                    parts.push(SpannedAstNode::new(
+                        AstNode::Constant {
+                            value: Value::from(text),
+                        },
+                        token.span,
+                    ));
+                    parts.push(self.expression()?);
+                }
+                Kind::StringLiteral(text) => {
+                    parts.push(SpannedAstNode::new(
+                        AstNode::Constant {
+                            value: Value::from(text),
+                        },
+                        token.span,
+                    ));
+                    let span = Span::merge(vec![&start, &token.span]);
+                    return Ok(SpannedAstNode::new(AstNode::Interpolation { parts }, span));
+                }
+                // An error in the rest of the string, such as an invalid escape
+                Kind::Error { .. } => {
+                    return Err(ParsingError::UnexpectedToken {
+                        expected: "string literal".to_string(),
+                        actual: token,
+                    })
+                }
+                _ => {
+                    return Err(ParsingError::UnexpectedToken {
+                        expected: "'}'".to_string(),
+                        actual: token,
+                    })
+                }
+            }
+        }
+    }
+
+    /// Parse the parameters of a function following its '(', up to and including the ')'
+    fn parameters(&mut self) -> Result<Vec<Token>, ParsingError> {
+        let parameters = match self.next().kind {
+            Kind::RightParen => vec![],
+            Kind::IdentifierLiteral(_) => self.parameter_list()?,
+            _ => {
+                return Err(ParsingError::UnexpectedToken {
+                    expected: "parameter list or ')'.".to_string(),
+                    actual: self.advance(),
+                })
+            }
+        };
+        self.eat(Kind::RightParen)?;
+        Ok(parameters)
+    }
+
     /// Parse a parameter list and return a vector of the `Token`s that represent the paremeter names
     fn parameter_list(&mut self) -> Result<Vec<Token>, ParsingError> {
         let mut parameters = vec![];
@@ -641,17 +1288,34 @@ impl<'a> Parser<'a> {
     }
 
     fn has_next(&mut self) -> bool {
-        self.scanner.peek().is_some()
+        self.peek_kind(0).is_some()
+    }
+
+    /// Indicates whether the next `Token` is of the given `kind`. False at the end of the source.
+    fn check(&mut self, kind: Kind) -> bool {
+        self.peek_kind(0) == Some(&kind)
+    }
+
+    /// The kind of the `Token` `n` tokens after the next one, if there is one.
+    /// Tokens scanned to find it are buffered until they are consumed.
+    fn peek_kind(&mut self, n: usize) -> Option<&Kind> {
+        while self.lookahead.len() <= n {
+            let token = self.scanner.next()?;
+            self.lookahead.push_back(token);
+        }
+        self.lookahead.get(n).map(|token| &token.kind)
     }
 
     /// Get a reference to the next `Token` that will be returned by `advance`
     fn next(&mut self) -> &Token {
-        self.scanner.peek().unwrap()
+        self.peek_kind(0);
+        self.lookahead.front().unwrap()
     }
 
-    /// Return the next `Token` and advance `self.scanner` to the next `Token`
+    /// Return the next `Token` and advance to the one after it
     fn advance(&mut self) -> Token {
-        self.scanner.next().unwrap()
+        self.peek_kind(0);
+        self.lookahead.pop_front().unwrap()
     }
 
     /// Advance if the current `Token` matches `kind`. Otherwise, return an error
@@ -666,9 +1330,9 @@ impl<'a> Parser<'a> {
         }
     }
 
-    /// Consume tokens until current is '{', '}', or the token after a ';'
+    /// Consume tokens until current is '{', '}', the token after a ';', or the end of the source
     fn synchronize(&mut self) {
-        loop {
+        while self.has_next() {
             match self.next().kind {
                 Kind::Semicolon => {
                     self.advance();
@@ -684,3 +1348,30 @@ impl<'a> Parser<'a> {
         }
     }
 }
+
+/// The binary operator applied by a compound assignment operator such as '+='
+fn compound_operator(kind: &Kind) -> Option<Kind> {
+    match kind {
+        Kind::PlusEqual => Some(Kind::Plus),
+        Kind::MinusEqual => Some(Kind::Minus),
+        Kind::StarEqual => Some(Kind::Star),
+        Kind::SlashEqual => Some(Kind::Slash),
+        Kind::PercentEqual => Some(Kind::Percent),
+        Kind::StarStarEqual => Some(Kind::StarStar),
+        Kind::AmpersandEqual => Some(Kind::Ampersand),
+        Kind::PipeEqual => Some(Kind::Pipe),
+        Kind::CaretEqual => Some(Kind::Caret),
+        Kind::LessLessEqual => Some(Kind::LessLess),
+        Kind::GreaterGreaterEqual => Some(Kind::GreaterGreater),
+        _ => None,
+    }
+}
+
+/// The binary operator applied by '++' or '--'
+fn increment_operator(kind: &Kind) -> Kind {
+    if *kind == Kind::PlusPlus {
+        Kind::Plus
+    } else {
+        Kind::Minus
+    }
+}
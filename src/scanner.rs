@@ -14,6 +14,10 @@ pub struct Scanner<'a> {
 
     /// The index in the underlying source string at which the current token begins
     current_start_index: usize,
+
+    /// The number of unclosed '{' in the expression of each string interpolation
+    /// being scanned, innermost last
+    interpolations: Vec<usize>,
 }
 
 impl Iterator for Scanner<'_> {
@@ -22,55 +26,109 @@ impl Iterator for Scanner<'_> {
     /// Return the next `Token` in the source.
     /// If there is no next token, return Token::Eof
     fn next(&mut self) -> Option<Token> {
-        if self.peek(0) == None {
-            return None;
-        }
+        self.peek(0)?;
 
         self.consume_whitespace();
-        if let Some(ch) = self.advance() {
-            Some(match ch {
-                '{' => self.take_token(Kind::LeftBrace),
-                '}' => self.take_token(Kind::RightBrace),
-                '(' => self.take_token(Kind::LeftParen),
-                ')' => self.take_token(Kind::RightParen),
-                ',' => self.take_token(Kind::Comma),
-                '.' => self.take_token(Kind::Dot),
-                '-' => self.take_token(Kind::Minus),
-                '+' => self.take_token(Kind::Plus),
-                '/' => self.take_token(Kind::Slash),
-                '*' => self.take_token(Kind::Star),
-                ';' => self.take_token(Kind::Semicolon),
-
-                '!' if self.peek(0) == Some('=') => {
-                    self.advance();
-                    self.take_token(Kind::BangEqual)
-                }
-                '!' => self.take_token(Kind::Bang),
-                '=' if self.peek(0) == Some('=') => {
-                    self.advance();
-                    self.take_token(Kind::EqualEqual)
+        self.advance().map(|ch| match ch {
+            '{' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
                 }
-                '=' => self.take_token(Kind::Equal),
-                '>' if self.peek(0) == Some('=') => {
-                    self.advance();
-                    self.take_token(Kind::GreaterEqual)
+                self.take_token(Kind::LeftBrace)
+            }
+            '}' => match self.interpolations.last_mut() {
+                // The end of an interpolated expression continues the string
+                Some(0) => {
+                    self.interpolations.pop();
+                    self.string_literal()
                 }
-                '>' => self.take_token(Kind::Greater),
-                '<' if self.peek(0) == Some('=') => {
-                    self.advance();
-                    self.take_token(Kind::LessEqual)
+                Some(depth) => {
+                    *depth -= 1;
+                    self.take_token(Kind::RightBrace)
                 }
-                '<' => self.take_token(Kind::Less),
+                None => self.take_token(Kind::RightBrace),
+            },
+            '(' => self.take_token(Kind::LeftParen),
+            ')' => self.take_token(Kind::RightParen),
+            '[' => self.take_token(Kind::LeftBracket),
+            ']' => self.take_token(Kind::RightBracket),
+            ',' => self.take_token(Kind::Comma),
+            '.' => self.take_token(Kind::Dot),
+            '-' if self.peek(0) == Some('-') => {
+                self.advance();
+                self.take_token(Kind::MinusMinus)
+            }
+            '-' => self.take_operator(Kind::Minus, Kind::MinusEqual),
+            '+' if self.peek(0) == Some('+') => {
+                self.advance();
+                self.take_token(Kind::PlusPlus)
+            }
+            '+' => self.take_operator(Kind::Plus, Kind::PlusEqual),
+            '/' => self.take_operator(Kind::Slash, Kind::SlashEqual),
+            '*' if self.peek(0) == Some('*') => {
+                self.advance();
+                self.take_operator(Kind::StarStar, Kind::StarStarEqual)
+            }
+            '*' => self.take_operator(Kind::Star, Kind::StarEqual),
+            '%' => self.take_operator(Kind::Percent, Kind::PercentEqual),
+            '&' => self.take_operator(Kind::Ampersand, Kind::AmpersandEqual),
+            '|' => self.take_operator(Kind::Pipe, Kind::PipeEqual),
+            '^' => self.take_operator(Kind::Caret, Kind::CaretEqual),
+            '~' => self.take_token(Kind::Tilde),
+            ';' => self.take_token(Kind::Semicolon),
+            ':' => self.take_token(Kind::Colon),
+            '?' if self.peek(0) == Some('?') => {
+                self.advance();
+                self.take_token(Kind::QuestionQuestion)
+            }
+            '?' if self.peek(0) == Some('.') => {
+                self.advance();
+                self.take_token(Kind::QuestionDot)
+            }
+            '?' => self.take_token(Kind::Question),
 
-                'a'..='z' | 'A'..='Z' | '_' => self.identifier_literal(),
-                '0'..='9' => self.number_literal(),
-                '"' => self.string_literal(),
+            '!' if self.peek(0) == Some('=') => {
+                self.advance();
+                self.take_token(Kind::BangEqual)
+            }
+            '!' => self.take_token(Kind::Bang),
+            '=' if self.peek(0) == Some('=') => {
+                self.advance();
+                self.take_token(Kind::EqualEqual)
+            }
+            '=' if self.peek(0) == Some('>') => {
+                self.advance();
+                self.take_token(Kind::Arrow)
+            }
+            '=' => self.take_token(Kind::Equal),
+            '>' if self.peek(0) == Some('=') => {
+                self.advance();
+                self.take_token(Kind::GreaterEqual)
+            }
+            '>' if self.peek(0) == Some('>') => {
+                self.advance();
+                self.take_operator(Kind::GreaterGreater, Kind::GreaterGreaterEqual)
+            }
+            '>' => self.take_token(Kind::Greater),
+            '<' if self.peek(0) == Some('=') => {
+                self.advance();
+                self.take_token(Kind::LessEqual)
+            }
+            '<' if self.peek(0) == Some('<') => {
+                self.advance();
+                self.take_operator(Kind::LessLess, Kind::LessLessEqual)
+            }
+            '<' => self.take_token(Kind::Less),
 
-                _ => self.take_error_token("unrecognized character"),
-            })
-        } else {
-            None
-        }
+            'a'..='z' | 'A'..='Z' | '_' => self.identifier_literal(),
+            '0'..='9' => self.number_literal(),
+            '"' if self.peek(0) == Some('"') && self.peek(1) == Some('"') => {
+                self.raw_string_literal()
+            }
+            '"' => self.string_literal(),
+
+            _ => self.take_error_token("unrecognized character"),
+        })
     }
 }
 
@@ -81,14 +139,14 @@ impl<'a> Scanner<'a> {
             characters: source.chars(),
             current: String::new(),
             current_start_index: 0,
+            interpolations: vec![],
         }
     }
 
     /// Consume a single `char` from `self.characters` and append it to `self.current`
     fn advance(&mut self) -> Option<char> {
-        self.characters.next().map(|ch| {
+        self.characters.next().inspect(|&ch| {
             self.current.push(ch);
-            ch
         })
     }
 
@@ -105,22 +163,33 @@ impl<'a> Scanner<'a> {
 
         match self.current.as_str() {
             "and" => self.take_token(Kind::And),
+            "as" => self.take_token(Kind::As),
+            "break" => self.take_token(Kind::Break),
+            "case" => self.take_token(Kind::Case),
+            "catch" => self.take_token(Kind::Catch),
             "class" => self.take_token(Kind::Class),
+            "continue" => self.take_token(Kind::Continue),
+            "default" => self.take_token(Kind::Default),
             "else" => self.take_token(Kind::Else),
 
             "false" => self.take_token(Kind::False),
+            "finally" => self.take_token(Kind::Finally),
             "for" => self.take_token(Kind::For),
             "fun" => self.take_token(Kind::Fun),
 
             "if" => self.take_token(Kind::If),
+            "import" => self.take_token(Kind::Import),
             "nil" => self.take_token(Kind::Nil),
             "or" => self.take_token(Kind::Or),
             "print" => self.take_token(Kind::Print),
             "return" => self.take_token(Kind::Return),
             "super" => self.take_token(Kind::Super),
+            "switch" => self.take_token(Kind::Switch),
 
             "this" => self.take_token(Kind::This),
+            "throw" => self.take_token(Kind::Throw),
             "true" => self.take_token(Kind::True),
+            "try" => self.take_token(Kind::Try),
 
             "var" => self.take_token(Kind::Var),
             "while" => self.take_token(Kind::While),
@@ -135,44 +204,229 @@ impl<'a> Scanner<'a> {
         }
     }
 
-    /// Consumes a number literal and makes a Token
+    /// Consumes a number literal and makes a Token.
+    ///
+    /// Numbers may be written in decimal with an optional fraction and exponent,
+    /// or as integers in hexadecimal (`0x`) or binary (`0b`). Any of them may
+    /// contain '_' between digits as a separator.
     fn number_literal(&mut self) -> Token {
-        while is_digit(self.peek(0)) {
-            self.advance();
+        if self.current == "0" {
+            match self.peek(0) {
+                Some('x' | 'X') => return self.radix_literal(16, "hexadecimal"),
+                Some('b' | 'B') => return self.radix_literal(2, "binary"),
+                _ => {}
+            }
         }
 
+        self.consume_digits(10);
+
         if self.peek(0) == Some('.') && is_digit(self.peek(1)) {
             self.advance();
-            self.advance();
+            self.consume_digits(10);
+        }
 
-            while is_digit(self.peek(0)) {
+        if let Some('e' | 'E') = self.peek(0) {
+            self.advance();
+            if let Some('+' | '-') = self.peek(0) {
                 self.advance();
             }
+            if !is_digit(self.peek(0)) {
+                return self.take_error_token("missing digits in exponent");
+            }
+            self.consume_digits(10);
+        }
+
+        if !has_valid_separators(&self.current, 10) {
+            return self.take_error_token("invalid digit separator");
         }
 
         let (source, span) = self.take_current();
         Token {
             span,
-            kind: Kind::NumberLiteral(source.parse().unwrap()),
+            kind: Kind::NumberLiteral(source.replace('_', "").parse().unwrap()),
         }
     }
 
-    /// Consumes a string literal and makes a Token
-    fn string_literal(&mut self) -> Token {
-        while let Some(ch) = self.advance() {
-            if ch == '"' {
+    /// Consumes the prefix and digits of an integer literal in the given `radix`
+    /// and makes a Token
+    fn radix_literal(&mut self, radix: u32, name: &str) -> Token {
+        self.advance();
+        self.consume_digits(radix);
+
+        let digits = &self.current[2..];
+        if !digits.chars().any(|ch| ch.is_digit(radix)) {
+            return self.take_error_token(&format!("missing digits in {} literal", name));
+        }
+        if !has_valid_separators(&self.current, radix) {
+            return self.take_error_token("invalid digit separator");
+        }
+
+        let value = digits
+            .chars()
+            .filter_map(|ch| ch.to_digit(radix))
+            .fold(0f64, |value, digit| value * radix as f64 + digit as f64);
+        let (_, span) = self.take_current();
+        Token {
+            span,
+            kind: Kind::NumberLiteral(value),
+        }
+    }
+
+    /// Consumes any digits in the given `radix` and digit separators
+    fn consume_digits(&mut self, radix: u32) {
+        while let Some(ch) = self.peek(0) {
+            if ch != '_' && !ch.is_digit(radix) {
                 break;
             }
+            self.advance();
         }
+    }
 
-        if !self.current.ends_with('"') {
-            return self.take_error_token("unclosed string literal");
+    /// Consumes a string literal, or the part of one up to an interpolated
+    /// expression, and makes a Token.
+    ///
+    /// A string containing `${expression}` is scanned as an `Interpolation` token
+    /// for the text before the expression, the tokens of the expression, and then
+    /// the rest of the string, starting from the closing '}'. Escape sequences are
+    /// replaced by the characters they represent, and the first invalid one, or the
+    /// first empty interpolation, makes the token an error spanning just that part.
+    fn string_literal(&mut self) -> Token {
+        let mut value = String::new();
+        let mut invalid_part = None;
+        loop {
+            match self.advance() {
+                None => return self.take_error_token("unclosed string literal"),
+                Some('"') => break,
+                Some('$') if self.peek(0) == Some('{') => {
+                    if let Some(error) = self.empty_interpolation() {
+                        invalid_part = invalid_part.or(Some(error));
+                        continue;
+                    }
+                    self.advance();
+                    self.interpolations.push(0);
+                    return self.take_string_token(Kind::Interpolation(value), invalid_part);
+                }
+                Some('\\') => match self.escape_sequence() {
+                    Ok(ch) => value.push(ch),
+                    Err(error) => invalid_part = invalid_part.or(Some(error)),
+                },
+                Some(ch) => value.push(ch),
+            }
+        }
+
+        self.take_string_token(Kind::StringLiteral(value), invalid_part)
+    }
+
+    /// Consumes the rest of an interpolation following a '$' if it contains only
+    /// whitespace, as in `${}`, and returns its error message and span.
+    fn empty_interpolation(&mut self) -> Option<(&'static str, Span)> {
+        let start = self.current_start_index + self.current.len() - 1;
+        let mut count = 1;
+        while matches!(
+            self.peek(count),
+            Some(' ') | Some('\t') | Some('\r') | Some('\n')
+        ) {
+            count += 1;
+        }
+        if self.peek(count) != Some('}') {
+            return None;
+        }
+
+        for _ in 0..=count {
+            self.advance();
+        }
+        let end = self.current_start_index + self.current.len();
+        Some(("empty interpolation", Span::new(start, end)))
+    }
+
+    /// Consumes the rest of an escape sequence following a '\\' and returns the
+    /// character it represents. An invalid escape sequence is consumed up to
+    /// the point where it became invalid, and its message and span are returned.
+    fn escape_sequence(&mut self) -> Result<char, (&'static str, Span)> {
+        let start = self.current_start_index + self.current.len() - 1;
+        let escaped = match self.advance() {
+            Some('n') => Some('\n'),
+            Some('t') => Some('\t'),
+            Some('r') => Some('\r'),
+            Some('0') => Some('\0'),
+            Some('\\') => Some('\\'),
+            Some('"') => Some('"'),
+            Some('$') => Some('$'),
+            Some('u') => return self.unicode_escape(start),
+            _ => None,
+        };
+
+        let end = self.current_start_index + self.current.len();
+        escaped.ok_or(("invalid escape sequence", Span::new(start, end)))
+    }
+
+    /// Consumes the `{XXXX}` of a unicode escape sequence beginning at `start`,
+    /// which holds the hexadecimal code point of a character
+    fn unicode_escape(&mut self, start: usize) -> Result<char, (&'static str, Span)> {
+        let mut digits = String::new();
+        let mut is_closed = false;
+        if self.peek(0) == Some('{') {
+            self.advance();
+            while let Some(ch) = self.peek(0).filter(char::is_ascii_hexdigit) {
+                self.advance();
+                digits.push(ch);
+            }
+            if self.peek(0) == Some('}') {
+                self.advance();
+                is_closed = true;
+            }
+        }
+
+        let end = self.current_start_index + self.current.len();
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .filter(|_| is_closed && digits.len() <= 6)
+            .and_then(char::from_u32)
+            .ok_or(("invalid unicode escape sequence", Span::new(start, end)))
+    }
+
+    /// Consumes a raw string literal delimited by `"""` and makes a Token. Raw
+    /// strings may span multiple lines and contain quotes, and are taken verbatim:
+    /// neither escape sequences nor interpolations are processed. A line break
+    /// directly after the opening delimiter is not part of the string.
+    fn raw_string_literal(&mut self) -> Token {
+        self.advance();
+        self.advance();
+        if self.peek(0) == Some('\n') {
+            self.advance();
+        } else if self.peek(0) == Some('\r') && self.peek(1) == Some('\n') {
+            self.advance();
+            self.advance();
+        }
+        let opening = self.current.len();
+
+        while !self.current[opening..].ends_with("\"\"\"") {
+            if self.advance().is_none() {
+                return self.take_error_token("unclosed string literal");
+            }
         }
 
         let (source, span) = self.take_current();
         Token {
             span,
-            kind: Kind::StringLiteral(source[1..(source.len() - 1)].to_string()),
+            kind: Kind::StringLiteral(source[opening..(source.len() - 3)].to_string()),
+        }
+    }
+
+    /// Consume `self.current` to produce a string `Token` with the given kind, or
+    /// an error `Token` for the given invalid part of the string
+    fn take_string_token(&mut self, kind: Kind, invalid_part: Option<(&str, Span)>) -> Token {
+        let (source, span) = self.take_current();
+        match invalid_part {
+            Some((message, part_span)) => Token {
+                span: part_span,
+                kind: Kind::Error {
+                    message: message.to_string(),
+                    source: source[(part_span.start - span.start)..(part_span.end - span.start)]
+                        .to_string(),
+                },
+            },
+            None => Token { span, kind },
         }
     }
 
@@ -182,6 +436,17 @@ impl<'a> Scanner<'a> {
         Token { kind, span }
     }
 
+    /// Consume `self.current` to produce a `Token` for a binary operator with the given
+    /// `kind`, or for its compound assignment with `assignment_kind` if followed by '='
+    fn take_operator(&mut self, kind: Kind, assignment_kind: Kind) -> Token {
+        if self.peek(0) == Some('=') {
+            self.advance();
+            self.take_token(assignment_kind)
+        } else {
+            self.take_token(kind)
+        }
+    }
+
     /// Consume `self.current` to produce a `Token` with `Kind::Error` with the given `message`
     fn take_error_token(&mut self, message: &str) -> Token {
         let (source, span) = self.take_current();
@@ -229,22 +494,22 @@ impl<'a> Scanner<'a> {
 }
 
 fn is_digit(ch: Option<char>) -> bool {
-    if let Some(_c @ '0'..='9') = ch {
-        true
-    } else {
-        false
-    }
+    matches!(ch, Some('0'..='9'))
 }
 
 fn is_alpha_or_under(ch: Option<char>) -> bool {
-    if let Some(c) = ch {
-        match c {
-            'a'..='z' | 'A'..='Z' | '_' => true,
-            _ => false,
-        }
-    } else {
-        false
-    }
+    matches!(ch, Some('a'..='z' | 'A'..='Z' | '_'))
+}
+
+/// Whether every '_' in the number literal `source` is between two digits in `radix`
+fn has_valid_separators(source: &str, radix: u32) -> bool {
+    let chars: Vec<char> = source.chars().collect();
+    chars.iter().enumerate().all(|(index, &ch)| {
+        ch != '_'
+            || (index > 0
+                && chars[index - 1].is_digit(radix)
+                && chars.get(index + 1).is_some_and(|ch| ch.is_digit(radix)))
+    })
 }
 
 #[cfg(test)]
@@ -259,6 +524,35 @@ mod tests {
         single_token_test(String::from("123.456"), Kind::NumberLiteral(123.456f64));
         single_token_test(String::from("0.456"), Kind::NumberLiteral(0.456f64));
         single_token_test(String::from("0.0"), Kind::NumberLiteral(0f64));
+        single_token_test(String::from("0xFF"), Kind::NumberLiteral(255f64));
+        single_token_test(String::from("0Xa_b"), Kind::NumberLiteral(171f64));
+        single_token_test(String::from("0b1010"), Kind::NumberLiteral(10f64));
+        single_token_test(String::from("1.5e-3"), Kind::NumberLiteral(1.5e-3f64));
+        single_token_test(String::from("2E+10"), Kind::NumberLiteral(2e10f64));
+        single_token_test(String::from("1_000_000"), Kind::NumberLiteral(1e6f64));
+        single_token_test(String::from("0.000_1"), Kind::NumberLiteral(1e-4f64));
+    }
+
+    #[test]
+    fn invalid_number_literals() {
+        let cases = vec![
+            ("0x", "missing digits in hexadecimal literal"),
+            ("0b", "missing digits in binary literal"),
+            ("1e", "missing digits in exponent"),
+            ("1.5e-", "missing digits in exponent"),
+            ("1__0", "invalid digit separator"),
+            ("1_", "invalid digit separator"),
+            ("0x_1", "invalid digit separator"),
+        ];
+
+        for (source, expected_message) in cases {
+            let token = scanner::Scanner::new(source).next().unwrap();
+            assert_eq!(token.span, Span::new(0, source.len()), "{}", source);
+            match token.kind {
+                Kind::Error { message, .. } => assert_eq!(message, expected_message),
+                kind => panic!("Expected an error token but got {:?}", kind),
+            }
+        }
     }
 
     #[test]
@@ -270,6 +564,55 @@ mod tests {
         );
     }
 
+    #[test]
+    fn escape_sequences() {
+        single_token_test(
+            String::from(r#""a\tb\nc\r\0""#),
+            Kind::StringLiteral("a\tb\nc\r\0".to_string()),
+        );
+        single_token_test(
+            String::from(r#""\\ \" \${x}""#),
+            Kind::StringLiteral("\\ \" ${x}".to_string()),
+        );
+        single_token_test(
+            String::from(r#""\u{48}\u{e9}\u{1F600}""#),
+            Kind::StringLiteral("H\u{e9}\u{1F600}".to_string()),
+        );
+    }
+
+    #[test]
+    fn invalid_escape_sequences() {
+        let cases = vec![
+            (r#""ab\qcd""#, "\\q", Span::new(3, 5)),
+            (r#""\u{110000}""#, "\\u{110000}", Span::new(1, 11)),
+            (r#""\u{zz}""#, "\\u{", Span::new(1, 4)),
+            (r#""\u48""#, "\\u", Span::new(1, 3)),
+            (r#""a${}b""#, "${}", Span::new(2, 5)),
+            ("\"${ \t}\"", "${ \t}", Span::new(1, 6)),
+        ];
+
+        for (source, escape, span) in cases {
+            let token = scanner::Scanner::new(source).next().unwrap();
+            assert_eq!(token.span, span, "{}", source);
+            match token.kind {
+                Kind::Error { source, .. } => assert_eq!(source, escape),
+                kind => panic!("Expected an error token but got {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn raw_string_literals() {
+        single_token_test(
+            String::from("\"\"\"\"\"\""),
+            Kind::StringLiteral("".to_string()),
+        );
+        single_token_test(
+            String::from("\"\"\"\n<a href=\"${url}\">\\n</a>\n\"\"\""),
+            Kind::StringLiteral("<a href=\"${url}\">\\n</a>\n".to_string()),
+        );
+    }
+
     #[test]
     fn identifier_literals() {
         single_token_test(String::from("x"), Kind::IdentifierLiteral("x".to_string()));
@@ -306,6 +649,17 @@ mod tests {
         single_token_test(String::from("return"), Kind::Return);
         single_token_test(String::from("super"), Kind::Super);
         single_token_test(String::from("this"), Kind::This);
+        single_token_test(String::from("throw"), Kind::Throw);
+        single_token_test(String::from("try"), Kind::Try);
+        single_token_test(String::from("catch"), Kind::Catch);
+        single_token_test(String::from("finally"), Kind::Finally);
+        single_token_test(String::from("import"), Kind::Import);
+        single_token_test(String::from("as"), Kind::As);
+        single_token_test(String::from("break"), Kind::Break);
+        single_token_test(String::from("continue"), Kind::Continue);
+        single_token_test(String::from("switch"), Kind::Switch);
+        single_token_test(String::from("case"), Kind::Case);
+        single_token_test(String::from("default"), Kind::Default);
     }
 
     #[test]
@@ -314,6 +668,8 @@ mod tests {
         single_token_test(String::from("}"), Kind::RightBrace);
         single_token_test(String::from("("), Kind::LeftParen);
         single_token_test(String::from(")"), Kind::RightParen);
+        single_token_test(String::from("["), Kind::LeftBracket);
+        single_token_test(String::from("]"), Kind::RightBracket);
         single_token_test(String::from(","), Kind::Comma);
         single_token_test(String::from("."), Kind::Dot);
         single_token_test(String::from("-"), Kind::Minus);
@@ -321,6 +677,11 @@ mod tests {
         single_token_test(String::from("*"), Kind::Star);
         single_token_test(String::from("/"), Kind::Slash);
         single_token_test(String::from(";"), Kind::Semicolon);
+        single_token_test(String::from(":"), Kind::Colon);
+        single_token_test(String::from("=>"), Kind::Arrow);
+        single_token_test(String::from("?"), Kind::Question);
+        single_token_test(String::from("??"), Kind::QuestionQuestion);
+        single_token_test(String::from("?."), Kind::QuestionDot);
         single_token_test(String::from("!"), Kind::Bang);
         single_token_test(String::from("!="), Kind::BangEqual);
         single_token_test(String::from("="), Kind::Equal);
@@ -329,6 +690,27 @@ mod tests {
         single_token_test(String::from(">="), Kind::GreaterEqual);
         single_token_test(String::from("<"), Kind::Less);
         single_token_test(String::from("<="), Kind::LessEqual);
+        single_token_test(String::from("%"), Kind::Percent);
+        single_token_test(String::from("**"), Kind::StarStar);
+        single_token_test(String::from("&"), Kind::Ampersand);
+        single_token_test(String::from("|"), Kind::Pipe);
+        single_token_test(String::from("^"), Kind::Caret);
+        single_token_test(String::from("~"), Kind::Tilde);
+        single_token_test(String::from("<<"), Kind::LessLess);
+        single_token_test(String::from(">>"), Kind::GreaterGreater);
+        single_token_test(String::from("++"), Kind::PlusPlus);
+        single_token_test(String::from("--"), Kind::MinusMinus);
+        single_token_test(String::from("+="), Kind::PlusEqual);
+        single_token_test(String::from("-="), Kind::MinusEqual);
+        single_token_test(String::from("*="), Kind::StarEqual);
+        single_token_test(String::from("/="), Kind::SlashEqual);
+        single_token_test(String::from("%="), Kind::PercentEqual);
+        single_token_test(String::from("**="), Kind::StarStarEqual);
+        single_token_test(String::from("&="), Kind::AmpersandEqual);
+        single_token_test(String::from("|="), Kind::PipeEqual);
+        single_token_test(String::from("^="), Kind::CaretEqual);
+        single_token_test(String::from("<<="), Kind::LessLessEqual);
+        single_token_test(String::from(">>="), Kind::GreaterGreaterEqual);
     }
 
     #[test]
@@ -339,7 +721,7 @@ mod tests {
                 print \"hey   \"
         ";
 
-        let mut scanner = scanner::Scanner::new(&source);
+        let mut scanner = scanner::Scanner::new(source);
         assert_eq!(scanner.next().unwrap().kind, Kind::While);
         assert_eq!(scanner.next().unwrap().kind, Kind::LeftParen);
         assert_eq!(scanner.next().unwrap().kind, Kind::True);
@@ -351,6 +733,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn interpolated_strings() {
+        let mut scanner = scanner::Scanner::new("\"a ${b + {}} c ${d}\"");
+        assert_eq!(
+            scanner.next().unwrap().kind,
+            Kind::Interpolation("a ".to_string())
+        );
+        assert_eq!(
+            scanner.next().unwrap().kind,
+            Kind::IdentifierLiteral("b".to_string())
+        );
+        assert_eq!(scanner.next().unwrap().kind, Kind::Plus);
+        assert_eq!(scanner.next().unwrap().kind, Kind::LeftBrace);
+        assert_eq!(scanner.next().unwrap().kind, Kind::RightBrace);
+        assert_eq!(
+            scanner.next().unwrap().kind,
+            Kind::Interpolation(" c ".to_string())
+        );
+        assert_eq!(
+            scanner.next().unwrap().kind,
+            Kind::IdentifierLiteral("d".to_string())
+        );
+        assert_eq!(
+            scanner.next().unwrap().kind,
+            Kind::StringLiteral("".to_string())
+        );
+        assert_eq!(scanner.next(), None);
+    }
+
     #[test]
     fn empty_file() {
         let mut scanner = scanner::Scanner::new("");
@@ -366,7 +777,7 @@ long_id // This is a comment
         "
         .trim();
 
-        let mut scanner = scanner::Scanner::new(&source);
+        let mut scanner = scanner::Scanner::new(source);
         assert_eq!(scanner.next().unwrap().span, Span::new(0, 7));
         assert_eq!(scanner.next().unwrap().span, Span::new(30, 38));
         assert_eq!(scanner.next(), None);
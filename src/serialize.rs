@@ -0,0 +1,416 @@
+use crate::error::LoadError;
+use crate::executable::Executable;
+use crate::object::{ObjClass, ObjClosure, ObjFunction, ObjString};
+use crate::opcode::OpCode;
+use crate::symbol::GlobalSlots;
+use crate::token::Span;
+use crate::value::Value;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::rc::Rc;
+
+/// The bytes at the start of every compiled Lox file
+pub const MAGIC: &[u8; 4] = b"LOXC";
+
+/// The version of the compiled file format. Files written with a
+/// different version are rejected by `deserialize`.
+pub const FORMAT_VERSION: u16 = 4;
+
+// Tags identifying the kind of each serialized constant
+const CONSTANT_NIL: u8 = 0;
+const CONSTANT_BOOL: u8 = 1;
+const CONSTANT_NUMBER: u8 = 2;
+const CONSTANT_STRING: u8 = 3;
+const CONSTANT_FUNCTION: u8 = 4;
+const CONSTANT_CLASS: u8 = 5;
+
+/// Serialize a compiled script, along with the source it was compiled from, into
+/// the compiled file format.
+///
+/// The source is stored so that errors raised while running the compiled script
+/// can still be reported with the relevant bits of source code. The script's
+/// globals are named using `global_slots`, which it was compiled against.
+///
+/// Layout (all integers little endian):
+///
+/// * `MAGIC`, then `FORMAT_VERSION` as a u16
+/// * the source as a string (u32 length, then UTF-8 bytes)
+/// * the global variables referenced by the script (u32 count, then the u32 slot and
+///   the name of each), so that they can be assigned new slots when loaded
+/// * the script function
+///
+/// A function is its name, its arity (u8), its upvalue descriptors (u32 count, then
+/// a u8 `is_local` flag and u32 index for each), its encoded instructions (u32 length,
+/// then the bytes), the span of each instruction (two u32s each), and its constants
+/// (u32 count, then each tagged constant).
+pub fn serialize(script: &ObjClosure, source: &str, global_slots: &GlobalSlots) -> Vec<u8> {
+    let mut globals = BTreeSet::new();
+    let mut function = vec![];
+    write_function(&mut function, &script.function, &mut globals);
+
+    let mut out = vec![];
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    write_string(&mut out, source);
+
+    write_u32(&mut out, globals.len());
+    for slot in globals {
+        let name = global_slots.name(slot).expect("Global slot without a name");
+        write_u32(&mut out, slot);
+        write_string(&mut out, name.as_str());
+    }
+
+    out.extend_from_slice(&function);
+    out
+}
+
+/// Load a compiled script and its source from bytes written by `serialize`.
+/// The script's globals are assigned slots in `global_slots`, so that it can be
+/// run by the VM that they belong to.
+///
+/// Returns a `LoadError` if the bytes are not a valid compiled file for this version.
+pub fn deserialize(
+    bytes: &[u8],
+    global_slots: &mut GlobalSlots,
+) -> Result<(ObjClosure, String), LoadError> {
+    let mut reader = Reader::new(bytes);
+
+    if !is_compiled(bytes) {
+        return Err(LoadError::new("Not a compiled Lox file".to_string()));
+    }
+    reader.position = MAGIC.len();
+
+    let version = u16::from_le_bytes([reader.read_u8()?, reader.read_u8()?]);
+    if version != FORMAT_VERSION {
+        return Err(LoadError::new(format!(
+            "Unsupported compiled file version {} (expected {})",
+            version, FORMAT_VERSION
+        )));
+    }
+
+    let source = reader.read_string()?;
+
+    let global_count = reader.read_u32()?;
+    for _ in 0..global_count {
+        let slot = reader.read_u32()?;
+        let name = reader.read_string()?;
+        reader.globals.insert(slot, global_slots.slot(&name));
+    }
+
+    let function = reader.read_function()?;
+
+    if reader.position != bytes.len() {
+        return Err(LoadError::new(
+            "Unexpected data after the end of the script".to_string(),
+        ));
+    }
+
+    if !function.upvalues.is_empty() {
+        return Err(LoadError::new(
+            "The script function may not capture upvalues".to_string(),
+        ));
+    }
+
+    Ok((
+        ObjClosure {
+            function: Rc::new(function),
+            upvalues: vec![],
+        },
+        source,
+    ))
+}
+
+/// Indicates whether `bytes` begin with the compiled file magic header
+pub fn is_compiled(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+fn write_u8(out: &mut Vec<u8>, value: u8) {
+    out.push(value);
+}
+
+fn write_u32(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u32).to_le_bytes());
+}
+
+fn write_string(out: &mut Vec<u8>, string: &str) {
+    write_u32(out, string.len());
+    out.extend_from_slice(string.as_bytes());
+}
+
+/// Write `function`, adding the slot of each global it references to `globals`
+fn write_function(out: &mut Vec<u8>, function: &ObjFunction, globals: &mut BTreeSet<usize>) {
+    write_string(out, &function.name.string);
+    write_u8(out, function.arity);
+
+    write_u32(out, function.upvalues.len());
+    for (is_local, index) in function.upvalues.iter() {
+        write_u8(out, *is_local as u8);
+        write_u32(out, *index);
+    }
+
+    let bin = &function.bin;
+    write_u32(out, bin.len());
+    out.extend_from_slice(bin.code());
+
+    let mut offset = 0;
+    while offset < bin.len() {
+        let span = bin.span_at(offset);
+        write_u32(out, span.start);
+        write_u32(out, span.end);
+
+        let (op, next) = bin.read_instruction(offset);
+        if let OpCode::DeclareGlobal(slot) | OpCode::GetGlobal(slot) | OpCode::SetGlobal(slot) = op
+        {
+            globals.insert(slot);
+        }
+        offset = next;
+    }
+
+    write_u32(out, bin.constants().len());
+    for constant in bin.constants() {
+        write_constant(out, constant, globals);
+    }
+}
+
+fn write_constant(out: &mut Vec<u8>, constant: &Value, globals: &mut BTreeSet<usize>) {
+    match constant {
+        Value::Nil => write_u8(out, CONSTANT_NIL),
+        Value::Bool(b) => {
+            write_u8(out, CONSTANT_BOOL);
+            write_u8(out, *b as u8);
+        }
+        Value::Number(n) => {
+            write_u8(out, CONSTANT_NUMBER);
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+        Value::String(s) => {
+            write_u8(out, CONSTANT_STRING);
+            write_string(out, &s.string);
+        }
+        Value::Function(function) => {
+            write_u8(out, CONSTANT_FUNCTION);
+            write_function(out, function, globals);
+        }
+        Value::Class(class) => {
+            write_u8(out, CONSTANT_CLASS);
+            write_string(out, &class.name.string);
+        }
+        Value::Closure(_)
+        | Value::Instance(_)
+        | Value::BoundMethod(_)
+        | Value::Native(_)
+        | Value::NativeMethod(_)
+        | Value::List(_)
+        | Value::Map(_)
+        | Value::Module(_) => {
+            panic!("Cannot serialize runtime constant {:?}", constant)
+        }
+    }
+}
+
+/// A cursor over serialized bytes that errors instead of reading past the end
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+
+    /// The slot in the loading VM for each global slot in the file
+    globals: HashMap<usize, usize>,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader {
+            bytes,
+            position: 0,
+            globals: HashMap::new(),
+        }
+    }
+
+    fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], LoadError> {
+        if self.bytes.len() - self.position < count {
+            return Err(LoadError::new(
+                "Unexpected end of compiled file".to_string(),
+            ));
+        }
+        let bytes = &self.bytes[self.position..self.position + count];
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> Result<usize, LoadError> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    fn read_f64(&mut self) -> Result<f64, LoadError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.read_bytes(8)?);
+        Ok(f64::from_le_bytes(bytes))
+    }
+
+    fn read_bool(&mut self) -> Result<bool, LoadError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(LoadError::new(format!("Invalid boolean value {}", b))),
+        }
+    }
+
+    fn read_string(&mut self) -> Result<String, LoadError> {
+        let length = self.read_u32()?;
+        let bytes = self.read_bytes(length)?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| LoadError::new("Invalid UTF-8 in string".to_string()))
+    }
+
+    fn read_function(&mut self) -> Result<ObjFunction, LoadError> {
+        let name = self.read_string()?;
+        let arity = self.read_u8()?;
+
+        let upvalue_count = self.read_u32()?;
+        let mut upvalues = vec![];
+        for _ in 0..upvalue_count {
+            let is_local = self.read_bool()?;
+            let index = self.read_u32()?;
+            upvalues.push((is_local, index));
+        }
+
+        let mut bin = Executable::new(name.clone());
+        let code_length = self.read_u32()?;
+        let code = self.read_bytes(code_length)?;
+        let mut offset = 0;
+        while offset < code.len() {
+            let (op, next) = OpCode::decode(code, offset).ok_or_else(|| {
+                LoadError::new(format!("Invalid instruction at {} in {}", offset, name))
+            })?;
+            let op = self.relocate_global(op, &name)?;
+            let start = self.read_u32()?;
+            let end = self.read_u32()?;
+            bin.push_opcode(op, Span::new(start, end));
+            offset = next;
+        }
+
+        let constant_count = self.read_u32()?;
+        for _ in 0..constant_count {
+            let constant = self.read_constant()?;
+            bin.add_constant(constant);
+        }
+
+        validate(&bin, upvalues.len())?;
+
+        Ok(ObjFunction {
+            arity,
+            bin,
+            name: Box::new(ObjString::from(name)),
+            upvalues,
+        })
+    }
+
+    /// Replace the global slot referenced by `op`, if any, with the slot
+    /// assigned to the same name when the file was loaded
+    fn relocate_global(&self, op: OpCode, function_name: &str) -> Result<OpCode, LoadError> {
+        let relocate = |slot: usize| {
+            self.globals.get(&slot).copied().ok_or_else(|| {
+                LoadError::new(format!("Invalid global slot {} in {}", slot, function_name))
+            })
+        };
+        Ok(match op {
+            OpCode::DeclareGlobal(slot) => OpCode::DeclareGlobal(relocate(slot)?),
+            OpCode::GetGlobal(slot) => OpCode::GetGlobal(relocate(slot)?),
+            OpCode::SetGlobal(slot) => OpCode::SetGlobal(relocate(slot)?),
+            op => op,
+        })
+    }
+
+    fn read_constant(&mut self) -> Result<Value, LoadError> {
+        match self.read_u8()? {
+            CONSTANT_NIL => Ok(Value::Nil),
+            CONSTANT_BOOL => Ok(Value::Bool(self.read_bool()?)),
+            CONSTANT_NUMBER => Ok(Value::Number(self.read_f64()?)),
+            CONSTANT_STRING => Ok(Value::from(self.read_string()?)),
+            CONSTANT_FUNCTION => Ok(Value::from(self.read_function()?)),
+            CONSTANT_CLASS => Ok(Value::from(ObjClass {
+                name: Box::new(ObjString::from(self.read_string()?)),
+                methods: RefCell::new(HashMap::new()),
+                class_methods: RefCell::new(HashMap::new()),
+                getters: RefCell::new(HashMap::new()),
+            })),
+            tag => Err(LoadError::new(format!("Unknown constant tag {}", tag))),
+        }
+    }
+}
+
+/// Check that every constant index, jump destination, and upvalue index in `bin`
+/// refers to something that exists. Operands that depend on the depth of the
+/// stack, such as local slots and list lengths, are checked by the VM as it runs.
+fn validate(bin: &Executable, upvalue_count: usize) -> Result<(), LoadError> {
+    let constant_count = bin.constants().len();
+
+    // Jumps may only land at the start of an instruction or the end of the code
+    let mut instruction_starts = HashSet::new();
+    let mut offset = 0;
+    while offset < bin.len() {
+        instruction_starts.insert(offset);
+        offset = bin.read_instruction(offset).1;
+    }
+    instruction_starts.insert(offset);
+
+    let mut offset = 0;
+    while offset < bin.len() {
+        let (op, next) = bin.read_instruction(offset);
+        offset = next;
+
+        let (operand, valid, kind) = match op {
+            OpCode::Constant(arg)
+            | OpCode::ConstantLong(arg)
+            | OpCode::GetSuper(arg)
+            | OpCode::Closure(arg)
+            | OpCode::ReadField(arg)
+            | OpCode::SetField(arg)
+            | OpCode::Import(arg) => (arg, arg < constant_count, "constant"),
+            OpCode::Jump(arg)
+            | OpCode::JumpIfTrue(arg)
+            | OpCode::JumpIfFalse(arg)
+            | OpCode::PushHandler(arg) => {
+                (arg, instruction_starts.contains(&arg), "jump destination")
+            }
+            OpCode::GetUpvalue(arg) | OpCode::SetUpvalue(arg) => {
+                (arg, arg < upvalue_count, "upvalue")
+            }
+            _ => continue,
+        };
+        if !valid {
+            return Err(LoadError::new(format!(
+                "Invalid {} {} in {}",
+                kind, operand, bin.name
+            )));
+        }
+
+        // A closure may only capture upvalues that its enclosing function has
+        if let OpCode::Closure(arg) = op {
+            let function = match bin.get_constant(arg) {
+                Value::Function(function) => function,
+                value => {
+                    return Err(LoadError::new(format!(
+                        "Invalid closure constant {} in {}",
+                        value, bin.name
+                    )))
+                }
+            };
+            for (is_local, index) in function.upvalues.iter() {
+                if !is_local && *index >= upvalue_count {
+                    return Err(LoadError::new(format!(
+                        "Invalid upvalue {} in {}",
+                        index, function.name
+                    )));
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,155 @@
+use crate::object::ObjString;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::{Rc, Weak};
+
+thread_local! {
+    /// Every live interned string, by contents
+    static STRINGS: RefCell<HashMap<String, Weak<ObjString>>> = RefCell::new(HashMap::new());
+}
+
+/// Get the single shared `ObjString` with the contents `string`, creating it if
+/// there is no live string with those contents.
+///
+/// Because every string Value is interned, two string Values are equal exactly
+/// when they point to the same `ObjString`.
+pub fn intern(string: &str) -> Rc<ObjString> {
+    STRINGS.with(|strings| {
+        let mut strings = strings.borrow_mut();
+        if let Some(existing) = strings.get(string).and_then(Weak::upgrade) {
+            return existing;
+        }
+        let interned = Rc::new(ObjString::from(string));
+        strings.insert(string.to_string(), Rc::downgrade(&interned));
+        interned
+    })
+}
+
+/// Forget the interned string with the contents `string` if it is no longer alive.
+/// Called when an `ObjString` is dropped.
+pub(crate) fn release(string: &str) {
+    // The table may already be gone if the thread is exiting
+    let _ = STRINGS.try_with(|strings| {
+        if let Ok(mut strings) = strings.try_borrow_mut() {
+            if strings
+                .get(string)
+                .is_some_and(|weak| weak.strong_count() == 0)
+            {
+                strings.remove(string);
+            }
+        }
+    });
+}
+
+/// The number of distinct strings currently interned
+pub fn interned_count() -> usize {
+    STRINGS.with(|strings| {
+        strings
+            .borrow()
+            .values()
+            .filter(|weak| weak.strong_count() > 0)
+            .count()
+    })
+}
+
+/// An interned string used as the key of fields, methods, and globals.
+///
+/// Symbols are hashed and compared by identity, so lookups never need to
+/// examine the contents of the string.
+#[derive(Clone)]
+pub struct Symbol(Rc<ObjString>);
+
+impl Symbol {
+    /// The symbol for `name`
+    pub fn new(name: &str) -> Self {
+        Symbol(intern(name))
+    }
+
+    /// The contents of the symbol
+    pub fn as_str(&self) -> &str {
+        &self.0.string
+    }
+}
+
+/// Converts an interned string to a Symbol. Strings created through
+/// `intern` (including every `Value::String`) are always interned.
+impl From<Rc<ObjString>> for Symbol {
+    fn from(string: Rc<ObjString>) -> Self {
+        Symbol(string)
+    }
+}
+
+impl From<&Rc<ObjString>> for Symbol {
+    fn from(string: &Rc<ObjString>) -> Self {
+        Symbol(string.clone())
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (Rc::as_ptr(&self.0) as usize).hash(state)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+/// The mapping between global variable names and the slots that hold their values.
+///
+/// Each VM has its own table, which code must be compiled against before the VM
+/// can run it. A slot is never released once assigned, so that globals persist
+/// between REPL lines and imported modules.
+#[derive(Debug, Default)]
+pub struct GlobalSlots {
+    slots: HashMap<Symbol, usize>,
+    names: Vec<Symbol>,
+}
+
+impl GlobalSlots {
+    /// The slot holding the global variable `name`, assigning a new slot if
+    /// `name` has not been seen before
+    pub fn slot(&mut self, name: &str) -> usize {
+        let symbol = Symbol::new(name);
+        if let Some(slot) = self.slots.get(&symbol) {
+            return *slot;
+        }
+        let slot = self.names.len();
+        self.names.push(symbol.clone());
+        self.slots.insert(symbol, slot);
+        slot
+    }
+
+    /// The name of the global variable held in `slot`, if the slot has been assigned
+    pub fn name(&self, slot: usize) -> Option<&Symbol> {
+        self.names.get(slot)
+    }
+
+    /// The number of slots assigned
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Indicates whether no slots have been assigned
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
@@ -15,13 +15,37 @@ pub enum Kind {
     RightBrace,
     LeftParen,
     RightParen,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
+    MinusMinus,
+    MinusEqual,
     Plus,
+    PlusPlus,
+    PlusEqual,
     Slash,
+    SlashEqual,
     Star,
+    StarEqual,
+    StarStar,
+    StarStarEqual,
+    Percent,
+    PercentEqual,
+    Ampersand,
+    AmpersandEqual,
+    Pipe,
+    PipeEqual,
+    Caret,
+    CaretEqual,
+    Tilde,
     Semicolon,
+    Colon,
+    Arrow,
+    Question,
+    QuestionQuestion,
+    QuestionDot,
 
     Bang,
     BangEqual,
@@ -31,9 +55,14 @@ pub enum Kind {
     GreaterEqual,
     Less,
     LessEqual,
+    GreaterGreater,
+    GreaterGreaterEqual,
+    LessLess,
+    LessLessEqual,
 
     IdentifierLiteral(String),
     StringLiteral(String),
+    Interpolation(String),
     NumberLiteral(f64),
 
     And,
@@ -52,6 +81,17 @@ pub enum Kind {
     Return,
     Super,
     This,
+    Throw,
+    Try,
+    Catch,
+    Finally,
+    Import,
+    As,
+    Break,
+    Continue,
+    Switch,
+    Case,
+    Default,
 
     Error { message: String, source: String },
 }
@@ -63,13 +103,37 @@ impl fmt::Display for Kind {
             Kind::RightBrace => write!(f, "}}"),
             Kind::LeftParen => write!(f, "("),
             Kind::RightParen => write!(f, ")"),
+            Kind::LeftBracket => write!(f, "["),
+            Kind::RightBracket => write!(f, "]"),
             Kind::Comma => write!(f, ","),
             Kind::Dot => write!(f, "."),
             Kind::Minus => write!(f, "-"),
+            Kind::MinusMinus => write!(f, "--"),
+            Kind::MinusEqual => write!(f, "-="),
             Kind::Plus => write!(f, "+"),
+            Kind::PlusPlus => write!(f, "++"),
+            Kind::PlusEqual => write!(f, "+="),
             Kind::Slash => write!(f, "/"),
+            Kind::SlashEqual => write!(f, "/="),
             Kind::Star => write!(f, "*"),
+            Kind::StarEqual => write!(f, "*="),
+            Kind::StarStar => write!(f, "**"),
+            Kind::StarStarEqual => write!(f, "**="),
+            Kind::Percent => write!(f, "%"),
+            Kind::PercentEqual => write!(f, "%="),
+            Kind::Ampersand => write!(f, "&"),
+            Kind::AmpersandEqual => write!(f, "&="),
+            Kind::Pipe => write!(f, "|"),
+            Kind::PipeEqual => write!(f, "|="),
+            Kind::Caret => write!(f, "^"),
+            Kind::CaretEqual => write!(f, "^="),
+            Kind::Tilde => write!(f, "~"),
             Kind::Semicolon => write!(f, ";"),
+            Kind::Colon => write!(f, ":"),
+            Kind::Arrow => write!(f, "=>"),
+            Kind::Question => write!(f, "?"),
+            Kind::QuestionQuestion => write!(f, "??"),
+            Kind::QuestionDot => write!(f, "?."),
             Kind::Bang => write!(f, "!"),
             Kind::BangEqual => write!(f, "!="),
             Kind::Equal => write!(f, "="),
@@ -78,8 +142,13 @@ impl fmt::Display for Kind {
             Kind::GreaterEqual => write!(f, ">="),
             Kind::Less => write!(f, "<"),
             Kind::LessEqual => write!(f, "<="),
+            Kind::GreaterGreater => write!(f, ">>"),
+            Kind::GreaterGreaterEqual => write!(f, ">>="),
+            Kind::LessLess => write!(f, "<<"),
+            Kind::LessLessEqual => write!(f, "<<="),
             Kind::IdentifierLiteral(id) => write!(f, "{}", id),
             Kind::StringLiteral(s) => write!(f, "{}", s),
+            Kind::Interpolation(s) => write!(f, "{}${{", s),
             Kind::NumberLiteral(n) => write!(f, "{}", n),
             Kind::And => write!(f, "and"),
             Kind::Or => write!(f, "or"),
@@ -97,6 +166,17 @@ impl fmt::Display for Kind {
             Kind::Return => write!(f, "return"),
             Kind::Super => write!(f, "super"),
             Kind::This => write!(f, "this"),
+            Kind::Throw => write!(f, "throw"),
+            Kind::Try => write!(f, "try"),
+            Kind::Catch => write!(f, "catch"),
+            Kind::Finally => write!(f, "finally"),
+            Kind::Import => write!(f, "import"),
+            Kind::As => write!(f, "as"),
+            Kind::Break => write!(f, "break"),
+            Kind::Continue => write!(f, "continue"),
+            Kind::Switch => write!(f, "switch"),
+            Kind::Case => write!(f, "case"),
+            Kind::Default => write!(f, "default"),
             Kind::Error { message, .. } => write!(f, "{}", message),
         }
     }
@@ -116,7 +196,7 @@ impl Span {
     }
     /// Create and return a new span that minimally covers all of the spans in `spans`
     pub fn merge(spans: Vec<&Span>) -> Self {
-        let mut start = usize::max_value();
+        let mut start = usize::MAX;
         let mut end = 0;
         for span in spans {
             start = cmp::min(start, span.start);
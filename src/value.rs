@@ -1,4 +1,8 @@
-use crate::object::{ObjBoundMethod, ObjClass, ObjClosure, ObjFunction, ObjInstance, ObjString};
+use crate::object::{
+    ObjBoundMethod, ObjClass, ObjClosure, ObjFunction, ObjInstance, ObjList, ObjMap, ObjModule,
+    ObjNative, ObjNativeMethod, ObjString,
+};
+use crate::symbol;
 use std::cmp::Ordering;
 use std::fmt;
 use std::ops;
@@ -17,6 +21,11 @@ pub enum Value {
     Class(Rc<ObjClass>),
     Instance(Rc<ObjInstance>),
     BoundMethod(Rc<ObjBoundMethod>),
+    Native(Rc<ObjNative>),
+    NativeMethod(Rc<ObjNativeMethod>),
+    List(Rc<ObjList>),
+    Map(Rc<ObjMap>),
+    Module(Rc<ObjModule>),
 }
 
 impl fmt::Debug for Value {
@@ -31,6 +40,11 @@ impl fmt::Debug for Value {
             Value::Class(c) => write!(f, "{:?}", c),
             Value::Instance(i) => write!(f, "{:?}", i),
             Value::BoundMethod(m) => write!(f, "{:?}", m),
+            Value::Native(n) => write!(f, "{:?}", n),
+            Value::NativeMethod(m) => write!(f, "{:?}", m),
+            Value::List(l) => write!(f, "{:?}", l),
+            Value::Map(m) => write!(f, "{:?}", m),
+            Value::Module(m) => write!(f, "{:?}", m),
         }
     }
 }
@@ -47,6 +61,11 @@ impl fmt::Display for Value {
             Value::Class(c) => write!(f, "{}", c),
             Value::Instance(i) => write!(f, "{}", i),
             Value::BoundMethod(m) => write!(f, "{}", m),
+            Value::Native(n) => write!(f, "{}", n),
+            Value::NativeMethod(m) => write!(f, "{}", m),
+            Value::List(l) => write!(f, "{}", l),
+            Value::Map(m) => write!(f, "{}", m),
+            Value::Module(m) => write!(f, "{}", m),
         }
     }
 }
@@ -54,38 +73,22 @@ impl fmt::Display for Value {
 impl Value {
     /// Indicates whether the Value is a `Number` variant
     pub fn is_number(&self) -> bool {
-        if let Value::Number(..) = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Value::Number(..))
     }
 
     /// Indicates whether the Value is a `Bool` variant
     pub fn is_bool(&self) -> bool {
-        if let Value::Bool(..) = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Value::Bool(..))
     }
 
     /// Indicates whether the Value is a `Nil` variant
     pub fn is_nil(&self) -> bool {
-        if let Value::Nil = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Value::Nil)
     }
 
     /// Indicates whether the Value is a `String` variant
     pub fn is_string(&self) -> bool {
-        if let Value::String(_) = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Value::String(_))
     }
 
     /// Indicates whether the Value is 'Truthy' according to the rules of the language
@@ -93,7 +96,7 @@ impl Value {
         match self {
             Value::Bool(b) => *b,
             Value::Nil => false,
-            Value::Number(n) => (n - 0f64).abs() > std::f64::EPSILON,
+            Value::Number(n) => (n - 0f64).abs() > f64::EPSILON,
             Value::String(s) => !s.string.is_empty(),
             _ => true,
         }
@@ -174,6 +177,23 @@ impl ops::Div for Value {
     }
 }
 
+/// Overloads the `%` operator for Values. Only `Number` variants have a remainder.
+impl ops::Rem for Value {
+    type Output = Value;
+
+    fn rem(self, rhs: Value) -> Value {
+        if let Value::Number(left) = self {
+            if let Value::Number(right) = rhs {
+                Value::Number(left % right)
+            } else {
+                panic!("Attempted to take the remainder {:?} % {:?}", self, rhs);
+            }
+        } else {
+            panic!("Attempted to take the remainder {:?} % {:?}", self, rhs);
+        }
+    }
+}
+
 /// Overloads the unary `-` operator for Values. Only `Number` variants can be negated.
 impl ops::Neg for Value {
     type Output = Value;
@@ -188,39 +208,28 @@ impl ops::Neg for Value {
 }
 
 /// Overloads the `==` operator for Values.
+///
+/// Values of different types are never equal. Numbers and booleans compare by
+/// value and strings by contents; every other object compares by identity.
 impl PartialEq for Value {
     fn eq(&self, other: &Value) -> bool {
-        match self {
-            Value::Bool(b) => other.is_truthy() == *b,
-            Value::Number(n) => match other {
-                Value::Number(o) => n == o,
-                _ => false,
-            },
-            Value::Nil => other.is_nil(),
-            Value::Function(l) => match other {
-                Value::Function(r) => l == r,
-                _ => false,
-            },
-            Value::Closure(l) => match other {
-                Value::Closure(r) => l == r,
-                _ => false,
-            },
-            Value::String(l) => match other {
-                Value::String(r) => l.string == r.string,
-                _ => false,
-            },
-            Value::Class(l) => match other {
-                Value::Class(r) => l == r,
-                _ => false,
-            },
-            Value::Instance(l) => match other {
-                Value::Instance(r) => l == r,
-                _ => false,
-            },
-            Value::BoundMethod(l) => match other {
-                Value::BoundMethod(r) => l == r,
-                _ => false,
-            },
+        match (self, other) {
+            (Value::Number(l), Value::Number(r)) => l == r,
+            (Value::Bool(l), Value::Bool(r)) => l == r,
+            (Value::Nil, Value::Nil) => true,
+            // Strings are interned, so equal contents means the same ObjString
+            (Value::String(l), Value::String(r)) => Rc::ptr_eq(l, r),
+            (Value::Function(l), Value::Function(r)) => Rc::ptr_eq(l, r),
+            (Value::Closure(l), Value::Closure(r)) => Rc::ptr_eq(l, r),
+            (Value::Class(l), Value::Class(r)) => Rc::ptr_eq(l, r),
+            (Value::Instance(l), Value::Instance(r)) => Rc::ptr_eq(l, r),
+            (Value::BoundMethod(l), Value::BoundMethod(r)) => Rc::ptr_eq(l, r),
+            (Value::Native(l), Value::Native(r)) => Rc::ptr_eq(l, r),
+            (Value::NativeMethod(l), Value::NativeMethod(r)) => Rc::ptr_eq(l, r),
+            (Value::List(l), Value::List(r)) => Rc::ptr_eq(l, r),
+            (Value::Map(l), Value::Map(r)) => Rc::ptr_eq(l, r),
+            (Value::Module(l), Value::Module(r)) => Rc::ptr_eq(l, r),
+            _ => false,
         }
     }
 }
@@ -260,13 +269,13 @@ impl From<bool> for Value {
 
 impl From<String> for Value {
     fn from(string: String) -> Self {
-        Value::String(Rc::new(ObjString::from(string)))
+        Value::String(symbol::intern(&string))
     }
 }
 
 impl From<&str> for Value {
     fn from(string: &str) -> Self {
-        Value::String(Rc::new(ObjString::from(string)))
+        Value::String(symbol::intern(string))
     }
 }
 
@@ -276,6 +285,12 @@ impl From<ObjFunction> for Value {
     }
 }
 
+impl From<ObjNative> for Value {
+    fn from(native: ObjNative) -> Self {
+        Value::Native(Rc::new(native))
+    }
+}
+
 impl From<ObjClosure> for Value {
     fn from(closure: ObjClosure) -> Self {
         Value::Closure(Rc::new(closure))
@@ -293,3 +308,21 @@ impl From<ObjInstance> for Value {
         Value::Instance(Rc::new(instance))
     }
 }
+
+impl From<ObjList> for Value {
+    fn from(list: ObjList) -> Self {
+        Value::List(Rc::new(list))
+    }
+}
+
+impl From<ObjMap> for Value {
+    fn from(map: ObjMap) -> Self {
+        Value::Map(Rc::new(map))
+    }
+}
+
+impl From<ObjModule> for Value {
+    fn from(module: ObjModule) -> Self {
+        Value::Module(Rc::new(module))
+    }
+}
@@ -1,70 +1,442 @@
-use crate::error::RuntimeError;
+use crate::error::{RuntimeError, TraceFrame};
 use crate::executable::Executable;
-use crate::object::{ObjBoundMethod, ObjClass, ObjClosure, ObjFunction, ObjInstance, ObjUpvalue};
+use crate::gc::{GcStats, Heap, Marker};
+use crate::module;
+use crate::native;
+use crate::object::{
+    MapEntries, MapKey, NativeFn, ObjBoundMethod, ObjClass, ObjClosure, ObjFunction, ObjInstance,
+    ObjList, ObjMap, ObjModule, ObjNative, ObjNativeMethod, ObjString, ObjUpvalue,
+};
 use crate::opcode::OpCode;
+use crate::symbol::{GlobalSlots, Symbol};
 use crate::token::Span;
 use crate::value::Value;
 
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fs;
 use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
-#[derive(Debug, Default)]
-pub struct VM {
-    /// The index of the next byte to be read from the executable
+/// The default limit on the number of nested calls
+pub const DEFAULT_MAX_FRAMES: usize = 1024;
+
+/// A single invocation of a closure that has not yet returned
+#[derive(Debug)]
+struct CallFrame {
+    /// The closure being executed
+    closure: Rc<ObjClosure>,
+
+    /// The index of the next byte to be read from the closure's executable
     ip: usize,
 
-    /// The index in `stack` that is the bottom of the current frame
+    /// The index in `stack` that is the bottom of the frame
     base: usize,
 
+    /// Whether the frame belongs to an initializer, which always returns the new instance
+    is_initializer: bool,
+
+    /// The canonical path of the module, if the frame is running the
+    /// top-level code of an imported module
+    module: Option<PathBuf>,
+}
+
+/// An exception handler installed by a try statement
+#[derive(Debug)]
+struct Handler {
+    /// The number of active call frames when the handler was installed
+    frame_count: usize,
+
+    /// The length of the stack when the handler was installed
+    stack_len: usize,
+
+    /// The offset of the code that handles the thrown value
+    catch_ip: usize,
+}
+
+#[derive(Debug)]
+pub struct VM {
+    /// The active call frames. The last frame is the one currently executing.
+    frames: Vec<CallFrame>,
+
+    /// The maximum number of call frames before a stack overflow is reported
+    max_frames: usize,
+
     /// The runtime value stack
     stack: Vec<Value>,
 
-    /// The current global variables
-    globals: HashMap<String, Value>,
+    /// The current global variables, indexed by the slots from `global_slots`.
+    /// Slots of undeclared globals are None.
+    globals: Vec<Option<Value>>,
+
+    /// The slot assigned to each global variable name. Code is compiled against
+    /// this table before it is run.
+    global_slots: GlobalSlots,
+
+    /// The upvalues that still refer to variables on the stack
+    open_upvalues: Vec<Rc<RefCell<ObjUpvalue>>>,
+
+    /// The installed exception handlers. The last handler is the innermost.
+    handlers: Vec<Handler>,
+
+    /// The most recently caught error, kept so that it can be rethrown as it was
+    last_caught: Option<RuntimeError>,
+
+    /// The class of the error objects passed to catch blocks for errors
+    /// raised by the VM
+    error_class: Rc<ObjClass>,
+
+    /// The objects tracked by the garbage collector
+    heap: Heap,
+
+    /// The native methods available on every list, by name
+    list_methods: HashMap<Symbol, Rc<ObjNative>>,
+
+    /// The native methods available on every map, by name
+    map_methods: HashMap<Symbol, Rc<ObjNative>>,
+
+    /// The namespace of every imported module, by canonical path.
+    /// None while the module's top-level code is still running.
+    modules: HashMap<PathBuf, Option<Value>>,
+
+    /// The directory that the main script's imports are relative to.
+    /// Imports in a module are relative to the module's own directory.
+    directory: PathBuf,
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VM {
-    /// Create a new, empty VM
+    /// Create a new VM with only the standard native functions defined
     pub fn new() -> Self {
-        VM {
-            ip: 0,
-            base: 0,
+        let mut vm = VM {
+            frames: Vec::new(),
+            max_frames: DEFAULT_MAX_FRAMES,
             stack: Vec::new(),
-            globals: HashMap::new(),
-        }
+            globals: Vec::new(),
+            global_slots: GlobalSlots::default(),
+            open_upvalues: Vec::new(),
+            handlers: Vec::new(),
+            last_caught: None,
+            error_class: Rc::new(ObjClass {
+                name: Box::new(ObjString::from("Error")),
+                methods: RefCell::new(HashMap::new()),
+                class_methods: RefCell::new(HashMap::new()),
+                getters: RefCell::new(HashMap::new()),
+            }),
+            heap: Heap::new(),
+            list_methods: HashMap::new(),
+            map_methods: HashMap::new(),
+            modules: HashMap::new(),
+            directory: PathBuf::new(),
+        };
+        native::define_standard_library(&mut vm);
+        vm
+    }
+
+    /// Define a global variable `name` bound to a host function which
+    /// accepts exactly `arity` arguments.
+    ///
+    /// A `RuntimeError` returned by `function` is reported at the call site.
+    pub fn define_native(&mut self, name: &str, arity: u8, function: NativeFn) {
+        let native = ObjNative {
+            arity,
+            name: Box::new(ObjString::from(name)),
+            function,
+        };
+        let slot = self.global_slots.slot(name);
+        self.set_global_slot(slot, Value::from(native));
+    }
+
+    /// Define a method `name` available on every list, implemented by a host
+    /// function which accepts exactly `arity` arguments. The list is passed to
+    /// `function` before the arguments and is not counted in `arity`.
+    pub fn define_list_method(&mut self, name: &str, arity: u8, function: NativeFn) {
+        let native = ObjNative {
+            arity,
+            name: Box::new(ObjString::from(name)),
+            function,
+        };
+        self.list_methods.insert(Symbol::new(name), Rc::new(native));
+    }
+
+    /// Define a method `name` available on every map, implemented by a host
+    /// function which accepts exactly `arity` arguments. The map is passed to
+    /// `function` before the arguments and is not counted in `arity`.
+    pub fn define_map_method(&mut self, name: &str, arity: u8, function: NativeFn) {
+        let native = ObjNative {
+            arity,
+            name: Box::new(ObjString::from(name)),
+            function,
+        };
+        self.map_methods.insert(Symbol::new(name), Rc::new(native));
+    }
+
+    /// Create a new list containing `items`, tracked by the garbage collector
+    pub fn allocate_list(&mut self, items: Vec<Value>) -> Value {
+        let list = Rc::new(ObjList::from(items));
+        self.heap.track_list(&list);
+        Value::List(list)
+    }
+
+    /// Create a new map containing `entries`, tracked by the garbage collector
+    pub fn allocate_map(&mut self, entries: MapEntries) -> Value {
+        let map = Rc::new(ObjMap::from(entries));
+        self.heap.track_map(&map);
+        Value::Map(map)
     }
 
     /// Reset the VM's state, keeping the global variables
     pub fn reset(&mut self) {
-        self.ip = 0;
-        self.base = 0;
+        self.frames = Vec::new();
         self.stack = Vec::new();
+        self.open_upvalues = Vec::new();
+        self.handlers = Vec::new();
+        self.last_caught = None;
+    }
+
+    /// The slots assigned to global variable names, which scripts must be
+    /// compiled against to run on this VM
+    pub fn global_slots(&self) -> &GlobalSlots {
+        &self.global_slots
+    }
+
+    /// The slots assigned to global variable names, mutably, for compiling
+    /// or loading a script to run on this VM
+    pub fn global_slots_mut(&mut self) -> &mut GlobalSlots {
+        &mut self.global_slots
+    }
+
+    /// Set the path of the file that the main script was read from, so that
+    /// its imports are found relative to the file's directory rather than
+    /// the working directory.
+    pub fn set_script_path(&mut self, path: &Path) {
+        self.directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    }
+
+    /// Set the maximum depth of nested calls. Calls beyond this depth
+    /// produce a "Stack overflow" `RuntimeError`.
+    pub fn set_max_frames(&mut self, max_frames: usize) {
+        self.max_frames = max_frames;
+    }
+
+    /// Free every object that is part of a reference cycle and is no longer
+    /// reachable from the stack, the globals, or the active call frames.
+    ///
+    /// Values held by the host outside of the VM are not considered reachable.
+    pub fn collect_garbage(&mut self) {
+        let mut marker = Marker::new();
+        for value in self.stack.iter() {
+            marker.mark_value(value);
+        }
+        for value in self.globals.iter().flatten() {
+            marker.mark_value(value);
+        }
+        for frame in self.frames.iter() {
+            marker.mark_closure(&frame.closure);
+        }
+        for upvalue in self.open_upvalues.iter() {
+            marker.mark_upvalue(upvalue);
+        }
+        self.heap.collect(&marker);
     }
 
+    /// Statistics about the garbage collections run so far
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.stats()
+    }
+
+    /// Run the given script closure to completion, writing any printed output to `output_stream`.
+    /// The script must have been compiled or loaded against this VM's `global_slots`.
     pub fn execute<W: Write>(
         &mut self,
         closure: &ObjClosure,
         output_stream: &mut W,
     ) -> Result<(), RuntimeError> {
-        while self.ip < closure.function.bin.len() {
-            let op = closure.function.bin[self.ip];
-            self.ip += 1;
+        let script = Rc::new(ObjClosure {
+            function: closure.function.clone(),
+            upvalues: closure.upvalues.clone(),
+        });
+        self.frames.push(CallFrame {
+            closure: script,
+            ip: 0,
+            base: self.stack.len(),
+            is_initializer: false,
+            module: None,
+        });
+
+        let result = self.run(output_stream);
+        if let Err(mut error) = result {
+            // A rethrown error keeps the trace from where it was first thrown
+            if error.trace.is_empty() {
+                error.trace = self.stack_trace();
+            }
+            self.abandon_imports(0);
+            self.frames.clear();
+            self.handlers.clear();
+            return Err(error);
+        }
+        result
+    }
+
+    /// Describe the active call frames, innermost first
+    fn stack_trace(&self) -> Vec<TraceFrame> {
+        self.frames
+            .iter()
+            .rev()
+            .map(|frame| TraceFrame {
+                function: frame.closure.function.name.string.clone(),
+                span: frame
+                    .closure
+                    .function
+                    .bin
+                    .span_at(frame.ip.saturating_sub(1)),
+                source: frame.closure.function.bin.source.clone(),
+            })
+            .collect()
+    }
+
+    /// Run until the outermost frame returns, passing any errors to the
+    /// innermost exception handler. Errors without a handler are returned.
+    fn run<W: Write>(&mut self, output_stream: &mut W) -> Result<(), RuntimeError> {
+        loop {
+            match self.dispatch(output_stream) {
+                Err(error) if !self.handlers.is_empty() => self.catch(error),
+                result => return result,
+            }
+        }
+    }
+
+    /// Unwind to the innermost exception handler and continue at its catch
+    /// code with the thrown value on the top of the stack
+    fn catch(&mut self, mut error: RuntimeError) {
+        if error.trace.is_empty() {
+            error.trace = self.stack_trace();
+        }
+
+        let handler = self.handlers.pop().unwrap();
+        self.abandon_imports(handler.frame_count);
+        self.frames.truncate(handler.frame_count);
+        self.close_upvalues(handler.stack_len);
+        self.stack.truncate(handler.stack_len);
+
+        let value = match &error.value {
+            Some(value) => value.clone(),
+            None => self.error_object(error.message.clone(), error.span),
+        };
+        self.last_caught = Some(RuntimeError {
+            value: Some(value.clone()),
+            ..error
+        });
+        self.push(value);
+        self.frame_mut().ip = handler.catch_ip;
+        self.collect_garbage_if_needed();
+    }
+
+    /// Forget the modules being imported by the frames above `frame_count`,
+    /// which are being unwound, so that importing them again starts over
+    fn abandon_imports(&mut self, frame_count: usize) {
+        for frame in self.frames[frame_count..].iter() {
+            if let Some(path) = &frame.module {
+                self.modules.remove(path);
+            }
+        }
+    }
+
+    /// Create the error object passed to a catch block for an error raised by the VM.
+    /// It has the fields `message`, `start` and `end`.
+    fn error_object(&mut self, message: String, span: Span) -> Value {
+        let instance = Rc::new(ObjInstance::from(&self.error_class));
+        {
+            let mut fields = instance.fields.borrow_mut();
+            fields.insert(Symbol::new("message"), Value::from(message));
+            fields.insert(Symbol::new("start"), Value::from(span.start as f64));
+            fields.insert(Symbol::new("end"), Value::from(span.end as f64));
+        }
+        self.heap.track_instance(&instance);
+        Value::Instance(instance)
+    }
+
+    /// The error for throwing `value` at `span`. Throwing an error object
+    /// again reports its original message and span if it is never caught.
+    fn throw(&self, value: Value, span: Span) -> RuntimeError {
+        if let Value::Instance(instance) = &value {
+            if Rc::ptr_eq(&instance.class, &self.error_class) {
+                let fields = instance.fields.borrow();
+                let field = |name| fields.get(&Symbol::new(name));
+                if let (
+                    Some(Value::String(message)),
+                    Some(Value::Number(start)),
+                    Some(Value::Number(end)),
+                ) = (field("message"), field("start"), field("end"))
+                {
+                    let mut error = RuntimeError::new(
+                        message.string.clone(),
+                        Span::new(*start as usize, *end as usize),
+                    );
+                    error.value = Some(value.clone());
+                    return error;
+                }
+            }
+        }
+        RuntimeError::thrown(value, span)
+    }
+
+    /// Executes instructions from the current frame until the outermost frame
+    /// returns or runs out of instructions.
+    fn dispatch<W: Write>(&mut self, output_stream: &mut W) -> Result<(), RuntimeError> {
+        let mut ip = self.frame().ip;
+        let result = self.dispatch_from(&mut ip, output_stream);
+
+        // The stack trace and exception handlers need to know where the error happened
+        if result.is_err() {
+            self.frame_mut().ip = ip;
+        }
+        result
+    }
+
+    /// The dispatch loop. `ip` is the instruction pointer of the current frame. It is
+    /// stored back to the frame before a call, and by `dispatch` if an error is returned.
+    fn dispatch_from<W: Write>(
+        &mut self,
+        ip: &mut usize,
+        output_stream: &mut W,
+    ) -> Result<(), RuntimeError> {
+        let mut closure = self.frame().closure.clone();
+        let mut base = self.frame().base;
+        let mut depth = self.frames.len();
+        loop {
+            if *ip >= closure.function.bin.len() {
+                self.frames.pop();
+                return Ok(());
+            }
+            let start = *ip;
+            let (op, next) = closure.function.bin.read_instruction(start);
+            *ip = next;
+
+            // Spans are looked up only when an instruction needs one, mostly to report errors
+            let span = || closure.function.bin.span_at(start);
 
             if cfg!(feature = "disassemble") {
                 writeln!(output_stream, "{:?}", op).unwrap();
             }
             match op {
-                OpCode::Constant(index) => {
+                OpCode::Constant(index) | OpCode::ConstantLong(index) => {
                     self.push(closure.function.bin.get_constant(index).clone());
                 }
                 OpCode::Negate => {
                     let argument = self.pop()?;
-                    argument.assert_is_number_or(
-                        "Cannot negate non-numeric types",
-                        closure.function.bin.spans[self.ip - 1],
-                    )?;
+                    if !argument.is_number() {
+                        return Err(RuntimeError::new(
+                            "Cannot negate non-numeric types".to_string(),
+                            span(),
+                        ));
+                    }
                     self.push(-argument);
                 }
                 OpCode::Pop => {
@@ -74,20 +446,50 @@ impl VM {
                     let argument = self.pop()?;
                     self.push(Value::from(!argument.is_truthy()));
                 }
+                OpCode::BitNot => {
+                    let argument = self
+                        .pop()?
+                        .unwrap_integer_or("Cannot apply '~' to non-integer types", span())?;
+                    self.push(Value::from(!argument as f64));
+                }
                 OpCode::Return => {
-                    self.stack[self.base] = self.peek(0)?.clone();
-                    return Ok(());
+                    let return_value = if self.frame().is_initializer {
+                        self.stack[base].clone()
+                    } else {
+                        self.pop()?
+                    };
+                    let frame = self.frames.pop().unwrap();
+                    self.close_upvalues(frame.base);
+                    if let Some(path) = frame.module {
+                        self.modules.insert(path, Some(return_value.clone()));
+                    }
+
+                    if self.frames.is_empty() {
+                        self.stack.truncate(frame.base);
+                        return Ok(());
+                    }
+
+                    // Replace the callable and everything above it with the return value
+                    self.stack.truncate(frame.base - 1);
+                    self.push(return_value);
                 }
                 OpCode::Add
                 | OpCode::Subtract
                 | OpCode::Multiply
                 | OpCode::Divide
+                | OpCode::Modulo
+                | OpCode::Power
+                | OpCode::BitAnd
+                | OpCode::BitOr
+                | OpCode::BitXor
+                | OpCode::ShiftLeft
+                | OpCode::ShiftRight
                 | OpCode::Less
                 | OpCode::LessEqual
                 | OpCode::Greater
                 | OpCode::GreaterEqual
                 | OpCode::Equal
-                | OpCode::NotEqual => match self.binary_op(&op, &closure.function.bin) {
+                | OpCode::NotEqual => match self.binary_op(&op, &closure.function.bin, start) {
                     Ok(()) => {}
                     Err(e) => return Err(e),
                 },
@@ -96,55 +498,71 @@ impl VM {
                     output_stream.flush().unwrap();
                 }
                 OpCode::GetGlobal(name_index) => {
-                    self.get_global(name_index, &*closure.function)?;
+                    self.get_global(name_index, &closure.function, start)?;
                 }
                 OpCode::SetGlobal(name_index) => {
-                    self.set_global(name_index, &*closure.function)?;
+                    self.set_global(name_index, &closure.function, start)?;
                 }
                 OpCode::DeclareGlobal(name_index) => {
-                    self.declare_global(name_index, &*closure.function)?;
-                }
-                OpCode::GetLocal(index) => {
-                    self.push(self.stack[self.base + index].clone());
+                    self.declare_global(name_index);
                 }
+                OpCode::GetLocal(index) => match self.stack.get(base + index) {
+                    Some(value) => self.push(value.clone()),
+                    None => return Err(Self::invalid_local(index, span())),
+                },
                 OpCode::SetLocal(index) => {
-                    let stack_len = self.stack.len();
-                    self.stack[stack_len - 2 - index] = self.peek(0)?.clone();
+                    let value = self.peek(0)?.clone();
+                    match self.stack.get_mut(base + index) {
+                        Some(local) => *local = value,
+                        None => return Err(Self::invalid_local(index, span())),
+                    }
                 }
                 OpCode::Jump(destination) => {
-                    self.ip = destination as usize;
+                    *ip = destination;
                 }
                 OpCode::JumpIfTrue(destination) => {
                     if self.peek(0)?.is_truthy() {
-                        self.ip = destination as usize;
+                        *ip = destination;
                     }
                 }
                 OpCode::JumpIfFalse(destination) => {
                     if !self.peek(0)?.is_truthy() {
-                        self.ip = destination as usize;
+                        *ip = destination;
                     }
                 }
                 OpCode::Invoke(arg_count) => {
                     let callable = self.peek(arg_count + 1)?.clone();
 
+                    self.frame_mut().ip = *ip;
                     match callable {
                         Value::Closure(closure) => {
-                            self.call(&*closure, arg_count, output_stream)?;
+                            self.call(closure, arg_count, false)?;
                         }
                         Value::BoundMethod(method) => {
                             let stack_len = self.stack.len();
-                            self.stack[stack_len - (arg_count + 1) as usize] =
+                            self.stack[stack_len - (arg_count + 1)] =
                                 Value::Instance(method.receiver.clone());
-                            self.call(&*method.method, arg_count, output_stream)?;
+                            self.call(method.method.clone(), arg_count, false)?;
                         }
                         Value::Class(class) => {
-                            self.instantiate(&class, arg_count, output_stream)?;
+                            self.instantiate(&class, arg_count)?;
+                            self.collect_garbage_if_needed();
+                        }
+                        Value::Native(native) => {
+                            self.call_native(&native, arg_count, false)?;
+                            self.collect_garbage_if_needed();
+                        }
+                        Value::NativeMethod(method) => {
+                            let stack_len = self.stack.len();
+                            self.stack[stack_len - (arg_count + 1)] = method.receiver.clone();
+                            self.call_native(&method.method, arg_count, true)?;
+                            self.collect_garbage_if_needed();
                         }
                         _ => {
-                            return Err(RuntimeError {
-                                message: format!("Cannot invoke {}", callable),
-                                span: closure.function.bin.spans[self.ip - 1],
-                            });
+                            return Err(RuntimeError::new(
+                                format!("Cannot invoke {}", callable),
+                                span(),
+                            ));
                         }
                     }
                 }
@@ -154,137 +572,200 @@ impl VM {
                     let function = if let Value::Function(f) = arg_value {
                         f.clone()
                     } else {
-                        return Err(RuntimeError {
-                            message: format!("Closure instruction expected function constant argument, but got {}", arg_value),
-                            span: closure.function.bin.spans[self.ip - 1]
-                        });
+                        return Err(RuntimeError::new(format!("Closure instruction expected function constant argument, but got {}", arg_value), span()));
                     };
 
-                    let upvalues = RefCell::new(
-                        function
-                            .upvalues
-                            .iter()
-                            .map(|(is_local, index)| {
-                                if *is_local {
-                                    ObjUpvalue::from(self.stack[self.base + index].clone())
-                                } else {
-                                    ObjUpvalue::from(
-                                        closure
-                                            .upvalues
-                                            .borrow()
-                                            .get(*index)
-                                            .unwrap()
-                                            .value
-                                            .clone(),
-                                    )
+                    let upvalues = function
+                        .upvalues
+                        .iter()
+                        .map(|(is_local, index)| {
+                            if *is_local {
+                                // A local function may capture the slot that it is about to be stored in
+                                let slot = base + index;
+                                if slot > self.stack.len() {
+                                    return Err(Self::invalid_local(*index, span()));
                                 }
-                            })
-                            .collect(),
-                    );
+                                Ok(self.capture_upvalue(slot))
+                            } else {
+                                Ok(closure.upvalues[*index].clone())
+                            }
+                        })
+                        .collect::<Result<_, RuntimeError>>()?;
 
                     let closure = ObjClosure { function, upvalues };
                     let closure_value = Value::from(closure);
                     self.push(closure_value);
+                    self.collect_garbage_if_needed();
                 }
                 OpCode::GetUpvalue(index) => {
-                    self.push(closure.upvalues.borrow().get(index).unwrap().value.clone());
+                    let value = match &*closure.upvalues[index].borrow() {
+                        ObjUpvalue::Open(slot) => self.stack[*slot].clone(),
+                        ObjUpvalue::Closed(value) => value.clone(),
+                    };
+                    self.push(value);
                 }
                 OpCode::ReadField(name_index) => {
                     let name_constant = closure.function.bin.get_constant(name_index);
 
                     let name = if let Value::String(s) = name_constant {
-                        &s.string
+                        Symbol::from(s)
                     } else {
-                        return Err(RuntimeError {
-                            message: format!(
+                        return Err(RuntimeError::new(
+                            format!(
                                 "Expected field name ObjString but found {:?}",
                                 name_constant
                             ),
-                            span: closure.function.bin.spans[self.ip - 2],
-                        });
+                            span(),
+                        ));
                     };
 
                     let target_value = self.pop()?;
-                    if let Value::Instance(instance) = target_value {
-                        if let Some(method) = instance.class.methods.borrow().get(name) {
+                    if let Value::List(_) | Value::Map(_) = target_value {
+                        let method = self.native_method(target_value, &name, span())?;
+                        self.push(method);
+                    } else if let Value::Module(module) = target_value {
+                        let slot = Self::member_slot(&module, &name, span())?;
+                        let value = self
+                            .globals
+                            .get(slot)
+                            .cloned()
+                            .flatten()
+                            .unwrap_or(Value::Nil);
+                        self.push(value);
+                    } else if let Value::Class(class) = target_value {
+                        let method = class.class_methods.borrow().get(&name).cloned();
+                        match method {
+                            Some(method) => self.push(Value::Closure(method)),
+                            None => {
+                                return Err(RuntimeError::new(
+                                    format!("{:?} has no class method {}", class, name),
+                                    span(),
+                                ))
+                            }
+                        }
+                    } else if let Value::Instance(instance) = target_value {
+                        let getter = instance.class.getters.borrow().get(&name).cloned();
+                        if let Some(method) = instance.class.methods.borrow().get(&name) {
                             self.push(Value::BoundMethod(Rc::new(ObjBoundMethod {
                                 receiver: instance.clone(),
                                 method: method.clone(),
                             })));
-                        } else if let Some(v) = instance.fields.borrow().get(name) {
+                        } else if let Some(getter) = getter {
+                            // Call the getter with the instance as its receiver
+                            self.push(Value::Closure(getter.clone()));
+                            self.push(Value::Instance(instance.clone()));
+                            self.frame_mut().ip = *ip;
+                            self.call(getter, 0, false)?;
+                        } else if let Some(v) = instance.fields.borrow().get(&name) {
                             self.push(v.clone());
                         } else {
-                            return Err(RuntimeError {
-                                message: format!("{:?} has no field {}", instance, name),
-                                span: closure.function.bin.spans[self.ip - 1],
-                            });
+                            return Err(RuntimeError::new(
+                                format!("{:?} has no field {}", instance, name),
+                                span(),
+                            ));
                         }
                     } else {
-                        return Err(RuntimeError {
-                            message: format!("{:?} is not an instance", target_value),
-                            span: closure.function.bin.spans[self.ip - 1],
-                        });
+                        return Err(RuntimeError::new(
+                            format!("{:?} is not an instance", target_value),
+                            span(),
+                        ));
                     }
                 }
                 OpCode::SetField(name_index) => {
                     let name_constant = closure.function.bin.get_constant(name_index);
 
                     let field_name = if let Value::String(s) = name_constant {
-                        &s.string
+                        Symbol::from(s)
                     } else {
-                        return Err(RuntimeError {
-                            message: format!(
+                        return Err(RuntimeError::new(
+                            format!(
                                 "Expected field name ObjString but found {:?}",
                                 name_constant
                             ),
-                            span: closure.function.bin.spans[self.ip - 2],
-                        });
+                            span(),
+                        ));
                     };
 
                     let rvalue = self.pop()?;
                     let target_value = self.pop()?;
                     if let Value::Instance(instance) = target_value {
+                        if instance.class.getters.borrow().contains_key(&field_name) {
+                            return Err(RuntimeError::new(
+                                format!("Cannot assign to getter {}", field_name),
+                                span(),
+                            ));
+                        }
                         instance
                             .fields
                             .borrow_mut()
-                            .insert(field_name.clone(), rvalue.clone());
+                            .insert(field_name, rvalue.clone());
+                    } else if let Value::Module(module) = target_value {
+                        let slot = Self::member_slot(&module, &field_name, span())?;
+                        self.set_global_slot(slot, rvalue.clone());
                     } else {
-                        return Err(RuntimeError {
-                            message: format!("{:?} is not an instance", target_value),
-                            span: closure.function.bin.spans[self.ip - 1],
-                        });
+                        return Err(RuntimeError::new(
+                            format!("{:?} is not an instance", target_value),
+                            span(),
+                        ));
                     }
                     self.push(rvalue);
                 }
-                OpCode::SetUpvalue(index) => closure
-                    .upvalues
-                    .borrow_mut()
-                    .insert(index, ObjUpvalue::from(self.peek(0)?.clone())),
+                OpCode::SetUpvalue(index) => {
+                    let value = self.peek(0)?.clone();
+                    match &mut *closure.upvalues[index].borrow_mut() {
+                        ObjUpvalue::Open(slot) => self.stack[*slot] = value,
+                        ObjUpvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop()?;
+                }
                 OpCode::Method => {
                     let method_closure = self.pop()?.unwrap_closure_or(
                         "Expected a closure value at the top of the stack",
-                        closure.function.bin.spans[self.ip - 1],
+                        span(),
                     )?;
 
-                    let class = self.peek(0)?.unwrap_class_or(
-                        "Expected a class value at stack[top - 1]",
-                        closure.function.bin.spans[self.ip - 1],
+                    let class = self
+                        .peek(0)?
+                        .unwrap_class_or("Expected a class value at stack[top - 1]", span())?;
+
+                    // A method overrides an inherited getter of the same name
+                    let name = Symbol::new(&method_closure.function.name.string);
+                    class.getters.borrow_mut().remove(&name);
+                    class
+                        .methods
+                        .borrow_mut()
+                        .insert(name, method_closure.clone());
+                }
+                OpCode::ClassMethod | OpCode::Getter => {
+                    let method_closure = self.pop()?.unwrap_closure_or(
+                        "Expected a closure value at the top of the stack",
+                        span(),
                     )?;
 
-                    class.methods.borrow_mut().insert(
-                        method_closure.function.name.string.clone(),
-                        method_closure.clone(),
-                    );
+                    let class = self
+                        .peek(0)?
+                        .unwrap_class_or("Expected a class value at stack[top - 1]", span())?;
+
+                    let name = Symbol::new(&method_closure.function.name.string);
+                    let methods = if op == OpCode::ClassMethod {
+                        &class.class_methods
+                    } else {
+                        // A getter overrides an inherited method of the same name
+                        class.methods.borrow_mut().remove(&name);
+                        &class.getters
+                    };
+                    methods.borrow_mut().insert(name, method_closure.clone());
                 }
                 OpCode::Inherit => {
-                    let superclass = self.peek(1)?.unwrap_class_or(
-                        "Cannot inherit from a non-class value",
-                        closure.function.bin.spans[self.ip - 1],
-                    )?;
-                    let class = self.peek(0)?.unwrap_class_or(
-                        "Cannot inherit into a non-class value",
-                        closure.function.bin.spans[self.ip - 1],
-                    )?;
+                    let superclass = self
+                        .peek(1)?
+                        .unwrap_class_or("Cannot inherit from a non-class value", span())?;
+                    let class = self
+                        .peek(0)?
+                        .unwrap_class_or("Cannot inherit into a non-class value", span())?;
 
                     for (method_name, method) in superclass.methods.borrow().iter() {
                         class
@@ -292,130 +773,438 @@ impl VM {
                             .borrow_mut()
                             .insert(method_name.clone(), method.clone());
                     }
+                    for (method_name, method) in superclass.class_methods.borrow().iter() {
+                        class
+                            .class_methods
+                            .borrow_mut()
+                            .insert(method_name.clone(), method.clone());
+                    }
+                    for (getter_name, getter) in superclass.getters.borrow().iter() {
+                        class
+                            .getters
+                            .borrow_mut()
+                            .insert(getter_name.clone(), getter.clone());
+                    }
                 }
                 OpCode::GetSuper(name_index) => {
                     if let Value::Class(class) = self.pop()? {
                         let method_name = closure.function.bin.get_constant(name_index);
                         let method_name = if let Value::String(string) = method_name {
-                            &string.string
+                            Symbol::from(string)
                         } else {
-                            return Err(RuntimeError {
-                                message: format!(
+                            return Err(RuntimeError::new(
+                                format!(
                                     "Expected string constant argument but got {}",
                                     method_name
                                 ),
-                                span: closure.function.bin.spans[self.ip - 1],
-                            });
+                                span(),
+                            ));
                         };
 
-                        if let Some(method) = class.methods.borrow().get(method_name) {
-                            if let Value::Instance(instance) = self.pop()? {
+                        let instance = if let Value::Instance(instance) = self.pop()? {
+                            instance
+                        } else {
+                            return Err(RuntimeError::new(
+                                "expected receiver instance on the stack".to_string(),
+                                span(),
+                            ));
+                        };
+
+                        let method = class.methods.borrow().get(&method_name).cloned();
+                        let getter = class.getters.borrow().get(&method_name).cloned();
+                        match (method, getter) {
+                            (Some(method), _) => {
                                 self.push(Value::BoundMethod(Rc::new(ObjBoundMethod {
-                                    receiver: instance.clone(),
-                                    method: method.clone(),
+                                    receiver: instance,
+                                    method,
                                 })));
-                            } else {
-                                return Err(RuntimeError {
-                                    message: "expected receiver instance on the stack".to_string(),
-                                    span: closure.function.bin.spans[self.ip - 1],
-                                });
                             }
-                        } else {
-                            return Err(RuntimeError {
-                                message: format!("'super' has no method {}", method_name),
-                                span: closure.function.bin.spans[self.ip - 1],
-                            });
+                            (None, Some(getter)) => {
+                                // Call the superclass getter with the current instance
+                                self.push(Value::Closure(getter.clone()));
+                                self.push(Value::Instance(instance));
+                                self.frame_mut().ip = *ip;
+                                self.call(getter, 0, false)?;
+                            }
+                            (None, None) => {
+                                return Err(RuntimeError::new(
+                                    format!("'super' has no method {}", method_name),
+                                    span(),
+                                ));
+                            }
                         }
                     } else {
-                        return Err(RuntimeError {
-                            message: "'super' is not a class".to_string(),
-                            span: closure.function.bin.spans[self.ip - 1],
-                        });
+                        return Err(RuntimeError::new(
+                            "'super' is not a class".to_string(),
+                            span(),
+                        ));
                     }
                 }
                 OpCode::Bool => {
                     let truthiness = self.pop()?.is_truthy();
                     self.push(truthiness.into())
                 }
+                OpCode::BuildList(length) => {
+                    let items = self.pop_frame_values(base, length, span())?;
+                    let list = self.allocate_list(items);
+                    self.push(list);
+                    self.collect_garbage_if_needed();
+                }
+                OpCode::PushHandler(catch_ip) => {
+                    self.handlers.push(Handler {
+                        frame_count: self.frames.len(),
+                        stack_len: self.stack.len(),
+                        catch_ip,
+                    });
+                }
+                OpCode::PopHandler => {
+                    self.handlers.pop();
+                }
+                OpCode::Stringify => {
+                    let value = self.pop()?;
+                    match value {
+                        Value::String(_) => self.push(value),
+                        _ => self.push(Value::from(value.to_string())),
+                    }
+                }
+                OpCode::Throw => {
+                    let value = self.pop()?;
+                    return Err(self.throw(value, span()));
+                }
+                OpCode::Rethrow => {
+                    let value = self.pop()?;
+                    return match self.last_caught.take() {
+                        Some(error) if error.value.as_ref() == Some(&value) => Err(error),
+                        _ => Err(self.throw(value, span())),
+                    };
+                }
+                OpCode::Import(path_index) => {
+                    let path = match closure.function.bin.get_constant(path_index) {
+                        Value::String(path) => path.string.clone(),
+                        constant => {
+                            return Err(RuntimeError::new(
+                                format!("Expected module path ObjString but found {:?}", constant),
+                                span(),
+                            ))
+                        }
+                    };
+                    let path = self.import_path(&closure.function.bin, &path);
+                    self.frame_mut().ip = *ip;
+                    self.import(&path)?;
+                }
+                OpCode::BuildMap(length) => {
+                    let values = self.pop_frame_values(base, 2 * length, span())?;
+                    let mut entries = MapEntries::default();
+                    for pair in values.chunks(2) {
+                        let key = MapKey::new(pair[0].clone())
+                            .map_err(|message| RuntimeError::new(message, span()))?;
+                        entries.insert(key, pair[1].clone());
+                    }
+                    let map = self.allocate_map(entries);
+                    self.push(map);
+                    self.collect_garbage_if_needed();
+                }
+                OpCode::GetIndex => {
+                    let index = self.pop()?;
+                    let target_value = self.pop()?;
+                    let item = match target_value {
+                        Value::List(list) => {
+                            let items = list.items.borrow();
+                            let index = ObjList::check_index(&index, items.len())
+                                .map_err(|message| RuntimeError::new(message, span()))?;
+                            items[index].clone()
+                        }
+                        Value::Map(map) => {
+                            let key = MapKey::new(index)
+                                .map_err(|message| RuntimeError::new(message, span()))?;
+                            let entries = map.entries.borrow();
+                            entries.get(&key).cloned().ok_or_else(|| {
+                                RuntimeError::new(format!("Map has no key {}", key), span())
+                            })?
+                        }
+                        _ => {
+                            return Err(RuntimeError::new(
+                                format!("Cannot index into {}", target_value),
+                                span(),
+                            ));
+                        }
+                    };
+                    self.push(item);
+                }
+                OpCode::SetIndex => {
+                    let rvalue = self.pop()?;
+                    let index = self.pop()?;
+                    let target_value = self.pop()?;
+                    match target_value {
+                        Value::List(list) => {
+                            let mut items = list.items.borrow_mut();
+                            let index = ObjList::check_index(&index, items.len())
+                                .map_err(|message| RuntimeError::new(message, span()))?;
+                            items[index] = rvalue.clone();
+                        }
+                        Value::Map(map) => {
+                            let key = MapKey::new(index)
+                                .map_err(|message| RuntimeError::new(message, span()))?;
+                            map.entries.borrow_mut().insert(key, rvalue.clone());
+                        }
+                        _ => {
+                            return Err(RuntimeError::new(
+                                format!("Cannot index into {}", target_value),
+                                span(),
+                            ));
+                        }
+                    }
+                    self.push(rvalue);
+                }
+                OpCode::Duplicate(count) => {
+                    self.peek(count - 1)?;
+                    let start = self.stack.len() - count;
+                    self.stack.extend_from_within(start..);
+                }
+                OpCode::Swap => {
+                    self.peek(1)?;
+                    let top = self.stack.len() - 1;
+                    self.stack.swap(top, top - 1);
+                }
             }
             if cfg!(feature = "disassemble") {
                 self.print_stack(output_stream);
-                writeln!(output_stream, " Globals: {:?}", self.globals).unwrap();
+                self.print_globals(output_stream);
                 writeln!(output_stream).unwrap();
             }
-        }
 
-        Ok(())
+            // Continue in the new frame after a call or a return
+            if self.frames.len() != depth {
+                let frame = self.frame();
+                closure = frame.closure.clone();
+                base = frame.base;
+                *ip = frame.ip;
+                depth = self.frames.len();
+            }
+        }
     }
 
-    fn call<W: Write>(
+    /// Push a new frame which executes `closure` with the `arg_count` arguments
+    /// at the top of the stack.
+    ///
+    /// Errors are reported at the current instruction of the calling frame,
+    /// so its instruction pointer must be up to date.
+    fn call(
         &mut self,
-        closure: &ObjClosure,
+        closure: Rc<ObjClosure>,
         arg_count: usize,
-        output_stream: &mut W,
+        is_initializer: bool,
     ) -> Result<(), RuntimeError> {
-        // Save the current IP and base to restore after returning
-        let ip_backup = self.ip;
-        let base_backup = self.base;
+        self.check_arity(closure.function.arity, arg_count)?;
 
-        // The arguments should already be on the stack.
-        // Adjust the base pointer to point at their start
-        self.base = self.stack.len() - (arg_count + 1) as usize;
+        if self.frames.len() >= self.max_frames {
+            return Err(RuntimeError::new(
+                "Stack overflow".to_string(),
+                self.current_span(),
+            ));
+        }
 
-        // Execution should begin at the beginning of the function
-        self.ip = 0;
+        // The arguments should already be on the stack.
+        // The base points at the receiver slot just below them.
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            base: self.stack.len() - (arg_count + 1),
+            is_initializer,
+            module: None,
+        });
 
-        // Run the function
-        self.execute(closure, output_stream)?;
+        Ok(())
+    }
 
-        // Remove everything from the stack except the return value
-        for _ in (self.base + 1)..self.stack.len() {
-            self.pop()?;
+    /// Push the namespace of the module at `path`, first running the module
+    /// if it has not been imported before
+    fn import(&mut self, path: &str) -> Result<(), RuntimeError> {
+        let span = self.current_span();
+        let key = fs::canonicalize(path).map_err(|e| {
+            RuntimeError::new(format!("Cannot import module '{}': {}", path, e), span)
+        })?;
+        match self.modules.get(&key) {
+            Some(Some(namespace)) => {
+                self.push(namespace.clone());
+                return Ok(());
+            }
+            Some(None) => {
+                return Err(RuntimeError::new(
+                    format!("Circular import of module '{}'", path),
+                    span,
+                ))
+            }
+            None => {}
         }
 
-        // Restore the ip and the base
-        self.ip = ip_backup;
-        self.base = base_backup;
+        let script = Rc::new(
+            module::load(path, &mut self.global_slots)
+                .map_err(|message| RuntimeError::new(message, span))?,
+        );
 
+        // Run the module like a call with no arguments. Its namespace is
+        // recorded and left in place of the closure when it returns.
+        self.push(Value::Closure(script.clone()));
+        self.push(Value::Nil);
+        self.call(script, 0, false)?;
+        self.frame_mut().module = Some(key.clone());
+        self.modules.insert(key, None);
         Ok(())
     }
 
-    fn instantiate<W: Write>(
+    /// Resolve the `path` of a module imported by `importer` against the
+    /// directory of the module or script that `importer` belongs to
+    fn import_path(&self, importer: &Executable, path: &str) -> String {
+        let directory = match &importer.source {
+            Some(source) => Path::new(&source.path)
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+            None => self.directory.clone(),
+        };
+        directory.join(path).to_string_lossy().to_string()
+    }
+
+    /// The global slot of the member `name` of `module`
+    fn member_slot(module: &ObjModule, name: &Symbol, span: Span) -> Result<usize, RuntimeError> {
+        module.members.get(name).copied().ok_or_else(|| {
+            RuntimeError::new(
+                format!("Module '{}' has no member {}", module.name, name),
+                span,
+            )
+        })
+    }
+
+    /// Bind the native method `name` to the list or map `receiver`
+    fn native_method(
+        &self,
+        receiver: Value,
+        name: &Symbol,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        let (type_name, methods) = match receiver {
+            Value::Map(_) => ("Map", &self.map_methods),
+            _ => ("List", &self.list_methods),
+        };
+        let method = methods.get(name).cloned().ok_or_else(|| {
+            RuntimeError::new(format!("{} has no method {}", type_name, name), span)
+        })?;
+        Ok(Value::NativeMethod(Rc::new(ObjNativeMethod {
+            receiver,
+            method,
+        })))
+    }
+
+    /// Call a host function with the `arg_count` arguments at the top of the stack.
+    /// If `with_receiver` is set, the receiver slot is passed as the first argument.
+    fn call_native(
         &mut self,
-        class: &Rc<ObjClass>,
+        native: &ObjNative,
         arg_count: usize,
-        output_stream: &mut W,
+        with_receiver: bool,
     ) -> Result<(), RuntimeError> {
+        self.check_arity(native.arity, arg_count)?;
+
+        // Copy the arguments off of the stack so that the native function may use the VM
+        let callable_slot = self.stack.len() - (arg_count + 2);
+        let args_start = if with_receiver {
+            callable_slot + 1
+        } else {
+            callable_slot + 2
+        };
+        let args: Vec<Value> = self.stack[args_start..].to_vec();
+
+        let result = (native.function)(self, &args)
+            .map_err(|e| RuntimeError::new(e.message, self.current_span()))?;
+
+        // Remove the callable, the receiver slot, and the arguments
+        self.stack.truncate(callable_slot);
+        self.push(result);
+
+        Ok(())
+    }
+
+    fn instantiate(&mut self, class: &Rc<ObjClass>, arg_count: usize) -> Result<(), RuntimeError> {
         // Create a new instance
-        let instance = ObjInstance::from(class);
-        let instance_value = Value::from(instance);
+        let instance = Rc::new(ObjInstance::from(class));
+        self.heap.track_instance(&instance);
+        let instance_value = Value::Instance(instance);
 
         // Run the init method if there is one
-        if class.methods.borrow().contains_key("init") {
+        let initializer = class.methods.borrow().get(&Symbol::new("init")).cloned();
+        if let Some(initializer) = initializer {
             // Use the new instance as "this"
             let stack_len = self.stack.len();
-            self.stack[stack_len - (arg_count + 1) as usize] = instance_value.clone();
+            self.stack[stack_len - (arg_count + 1)] = instance_value.clone();
 
-            self.call(
-                &class.methods.borrow_mut().get("init").unwrap(),
-                arg_count,
-                output_stream,
-            )?;
+            // The initializer's frame leaves the instance in place of the class when it returns
+            self.call(initializer, arg_count, true)
+        } else {
+            self.check_arity(0, arg_count)?;
 
-            // Ignore any return value
+            // Replace the class (callable) and the empty receiver slot with the new instance
             self.pop()?;
+            self.pop()?;
+            self.push(instance_value);
+
+            Ok(())
         }
+    }
 
-        // Pop the class (callable)
-        self.pop()?;
+    /// Returns an error if a function taking `arity` parameters was called with `arg_count` arguments
+    fn check_arity(&self, arity: u8, arg_count: usize) -> Result<(), RuntimeError> {
+        if arg_count == arity as usize {
+            Ok(())
+        } else {
+            Err(RuntimeError::new(
+                format!("Expected {} arguments but got {}", arity, arg_count),
+                self.current_span(),
+            ))
+        }
+    }
+
+    /// Returns the open upvalue referring to the stack slot `slot`,
+    /// creating one if no closure has captured the slot yet.
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<ObjUpvalue>> {
+        for upvalue in self.open_upvalues.iter() {
+            if let ObjUpvalue::Open(open_slot) = *upvalue.borrow() {
+                if open_slot == slot {
+                    return upvalue.clone();
+                }
+            }
+        }
 
-        // Leave the new instance on the top of the stack
-        self.push(instance_value);
+        let upvalue = Rc::new(RefCell::new(ObjUpvalue::Open(slot)));
+        self.heap.track_upvalue(&upvalue);
+        self.open_upvalues.push(upvalue.clone());
+        upvalue
+    }
 
-        Ok(())
+    /// Closes every open upvalue that refers to `stack[first_slot]` or above,
+    /// moving the variable's current value into the upvalue.
+    fn close_upvalues(&mut self, first_slot: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let slot = match *upvalue.borrow() {
+                ObjUpvalue::Open(slot) => slot,
+                ObjUpvalue::Closed(_) => return false,
+            };
+            if slot >= first_slot {
+                upvalue.replace(ObjUpvalue::Closed(stack[slot].clone()));
+                false
+            } else {
+                true
+            }
+        });
     }
 
-    fn binary_op(&mut self, op: &OpCode, bin: &Executable) -> Result<(), RuntimeError> {
+    fn binary_op(
+        &mut self,
+        op: &OpCode,
+        bin: &Executable,
+        offset: usize,
+    ) -> Result<(), RuntimeError> {
         let right = self.pop()?;
         let left = self.pop()?;
 
@@ -424,35 +1213,67 @@ impl VM {
             OpCode::Subtract
             | OpCode::Multiply
             | OpCode::Divide
+            | OpCode::Modulo
+            | OpCode::Power
             | OpCode::Less
             | OpCode::LessEqual
             | OpCode::Greater
-            | OpCode::GreaterEqual => {
-                if !left.is_number() || !right.is_number() {
-                    return Err(RuntimeError {
-                        message: format!("Cannot apply '{:?}' to non-numeric types", op),
-                        span: bin.spans[self.ip - 1],
-                    });
-                }
+            | OpCode::GreaterEqual
+                if (!left.is_number() || !right.is_number()) =>
+            {
+                return Err(RuntimeError::new(
+                    format!("Cannot apply '{:?}' to non-numeric types", op),
+                    bin.span_at(offset),
+                ));
             }
             OpCode::Add => {
                 if left.is_number() && !right.is_number() {
-                    return Err(RuntimeError {
-                        message: String::from("Cannot apply '+' to Number and Non-Number"),
-                        span: bin.spans[self.ip - 1],
-                    });
+                    return Err(RuntimeError::new(
+                        String::from("Cannot apply '+' to Number and Non-Number"),
+                        bin.span_at(offset),
+                    ));
                 } else if left.is_string() && !right.is_string() {
-                    return Err(RuntimeError {
-                        message: String::from("Cannot apply '+' to String and Non-String"),
-                        span: bin.spans[self.ip - 1],
-                    });
+                    return Err(RuntimeError::new(
+                        String::from("Cannot apply '+' to String and Non-String"),
+                        bin.span_at(offset),
+                    ));
                 } else if !left.is_number() && !left.is_string() {
-                    return Err(RuntimeError {
-                        message: String::from("Cannot apply '+' to non-numeric or non-string type"),
-                        span: bin.spans[self.ip - 1],
-                    });
+                    return Err(RuntimeError::new(
+                        String::from("Cannot apply '+' to non-numeric or non-string type"),
+                        bin.span_at(offset),
+                    ));
                 }
             }
+            OpCode::BitAnd
+            | OpCode::BitOr
+            | OpCode::BitXor
+            | OpCode::ShiftLeft
+            | OpCode::ShiftRight => {
+                let span = bin.span_at(offset);
+                let message = format!("Cannot apply '{:?}' to non-integer types", op);
+                let left = left.unwrap_integer_or(&message, span)?;
+                let right = right.unwrap_integer_or(&message, span)?;
+                let value = match op {
+                    OpCode::BitAnd => left & right,
+                    OpCode::BitOr => left | right,
+                    OpCode::BitXor => left ^ right,
+                    _ => {
+                        if !(0..i64::BITS as i64).contains(&right) {
+                            return Err(RuntimeError::new(
+                                format!("Cannot shift by {} bits", right),
+                                span,
+                            ));
+                        }
+                        if *op == OpCode::ShiftLeft {
+                            left << right
+                        } else {
+                            left >> right
+                        }
+                    }
+                };
+                self.push(Value::from(value as f64));
+                return Ok(());
+            }
             _ => {}
         }
 
@@ -461,6 +1282,11 @@ impl VM {
             OpCode::Subtract => left - right,
             OpCode::Multiply => left * right,
             OpCode::Divide => left / right,
+            OpCode::Modulo => left % right,
+            OpCode::Power => match (left, right) {
+                (Value::Number(base), Value::Number(exponent)) => Value::from(base.powf(exponent)),
+                _ => unreachable!(),
+            },
             OpCode::Less => Value::Bool(left < right),
             OpCode::LessEqual => Value::Bool(left <= right),
             OpCode::Greater => Value::Bool(left > right),
@@ -468,10 +1294,10 @@ impl VM {
             OpCode::Equal => Value::Bool(left == right),
             OpCode::NotEqual => Value::Bool(left != right),
             _ => {
-                return Err(RuntimeError {
-                    message: format!("Invalid binary operation {:?}", op),
-                    span: bin.spans[self.ip - 1],
-                })
+                return Err(RuntimeError::new(
+                    format!("Invalid binary operation {:?}", op),
+                    bin.span_at(offset),
+                ))
             }
         };
         self.push(value);
@@ -480,77 +1306,83 @@ impl VM {
 
     fn get_global(
         &mut self,
-        name_index: usize,
+        slot: usize,
         function: &ObjFunction,
+        offset: usize,
     ) -> Result<(), RuntimeError> {
-        let name_arg = function.bin.get_constant(name_index);
-        let value = if let Value::String(name) = name_arg {
-            if let Some(value) = self.globals.get(&name.string) {
-                value.clone()
-            } else {
-                return Err(RuntimeError {
-                    message: format!("Attempted to get unknown global {}", name),
-                    span: function.bin.spans[self.ip - 1],
-                });
-            }
+        if let Some(Some(value)) = self.globals.get(slot) {
+            self.push(value.clone());
+            Ok(())
         } else {
-            return Err(RuntimeError {
-                message: format!(
-                    "Attempted to lookup global by non-string name {:?}",
-                    name_arg
-                ),
-                span: function.bin.spans[self.ip - 1],
-            });
-        };
-        self.push(value);
-        Ok(())
+            Err(RuntimeError::new(
+                format!("Attempted to get unknown global {}", self.global_name(slot)),
+                function.bin.span_at(offset),
+            ))
+        }
     }
 
     fn set_global(
         &mut self,
-        name_index: usize,
+        slot: usize,
         function: &ObjFunction,
+        offset: usize,
     ) -> Result<(), RuntimeError> {
-        let name_arg = function.bin.get_constant(name_index);
-        if let Value::String(name) = name_arg {
-            if self.globals.contains_key(&name.string) {
-                self.globals
-                    .insert(name.string.clone(), self.peek(0)?.clone());
-            } else {
-                return Err(RuntimeError {
-                    message: format!("Assigned to set undeclared global {}", name),
-                    span: function.bin.spans[self.ip - 1],
-                });
-            }
+        let value = self.peek(0)?.clone();
+        if let Some(Some(global)) = self.globals.get_mut(slot) {
+            *global = value;
+            Ok(())
         } else {
-            return Err(RuntimeError {
-                message: format!("Attempted to set global by non-string name {:?}", name_arg),
-                span: function.bin.spans[self.ip - 1],
-            });
+            Err(RuntimeError::new(
+                format!(
+                    "Assigned to set undeclared global {}",
+                    self.global_name(slot)
+                ),
+                function.bin.span_at(offset),
+            ))
         }
+    }
 
-        Ok(())
+    fn declare_global(&mut self, slot: usize) {
+        self.set_global_slot(slot, Value::Nil);
     }
 
-    fn declare_global(
-        &mut self,
-        name_index: usize,
-        function: &ObjFunction,
-    ) -> Result<(), RuntimeError> {
-        let name_arg = function.bin.get_constant(name_index);
-        if let Value::String(name) = name_arg {
-            self.globals.insert(name.string.clone(), Value::Nil);
-        } else {
-            return Err(RuntimeError {
-                message: format!(
-                    "Attempted to declare global by non-string name {:?}",
-                    name_arg
-                ),
-                span: function.bin.spans[self.ip - 1],
-            });
+    /// Declare the global in `slot` if necessary and set its value
+    fn set_global_slot(&mut self, slot: usize, value: Value) {
+        if slot >= self.globals.len() {
+            self.globals.resize(slot + 1, None);
         }
+        self.globals[slot] = Some(value);
+    }
 
-        Ok(())
+    /// The name of the global in `slot`, for error messages
+    fn global_name(&self, slot: usize) -> String {
+        self.global_slots
+            .name(slot)
+            .map_or_else(|| format!("#{}", slot), |name| name.to_string())
+    }
+
+    /// The span of the instruction before the current frame's instruction pointer
+    fn current_span(&self) -> Span {
+        let frame = self.frame();
+        frame.closure.function.bin.span_at(frame.ip - 1)
+    }
+
+    /// Run a garbage collection if enough objects have been allocated since the last one.
+    /// Called after instructions that allocate, once the new objects are reachable.
+    fn collect_garbage_if_needed(&mut self) {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+    }
+
+    /// The currently executing call frame
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().unwrap()
+    }
+
+    /// The currently executing call frame, mutably
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().unwrap()
     }
 
     fn push(&mut self, value: Value) {
@@ -560,50 +1392,78 @@ impl VM {
     fn pop(&mut self) -> Result<Value, RuntimeError> {
         match self.stack.pop() {
             Some(v) => Ok(v),
-            None => Err(RuntimeError {
-                message: "Attempted pop() on an empty stack".to_string(),
-                span: Span::new(0, 0),
-            }),
+            None => Err(RuntimeError::new(
+                "Attempted pop() on an empty stack".to_string(),
+                Span::new(0, 0),
+            )),
         }
     }
 
     fn peek(&self, distance: usize) -> Result<&Value, RuntimeError> {
         if self.stack.len() <= distance {
-            Err(RuntimeError {
-                message: format!(
+            Err(RuntimeError::new(
+                format!(
                     "Attempted to peek({}) but stack length is {}.",
                     distance,
                     self.stack.len()
                 ),
-                span: Span::new(0, 0),
-            })
+                Span::new(0, 0),
+            ))
         } else {
             Ok(&self.stack[self.stack.len() - distance - 1])
         }
     }
 
+    /// The error for an instruction that refers to a local slot beyond the top of the stack
+    fn invalid_local(index: usize, span: Span) -> RuntimeError {
+        RuntimeError::new(format!("Invalid local slot {}", index), span)
+    }
+
+    /// Pop the top `count` values, which must belong to the frame starting at `base`
+    fn pop_frame_values(
+        &mut self,
+        base: usize,
+        count: usize,
+        span: Span,
+    ) -> Result<Vec<Value>, RuntimeError> {
+        if self.stack.len() - base < count {
+            return Err(RuntimeError::new(
+                format!("Expected {} values on the stack", count),
+                span,
+            ));
+        }
+        Ok(self.stack.split_off(self.stack.len() - count))
+    }
+
     fn print_stack<W: Write>(&self, output_stream: &mut W) {
         write!(output_stream, " Stack: ").unwrap();
         for (index, value) in self.stack.iter().enumerate() {
-            if index == self.base {
+            if index == self.frame().base {
                 write!(output_stream, "^ ").unwrap();
             }
             write!(output_stream, "[{:?}] ", value).unwrap();
         }
         writeln!(output_stream).unwrap();
     }
+
+    fn print_globals<W: Write>(&self, output_stream: &mut W) {
+        write!(output_stream, " Globals: ").unwrap();
+        for (slot, value) in self.globals.iter().enumerate() {
+            if let Some(value) = value {
+                write!(output_stream, "[{} = {:?}] ", self.global_name(slot), value).unwrap();
+            }
+        }
+        writeln!(output_stream).unwrap();
+    }
 }
 
 impl Value {
-    /// Returns an error with the given message and span if the value is not a Number variant
-    fn assert_is_number_or(&self, message: &str, span: Span) -> Result<(), RuntimeError> {
-        if self.is_number() {
-            Ok(())
-        } else {
-            Err(RuntimeError {
-                message: message.to_string(),
-                span,
-            })
+    /// Unwraps a `Number` variant holding an integer from the `Value` or returns an error with
+    /// the given message and span
+    fn unwrap_integer_or(&self, message: &str, span: Span) -> Result<i64, RuntimeError> {
+        match self {
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => Ok(*n as i64),
+            _ => Err(RuntimeError::new(message.to_string(), span)),
         }
     }
     /// Unwraps a `Closure` variant from the `Value` or returns an error with the given message and span
@@ -611,10 +1471,7 @@ impl Value {
         if let Value::Closure(closure) = self {
             Ok(closure.clone())
         } else {
-            Err(RuntimeError {
-                message: message.to_string(),
-                span,
-            })
+            Err(RuntimeError::new(message.to_string(), span))
         }
     }
     /// Unwraps a `Class` variant from the `Value` or returns an error with the given message and span
@@ -622,10 +1479,7 @@ impl Value {
         if let Value::Class(class) = self {
             Ok(class.clone())
         } else {
-            Err(RuntimeError {
-                message: message.to_string(),
-                span,
-            })
+            Err(RuntimeError::new(message.to_string(), span))
         }
     }
 }
@@ -1,11 +1,58 @@
 use crate::error::ReportableError;
+use crate::module::SourceFile;
 use crate::token::Span;
+use crate::value::Value;
+use std::rc::Rc;
+
+/// A call that was in progress when a `RuntimeError` occurred
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceFrame {
+    /// The name of the function being executed
+    pub function: String,
+
+    /// The location in the function that was executing
+    pub span: Span,
+
+    /// The source of the imported module that the function belongs to.
+    /// None for functions of the main script.
+    pub source: Option<Rc<SourceFile>>,
+}
 
 /// A ReportableError originating at runtime.
 #[derive(Debug)]
 pub struct RuntimeError {
     pub message: String,
     pub span: Span,
+
+    /// The calls in progress when the error occurred, innermost first.
+    /// Empty until the error is caught or escapes the VM.
+    pub trace: Vec<TraceFrame>,
+
+    /// The value thrown by a `throw` statement. None if the error was
+    /// raised by the VM itself.
+    pub value: Option<Value>,
+}
+
+impl RuntimeError {
+    /// Create a new RuntimeError with the given message at `span`, without a trace
+    pub fn new(message: String, span: Span) -> Self {
+        RuntimeError {
+            message,
+            span,
+            trace: vec![],
+            value: None,
+        }
+    }
+
+    /// Create a new RuntimeError for `value` thrown at `span`, without a trace
+    pub fn thrown(value: Value, span: Span) -> Self {
+        RuntimeError {
+            message: format!("Uncaught exception: {}", value),
+            span,
+            trace: vec![],
+            value: Some(value),
+        }
+    }
 }
 
 impl ReportableError for RuntimeError {
@@ -15,4 +62,7 @@ impl ReportableError for RuntimeError {
     fn message(&self) -> String {
         format!("Runtime Error - {}", self.message)
     }
+    fn trace(&self) -> &[TraceFrame] {
+        &self.trace
+    }
 }
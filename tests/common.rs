@@ -1,14 +1,24 @@
 use rlox::compiler;
 use rlox::error::ErrorReporter;
 use rlox::parser::Parser;
+use rlox::serialize;
+use rlox::symbol::GlobalSlots;
 use rlox::vm::VM;
+use std::fs;
 use std::io::Write;
+use std::path::Path;
 
 #[derive(Debug)]
 pub struct Output {
     pub contents: String,
 }
 
+impl Default for Output {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Output {
     pub fn new() -> Self {
         Output {
@@ -19,7 +29,7 @@ impl Output {
 
 impl Write for Output {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        self.contents.push_str(&std::str::from_utf8(buf).unwrap());
+        self.contents.push_str(std::str::from_utf8(buf).unwrap());
 
         Ok(buf.len())
     }
@@ -30,7 +40,20 @@ impl Write for Output {
 
 pub fn run(source: String) -> (Output, Output) {
     let mut vm = VM::new();
+    run_with_vm(source, &mut vm)
+}
+
+/// Run the script at `path`, which may import modules relative to its directory
+#[allow(dead_code)]
+pub fn run_file(path: &str) -> (Output, Output) {
+    let source = fs::read_to_string(path).unwrap();
+    let mut vm = VM::new();
+    vm.set_script_path(Path::new(path));
+    run_with_vm(source, &mut vm)
+}
 
+#[allow(dead_code)]
+pub fn run_with_vm(source: String, vm: &mut VM) -> (Output, Output) {
     let mut stdout = Output::new();
     let mut stderr = Output::new();
 
@@ -47,10 +70,10 @@ pub fn run(source: String) -> (Output, Output) {
     };
 
     // Compile
-    let script = match compiler::compile(ast) {
+    let script = match compiler::compile(ast, vm.global_slots_mut()) {
         Ok(bin) => bin,
-        Err(e) => {
-            reporter.report(&e);
+        Err(errors) => {
+            errors.iter().for_each(|e| reporter.report(e));
             return (stdout, stderr);
         }
     };
@@ -66,3 +89,50 @@ pub fn run(source: String) -> (Output, Output) {
 
     (stdout, stderr)
 }
+
+/// Compile `source` and serialize the resulting script. Panics if compilation fails.
+///
+/// The script is compiled against global slots of its own, as if by a separate
+/// process, so it is relocated into the slots of the VM that loads it.
+#[allow(dead_code)]
+pub fn compile_to_bytes(source: &str) -> Vec<u8> {
+    let ast = Parser::new(source).parse_program().unwrap();
+    let mut globals = GlobalSlots::default();
+    let script = compiler::compile(ast, &mut globals).unwrap();
+    serialize::serialize(&script, source, &globals)
+}
+
+/// Load and run a script serialized with `serialize::serialize`
+#[allow(dead_code)]
+pub fn run_compiled(bytes: &[u8]) -> (Output, Output) {
+    run_compiled_with_vm(bytes, &mut VM::new())
+}
+
+/// Load and run the serialized script at `path`, which may import modules
+/// relative to its directory
+#[allow(dead_code)]
+pub fn run_compiled_file(path: &Path) -> (Output, Output) {
+    let mut vm = VM::new();
+    vm.set_script_path(path);
+    run_compiled_with_vm(&fs::read(path).unwrap(), &mut vm)
+}
+
+#[allow(dead_code)]
+pub fn run_compiled_with_vm(bytes: &[u8], vm: &mut VM) -> (Output, Output) {
+    let mut stdout = Output::new();
+    let mut stderr = Output::new();
+
+    let (script, source) = match serialize::deserialize(bytes, vm.global_slots_mut()) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            writeln!(stderr, "{}", e).unwrap();
+            return (stdout, stderr);
+        }
+    };
+
+    if let Err(e) = vm.execute(&script, &mut stdout) {
+        ErrorReporter::new(&source, &mut stderr).report(&e);
+    }
+
+    (stdout, stderr)
+}
@@ -1,5 +1,13 @@
 mod common;
 
+use rlox::error::RuntimeError;
+use rlox::token::Span;
+use rlox::value::Value;
+use rlox::vm::VM;
+use std::env;
+use std::fs;
+use std::process;
+
 #[test]
 fn empty_source() {
     let source = "".trim().to_string();
@@ -136,7 +144,7 @@ true
 true
 true
 false
-true
+false
     "
     .trim();
 
@@ -542,10 +550,10 @@ fn function_recursive() {
     fun foo(a, b) {
         print a;
         if (a > 0) {
-            foo(a - 1);
+            foo(a - 1, b);
         }
     }
-    foo(2);
+    foo(2, 0);
     "
     .trim()
     .to_string();
@@ -962,3 +970,3153 @@ A instance
     assert_eq!(expected_stderr, stderr.contents.trim());
     assert_eq!(expected_stdout, stdout.contents.trim());
 }
+
+#[test]
+fn native_clock() {
+    let source = "
+    var start = clock();
+    print start > 0;
+    print clock() >= start;
+    print clock;
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "
+true
+true
+<native fn: clock>
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn native_defined_by_host() {
+    fn sum(_vm: &mut VM, args: &[Value]) -> Result<Value, RuntimeError> {
+        match (&args[0], &args[1]) {
+            (Value::Number(a), Value::Number(b)) => Ok(Value::from(a + b)),
+            _ => Err(RuntimeError::new(
+                "sum() expects two numbers".to_string(),
+                Span::new(0, 0),
+            )),
+        }
+    }
+
+    let source = "
+    print sum(1, 2);
+    var s = sum;
+    print s(sum(1, 2), 3) + 1;
+    print sum(\"a\", 1);
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "
+Runtime Error - sum() expects two numbers
+   4:     print sum(\"a\", 1);
+                ^^^^^^^^^^^
+[line 4] in script
+    "
+    .trim();
+    let expected_stdout = "
+3
+7
+    "
+    .trim();
+
+    let mut vm = VM::new();
+    vm.define_native("sum", 2, sum);
+    let (stdout, stderr) = common::run_with_vm(source, &mut vm);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn native_arity() {
+    let source = "
+    clock(1);
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "
+Runtime Error - Expected 0 arguments but got 1
+   1: clock(1);
+      ^^^^^^^^
+[line 1] in script
+    "
+    .trim();
+    let expected_stdout = "".trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn call_leaves_locals_intact() {
+    let source = "
+    {
+        fun f() {}
+        f();
+        var x = 1;
+        print x;
+    }
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "
+1
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn arity_function() {
+    let source = "
+    fun foo(a, b) {}
+    foo(1, 2, 3);
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "
+Runtime Error - Expected 2 arguments but got 3
+   2:     foo(1, 2, 3);
+          ^^^^^^^^^^^^
+[line 2] in script
+    "
+    .trim();
+    let expected_stdout = "".trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn arity_method() {
+    let source = "
+    class foo {
+        bar(a) {}
+    }
+    foo().bar();
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "
+Runtime Error - Expected 1 arguments but got 0
+   4:     foo().bar();
+                ^^^^^
+[line 4] in script
+    "
+    .trim();
+    let expected_stdout = "".trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn arity_initializer() {
+    let source = "
+    class foo {
+        init(a) {}
+    }
+    print foo(1);
+    foo(1, 2);
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "
+Runtime Error - Expected 1 arguments but got 2
+   5:     foo(1, 2);
+          ^^^^^^^^^
+[line 5] in script
+    "
+    .trim();
+    let expected_stdout = "
+foo instance
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn arity_no_initializer() {
+    let source = "
+    class foo {}
+    foo(1);
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "
+Runtime Error - Expected 0 arguments but got 1
+   2:     foo(1);
+          ^^^^^^
+[line 2] in script
+    "
+    .trim();
+    let expected_stdout = "".trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn closure_counter() {
+    let source = "
+    fun counter() {
+        var count = 0;
+        fun increment() {
+            count = count + 1;
+            return count;
+        }
+        return increment;
+    }
+    var a = counter();
+    var b = counter();
+    print a();
+    print a();
+    print b();
+    print a();
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "
+1
+2
+1
+3
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn closure_shared_state() {
+    let source = "
+    var get;
+    var set;
+    fun make() {
+        var x = \"one\";
+        fun g() {
+            return x;
+        }
+        fun s(value) {
+            x = value;
+        }
+        get = g;
+        set = s;
+        x = \"two\";
+        print g();
+    }
+    make();
+    print get();
+    set(\"three\");
+    print get();
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "
+two
+two
+three
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn closure_writes_visible_to_enclosing() {
+    let source = "
+    {
+        var a = 1;
+        fun set() {
+            a = 2;
+        }
+        set();
+        print a;
+    }
+    for (var i = 0; i < 3; i = i + 1) {
+        fun show() {
+            print i;
+        }
+        show();
+    }
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "
+2
+0
+1
+2
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn closure_capture_through_intermediate() {
+    let source = "
+    fun outer() {
+        var x = 1;
+        var y = 2;
+        fun middle() {
+            print y;
+            fun inner() {
+                print x;
+            }
+            inner();
+            print y;
+        }
+        middle();
+    }
+    outer();
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "
+2
+1
+2
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn local_function_recursive() {
+    let source = "
+    {
+        fun fib(n) {
+            if (n < 2) return n;
+            return fib(n - 1) + fib(n - 2);
+        }
+        print fib(10);
+    }
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "
+55
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn deep_recursion() {
+    let source = "
+    fun count(n) {
+        if (n == 0) return 0;
+        return 1 + count(n - 1);
+    }
+    print count(1000);
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "
+1000
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn stack_overflow() {
+    let source = "
+    fun forever(n) {
+        return forever(n + 1);
+    }
+    forever(0);
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = format!(
+        "
+Runtime Error - Stack overflow
+   2:         return forever(n + 1);
+                     ^^^^^^^^^^^^^^
+{}[... 1008 more calls]
+[line 4] in script
+    ",
+        "[line 2] in forever()\n".repeat(15)
+    );
+    let expected_stderr = expected_stderr.trim();
+    let expected_stdout = "".trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn max_frames() {
+    let source = "
+    fun count(n) {
+        if (n == 0) return 0;
+        return 1 + count(n - 1);
+    }
+    print count(8);
+    print count(9);
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "
+Runtime Error - Stack overflow
+   3:         return 1 + count(n - 1);
+                         ^^^^^^^^^^^^
+[line 3] in count()
+[line 3] in count()
+[line 3] in count()
+[line 3] in count()
+[line 3] in count()
+[line 3] in count()
+[line 3] in count()
+[line 3] in count()
+[line 3] in count()
+[line 6] in script
+    "
+    .trim();
+    let expected_stdout = "
+8
+    "
+    .trim();
+
+    let mut vm = VM::new();
+    vm.set_max_frames(10);
+    let (stdout, stderr) = common::run_with_vm(source, &mut vm);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn gc_collects_cycles() {
+    let source = "
+    class Node {}
+    fun make() {
+        var node = Node();
+        node.next = node;
+
+        var f;
+        fun g() {
+            return f;
+        }
+        f = g;
+    }
+    for (var i = 0; i < 10; i = i + 1) {
+        make();
+    }
+    var keep = Node();
+    keep.next = keep;
+    "
+    .trim()
+    .to_string();
+
+    let mut vm = VM::new();
+    let (_, stderr) = common::run_with_vm(source, &mut vm);
+    assert_eq!("", stderr.contents.trim());
+
+    vm.collect_garbage();
+    let stats = vm.gc_stats();
+    assert_eq!(stats.collections, 1);
+    assert_eq!(stats.objects_collected, 20);
+    assert_eq!(stats.live_objects, 1);
+
+    let (stdout, stderr) = common::run_with_vm("print keep.next.next;".to_string(), &mut vm);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!("Node instance", stdout.contents.trim());
+}
+
+#[test]
+fn gc_runs_automatically() {
+    let source = "
+    class Node {}
+    for (var i = 0; i < 5000; i = i + 1) {
+        var node = Node();
+        node.next = node;
+    }
+    "
+    .trim()
+    .to_string();
+
+    let mut vm = VM::new();
+    let (_, stderr) = common::run_with_vm(source, &mut vm);
+    assert_eq!("", stderr.contents.trim());
+
+    let stats = vm.gc_stats();
+    assert!(stats.collections > 0);
+    assert!(stats.live_objects < 1024);
+}
+
+#[test]
+fn gc_handles_long_chains() {
+    let source = "
+    class Node {
+        init(next) {
+            this.next = next;
+        }
+    }
+    var list = nil;
+    var nested = [];
+    for (var i = 0; i < 200000; i = i + 1) {
+        list = Node(list);
+        nested = [nested];
+    }
+
+    var length = 0;
+    for (var node = list; node != nil; node = node.next) {
+        length = length + 1;
+    }
+    print length;
+    list = nil;
+    nested = nil;
+    print \"released\";
+    "
+    .trim()
+    .to_string();
+
+    let mut vm = VM::new();
+    let (stdout, stderr) = common::run_with_vm(source, &mut vm);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!("200000\nreleased", stdout.contents.trim());
+    assert!(vm.gc_stats().collections > 0);
+}
+
+#[test]
+fn stack_trace() {
+    let source = "
+    fun inner(x) {
+        return x + nil;
+    }
+
+    fun outer(x) {
+        return inner(x);
+    }
+
+    class Foo {
+        bar() {
+            outer(1);
+        }
+    }
+
+    Foo().bar();
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "
+Runtime Error - Cannot apply '+' to Number and Non-Number
+   2:         return x + nil;
+                     ^^^^^^^
+[line 2] in inner()
+[line 6] in outer()
+[line 11] in bar()
+[line 15] in script
+    "
+    .trim();
+    let expected_stdout = "".trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn multiple_compiler_errors() {
+    let source = "
+    {
+        var a = 1;
+        var a = 2;
+    }
+    print this;
+    fun foo() {
+        var b;
+        var b;
+        print super.bar;
+    }
+    print \"not reached\";
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "
+Compilation Error - Redeclaration of local variable a
+   3:         var a = 2;
+              ^^^^^^^^^^
+Compilation Error - Cannot use 'this' outside of a class method.
+   5:     print this;
+                ^^^^
+Compilation Error - Redeclaration of local variable b
+   8:         var b;
+              ^^^^^^
+Compilation Error - 'super' may not be used outside methods
+   9:         print super.bar;
+                    ^^^^^^^^^
+    "
+    .trim();
+    let expected_stdout = "".trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn compiled_round_trip() {
+    let source = "
+    class Greeter {
+        init(name) { this.name = name; }
+        greet() { return \"Hello, \" + this.name; }
+    }
+    class Loud < Greeter {
+        greet() { return super.greet() + \"!\"; }
+    }
+    fun counter() {
+        var i = 0;
+        fun inc() { i = i + 1; return i; }
+        return inc;
+    }
+    var c = counter();
+    c();
+    print c();
+    print Loud(\"Lox\").greet();
+    print nil;
+    print 1.5 > 1 and true;
+    "
+    .trim();
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "
+2
+Hello, Lox!
+nil
+true
+    "
+    .trim();
+
+    let bytes = common::compile_to_bytes(source);
+    assert!(rlox::serialize::is_compiled(&bytes));
+
+    let (stdout, stderr) = common::run_compiled(&bytes);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn compiled_runtime_error() {
+    let source = "
+    fun f() {
+        return 1 + nil;
+    }
+    f();
+    "
+    .trim();
+
+    let expected_stderr = "
+Runtime Error - Cannot apply '+' to Number and Non-Number
+   2:         return 1 + nil;
+                     ^^^^^^^
+[line 2] in f()
+[line 4] in script
+    "
+    .trim();
+    let expected_stdout = "".trim();
+
+    let (stdout, stderr) = common::run_compiled(&common::compile_to_bytes(source));
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn compiled_invalid_files() {
+    let bytes = common::compile_to_bytes("print 1;");
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] = b'X';
+    let (_, stderr) = common::run_compiled(&bad_magic);
+    assert_eq!(
+        "Load Error - Not a compiled Lox file",
+        stderr.contents.trim()
+    );
+
+    let mut bad_version = bytes.clone();
+    bad_version[4] = 99;
+    let (_, stderr) = common::run_compiled(&bad_version);
+    assert_eq!(
+        "Load Error - Unsupported compiled file version 99 (expected 4)",
+        stderr.contents.trim()
+    );
+
+    let truncated = &bytes[..bytes.len() - 1];
+    let (_, stderr) = common::run_compiled(truncated);
+    assert_eq!(
+        "Load Error - Unexpected end of compiled file",
+        stderr.contents.trim()
+    );
+
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    let (_, stderr) = common::run_compiled(&trailing);
+    assert_eq!(
+        "Load Error - Unexpected data after the end of the script",
+        stderr.contents.trim()
+    );
+}
+
+#[test]
+fn compiled_corrupt_operands() {
+    // Compile `source` and replace the only occurrence of `from` with `to`
+    let corrupt = |source: &str, from: &[u8], to: &[u8]| {
+        let mut bytes = common::compile_to_bytes(source);
+        let starts: Vec<usize> = bytes
+            .windows(from.len())
+            .enumerate()
+            .filter(|(_, window)| *window == from)
+            .map(|(start, _)| start)
+            .collect();
+        assert_eq!(1, starts.len());
+        bytes[starts[0]..starts[0] + to.len()].copy_from_slice(to);
+        common::run_compiled(&bytes).1.contents
+    };
+
+    // GetLocal 0, Print
+    let stderr = corrupt("{ var a = 1; print a; }", &[19, 0, 14], &[19, 200]);
+    assert!(stderr.starts_with("Runtime Error - Invalid local slot 200"));
+
+    // SetLocal 0, Pop
+    let stderr = corrupt("{ var a = 1; a = 2; }", &[20, 0, 15], &[20, 200]);
+    assert!(stderr.starts_with("Runtime Error - Invalid local slot 200"));
+
+    // BuildList 2, Print
+    let stderr = corrupt("print [1, 2];", &[36, 2, 0, 14], &[36, 200]);
+    assert!(stderr.starts_with("Runtime Error - Expected 200 values on the stack"));
+
+    // BuildMap 1, Print
+    let stderr = corrupt("print {\"a\": 1};", &[39, 1, 0, 14], &[39, 200]);
+    assert!(stderr.starts_with("Runtime Error - Expected 400 values on the stack"));
+
+    // The name of `inner`, its arity, and its only upvalue: the enclosing
+    // function's upvalue 0
+    let stderr = corrupt(
+        "
+fun outer() {
+    var x = 1;
+    fun mid() {
+        fun inner() { return x; }
+        return inner;
+    }
+    return mid;
+}",
+        &[b'i', b'n', b'n', b'e', b'r', 0, 1, 0, 0, 0, 0, 0, 0, 0, 0],
+        &[b'i', b'n', b'n', b'e', b'r', 0, 1, 0, 0, 0, 0, 5],
+    );
+    assert_eq!("Load Error - Invalid upvalue 5 in inner", stderr.trim());
+}
+
+#[test]
+fn constant_long() {
+    // More constants than fit in a single byte, so later ones need ConstantLong
+    let assignments: Vec<String> = (1..=300).map(|n| format!("x = x + {};", n)).collect();
+    let source = format!("var x = 0;\n{}\nprint x;", assignments.join("\n"));
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "45150".trim();
+
+    let (stdout, stderr) = common::run(source.clone());
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+
+    let (stdout, stderr) = common::run_compiled(&common::compile_to_bytes(&source));
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn jumps_over_long_code() {
+    // Each assignment is 8 bytes and each list element is 3, so these bodies
+    // and expressions are longer than 64 KiB
+    let assignments = vec!["x = x + 1;"; 10000].join("\n");
+    let elements = vec!["x"; 25000].join(", ");
+    let source = format!(
+        "
+    fun long() {{
+        var x = 0;
+        {0}
+        return x > 1 and x < 100000;
+    }}
+    print long();
+
+    var y = 0;
+    while (y < 1) {{
+        var x = 0;
+        {0}
+        y = y + x;
+    }}
+    print y;
+
+    var x = 0;
+    {0}
+    print x > 1 and x < 100000;
+
+    var point = nil;
+    print point?.x ?? (x > 1 ? [{1}].len() : 0);
+    print point?.x([{1}]);
+
+    for (var i = 0; i < 3; i = i + 1) {{
+        switch (i) {{
+            case 0:
+                continue;
+            case 1:
+                print [{1}].len() + i;
+            default:
+                break;
+        }}
+        print \"after switch\";
+    }}
+    ",
+        assignments, elements
+    );
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "true\n10000\ntrue\n25000\nnil\n25001\nafter switch".trim();
+
+    let (stdout, stderr) = common::run(source.clone());
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+
+    let (stdout, stderr) = common::run_compiled(&common::compile_to_bytes(&source));
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn too_many_locals() {
+    let declarations: Vec<String> = (0..=256).map(|n| format!("var a{};", n)).collect();
+    let source = format!("{{\n{}\n}}", declarations.join(" "));
+
+    let (stdout, stderr) = common::run(source);
+    assert!(stderr
+        .contents
+        .starts_with("Compilation Error - Too many local variables in function"));
+    assert_eq!("", stdout.contents.trim());
+}
+
+#[test]
+fn interned_strings() {
+    let a = rlox::symbol::intern("interned_strings");
+    let b = rlox::symbol::intern(&String::from("interned_strings"));
+    assert!(std::rc::Rc::ptr_eq(&a, &b));
+    assert_eq!(Value::from("interned_strings"), Value::String(a.clone()));
+
+    // Strings are forgotten once nothing refers to them
+    let count = rlox::symbol::interned_count();
+    drop(a);
+    drop(b);
+    assert_eq!(count - 1, rlox::symbol::interned_count());
+}
+
+#[test]
+fn string_equality_after_concatenation() {
+    let source = "
+    var a = \"con\";
+    var b = a + \"cat\";
+    print b == \"concat\";
+    print b != \"concat\";
+    print b == \"con\";
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "
+true
+false
+false
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn global_slots_shared_between_compilations() {
+    let mut vm = VM::new();
+    common::run_with_vm("var shared = 1;".to_string(), &mut vm);
+    let (stdout, stderr) =
+        common::run_with_vm("shared = shared + 1; print shared;".to_string(), &mut vm);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!("2", stdout.contents.trim());
+}
+
+#[test]
+fn compiled_globals_relocated() {
+    let source = "
+    var first = \"one\";
+    fun second() { return first + \" two\"; }
+    print second();
+    print clock() > 0;
+    ";
+    let bytes = common::compile_to_bytes(source);
+
+    // This VM assigns global slots in a different order from the compiler
+    let mut vm = VM::new();
+    vm.global_slots_mut().slot("an_unrelated_global");
+    vm.global_slots_mut().slot("second");
+    let (stdout, stderr) = common::run_compiled_with_vm(&bytes, &mut vm);
+
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!("one two\ntrue", stdout.contents.trim());
+}
+
+#[test]
+fn global_slots_belong_to_vm() {
+    // Globals compiled for one VM do not take up slots in another
+    let mut first = VM::new();
+    let second = VM::new();
+    let initial = second.global_slots().len();
+    let declarations: Vec<String> = (0..100).map(|n| format!("var g{0} = {0};", n)).collect();
+    common::run_with_vm(declarations.join("\n"), &mut first);
+
+    assert_eq!(initial + 100, first.global_slots().len());
+    assert_eq!(initial, second.global_slots().len());
+}
+
+/// One Value of every variant, plus a second, distinct Value of the same variant
+fn value_pairs() -> Vec<(Value, Value)> {
+    use rlox::executable::Executable;
+    use rlox::object::{
+        ObjBoundMethod, ObjClass, ObjClosure, ObjFunction, ObjInstance, ObjList, ObjMap, ObjModule,
+        ObjNative, ObjNativeMethod, ObjString,
+    };
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    let function = || {
+        Rc::new(ObjFunction {
+            arity: 0,
+            bin: Executable::new("f".to_string()),
+            name: Box::new(ObjString::from("f")),
+            upvalues: vec![],
+        })
+    };
+    let closure = || {
+        Rc::new(ObjClosure {
+            function: function(),
+            upvalues: vec![],
+        })
+    };
+    let class = || {
+        Rc::new(ObjClass {
+            name: Box::new(ObjString::from("C")),
+            methods: RefCell::new(HashMap::new()),
+            class_methods: RefCell::new(HashMap::new()),
+            getters: RefCell::new(HashMap::new()),
+        })
+    };
+    let instance = || Rc::new(ObjInstance::from(&class()));
+    let bound_method = || {
+        Rc::new(ObjBoundMethod {
+            receiver: instance(),
+            method: closure(),
+        })
+    };
+    let native = || {
+        Rc::new(ObjNative {
+            arity: 0,
+            name: Box::new(ObjString::from("clock")),
+            function: rlox::native::clock,
+        })
+    };
+
+    let list = || Rc::new(ObjList::from(vec![Value::Number(1.0)]));
+    let map = || Rc::new(ObjMap::from(rlox::object::MapEntries::default()));
+    let native_method = || {
+        Rc::new(ObjNativeMethod {
+            receiver: Value::List(list()),
+            method: native(),
+        })
+    };
+    let module = || {
+        Rc::new(ObjModule {
+            name: Box::new(ObjString::from("m.lox")),
+            members: HashMap::new(),
+        })
+    };
+
+    vec![
+        (Value::Number(1.0), Value::Number(2.0)),
+        (Value::Bool(true), Value::Bool(false)),
+        (Value::Nil, Value::Number(0.0)),
+        (Value::from("a"), Value::from("b")),
+        (Value::Function(function()), Value::Function(function())),
+        (Value::Closure(closure()), Value::Closure(closure())),
+        (Value::Class(class()), Value::Class(class())),
+        (Value::Instance(instance()), Value::Instance(instance())),
+        (
+            Value::BoundMethod(bound_method()),
+            Value::BoundMethod(bound_method()),
+        ),
+        (Value::Native(native()), Value::Native(native())),
+        (
+            Value::NativeMethod(native_method()),
+            Value::NativeMethod(native_method()),
+        ),
+        (Value::List(list()), Value::List(list())),
+        (Value::Map(map()), Value::Map(map())),
+        (Value::Module(module()), Value::Module(module())),
+    ]
+}
+
+#[test]
+fn equality_between_variants() {
+    let pairs = value_pairs();
+    for (i, (left, other)) in pairs.iter().enumerate() {
+        // Every value equals itself (or a copy of itself), but not a distinct value
+        assert_eq!(left, &left.clone(), "{:?} == itself", left);
+        assert_ne!(left, other, "{:?} != {:?}", left, other);
+
+        // Values of different variants are never equal
+        for (j, (right, _)) in pairs.iter().enumerate() {
+            if i != j {
+                assert_ne!(left, right, "{:?} != {:?}", left, right);
+            }
+        }
+    }
+
+    // Strings compare by contents
+    assert_eq!(Value::from("abc"), Value::from(String::from("ab") + "c"));
+}
+
+#[test]
+fn equality_spec() {
+    let source = "
+    class Point {
+        init(x) { this.x = x; }
+        get() { return this.x; }
+    }
+    fun f() {}
+    var a = Point(1);
+    var b = Point(1);
+    a.self = a;
+    var m = a.get;
+
+    print true == 1;
+    print false == nil;
+    print false == 0;
+    print true == \"yes\";
+    print nil == nil;
+    print true == true;
+    print 1 == 1;
+    print \"str\" == \"str\";
+    print a == a;
+    print a == b;
+    print a.self == a;
+    print m == m;
+    print a.get == a.get;
+    print Point == Point;
+    print f == f;
+    print clock == clock;
+    print f != clock;
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "
+false
+false
+false
+false
+true
+true
+true
+true
+true
+false
+true
+true
+false
+true
+true
+true
+true
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn lists() {
+    let source = "
+    var xs = [1, \"two\", nil, [true]];
+    print xs;
+    print xs[1];
+    print xs[3][0];
+    print [];
+
+    xs[0] = xs[0] + 10;
+    print xs[0];
+    print xs[2] = 3;
+
+    var ys = xs;
+    ys[1] = 2;
+    print xs;
+    print xs == ys;
+    print [1] == [1];
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "
+[1, \"two\", nil, [true]]
+two
+true
+[]
+11
+3
+[11, 2, 3, [true]]
+true
+false
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn list_methods() {
+    let source = "
+    var xs = [];
+    xs.push(1);
+    xs.push(2);
+    print xs.push(3);
+    print xs.len();
+    print xs.pop();
+    xs.insert(0, 0);
+    xs.insert(3, 3);
+    print xs;
+    print xs.remove(1);
+    print xs;
+
+    var push = xs.push;
+    push(4);
+    print xs;
+    print push;
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "
+nil
+3
+3
+[0, 1, 2, 3]
+1
+[0, 2, 3]
+[0, 2, 3, 4]
+<native fn: push>
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn list_errors() {
+    let cases = vec![
+        (
+            "var xs = [1, 2];\nprint xs[2];",
+            "
+Runtime Error - List index 2 out of range
+   2: print xs[2];
+            ^^^^^
+[line 2] in script
+            ",
+        ),
+        (
+            "var xs = [1, 2];\nxs[-1] = 0;",
+            "
+Runtime Error - List index -1 out of range
+   2: xs[-1] = 0;
+      ^^^^^^^^^^
+[line 2] in script
+            ",
+        ),
+        (
+            "[1][0.5];",
+            "
+Runtime Error - List index must be an integer, not 0.5
+   1: [1][0.5];
+      ^^^^^^^^
+[line 1] in script
+            ",
+        ),
+        (
+            "var s = \"abc\";\nprint s[0];",
+            "
+Runtime Error - Cannot index into abc
+   2: print s[0];
+            ^^^^
+[line 2] in script
+            ",
+        ),
+        (
+            "[].pop();",
+            "
+Runtime Error - Cannot pop from an empty list
+   1: [].pop();
+         ^^^^^
+[line 1] in script
+            ",
+        ),
+        (
+            "[].insert(1, 0);",
+            "
+Runtime Error - List index 1 out of range
+   1: [].insert(1, 0);
+         ^^^^^^^^^^^^
+[line 1] in script
+            ",
+        ),
+        (
+            "[].sort();",
+            "
+Runtime Error - List has no method sort
+   1: [].sort();
+         ^^^^
+[line 1] in script
+            ",
+        ),
+    ];
+
+    for (source, expected_stderr) in cases {
+        let (stdout, stderr) = common::run(source.to_string());
+        assert_eq!(expected_stderr.trim(), stderr.contents.trim(), "{}", source);
+        assert_eq!("", stdout.contents.trim());
+    }
+}
+
+#[test]
+fn list_cycles() {
+    let source = "
+    var xs = [1];
+    xs.push(xs);
+    print xs;
+    print xs[1][1][0];
+
+    for (var i = 0; i < 10; i = i + 1) {
+        var ys = [];
+        ys.push([ys]);
+    }
+    "
+    .trim()
+    .to_string();
+
+    let mut vm = VM::new();
+    let (stdout, stderr) = common::run_with_vm(source, &mut vm);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!("[1, [...]]\n1", stdout.contents.trim());
+
+    vm.collect_garbage();
+    let stats = vm.gc_stats();
+    // Clearing either list of a cycle frees the other
+    assert_eq!(stats.objects_collected, 10);
+    assert_eq!(stats.live_objects, 1);
+}
+
+#[test]
+fn maps() {
+    let source = "
+    var m = {\"a\": 1, \"b\": [2], 3: \"three\"};
+    print m;
+    print m[\"a\"];
+    print m[\"b\"][0];
+    print m[1 + 2];
+    print {};
+
+    m[\"a\"] = m[\"a\"] + 10;
+    print m[\"c\"] = nil;
+    print m;
+
+    var n = m;
+    n[\"d\"] = {true: false};
+    print m[\"d\"];
+    print m == n;
+    print {} == {};
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "
+{\"a\": 1, \"b\": [2], 3: \"three\"}
+1
+2
+three
+{}
+nil
+{\"a\": 11, \"b\": [2], 3: \"three\", \"c\": nil}
+{true: false}
+true
+false
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn map_keys() {
+    let source = "
+    class Point {}
+    var p = Point();
+    var q = Point();
+    var xs = [];
+    fun f() {}
+
+    var m = {};
+    m[0] = \"zero\";
+    m[-0] = \"negative zero\";
+    m[\"0\"] = \"string\";
+    m[false] = \"false\";
+    m[nil] = \"nil\";
+    m[p] = \"p\";
+    m[q] = \"q\";
+    m[xs] = \"xs\";
+    m[f] = \"f\";
+    m[Point] = \"Point\";
+    m[\"ab\"] = \"ab\";
+
+    print m.len();
+    print m[0];
+    print m[\"a\" + \"b\"];
+    print m[p];
+    print m[q];
+    print m[xs];
+    print m[f];
+    print m[Point];
+    print m.has([]);
+    print m.has(Point());
+    print m.has(nil);
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "
+10
+negative zero
+ab
+p
+q
+xs
+f
+Point
+false
+false
+true
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn map_methods() {
+    let source = "
+    var m = {\"x\": 1, \"y\": 2, \"z\": 3};
+    print m.keys();
+    print m.values();
+    print m.has(\"y\");
+    print m.remove(\"y\");
+    print m.has(\"y\");
+    print m.len();
+    m[\"y\"] = 4;
+    print m.keys();
+    print m.values();
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "
+[\"x\", \"y\", \"z\"]
+[1, 2, 3]
+true
+2
+false
+2
+[\"x\", \"z\", \"y\"]
+[1, 3, 4]
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn map_errors() {
+    let cases = vec![
+        (
+            "var m = {1: 2};\nprint m[\"1\"];",
+            "
+Runtime Error - Map has no key \"1\"
+   2: print m[\"1\"];
+            ^^^^^^
+[line 2] in script
+            ",
+        ),
+        (
+            "var m = {};\nm[0/0] = 1;",
+            "
+Runtime Error - Cannot use NaN as a map key
+   2: m[0/0] = 1;
+      ^^^^^^^^^^
+[line 2] in script
+            ",
+        ),
+        (
+            "print {0/0: 1};",
+            "
+Runtime Error - Cannot use NaN as a map key
+   1: print {0/0: 1};
+            ^^^^^^^^
+[line 1] in script
+            ",
+        ),
+        (
+            "print {}.remove(1);",
+            "
+Runtime Error - Map has no key 1
+   1: print {}.remove(1);
+               ^^^^^^^^^
+[line 1] in script
+            ",
+        ),
+        (
+            "var m = {};\nm.push(1);",
+            "
+Runtime Error - Map has no method push
+   2: m.push(1);
+        ^^^^
+[line 2] in script
+            ",
+        ),
+        (
+            "print {1 2};",
+            "
+Parsing Error - Unexpected Token. Expected ':' but got 2
+   1: print {1 2};
+               ^
+Parsing Error - Unexpected Token. Expected primary expression but got }
+   1: print {1 2};
+                ^
+            ",
+        ),
+    ];
+
+    for (source, expected_stderr) in cases {
+        let (stdout, stderr) = common::run(source.to_string());
+        assert_eq!(expected_stderr.trim(), stderr.contents.trim(), "{}", source);
+        assert_eq!("", stdout.contents.trim());
+    }
+}
+
+#[test]
+fn map_cycles() {
+    let source = "
+    var m = {};
+    m[m] = m;
+    print m;
+
+    for (var i = 0; i < 10; i = i + 1) {
+        var n = {};
+        n[\"self\"] = n;
+    }
+    "
+    .trim()
+    .to_string();
+
+    let mut vm = VM::new();
+    let (stdout, stderr) = common::run_with_vm(source, &mut vm);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!("{{...}: {...}}", stdout.contents.trim());
+
+    vm.collect_garbage();
+    let stats = vm.gc_stats();
+    assert_eq!(stats.objects_collected, 10);
+    assert_eq!(stats.live_objects, 1);
+}
+
+#[test]
+fn exceptions() {
+    let source = "
+    fun check(n) {
+        if (n > 2) throw \"too big\";
+        return n;
+    }
+    try {
+        print check(1);
+        print check(3);
+        print \"unreachable\";
+    } catch (e) {
+        print \"caught \" + e;
+    }
+
+    try {
+        throw [1, 2];
+    } catch (e) {
+        print e;
+    } finally {
+        print \"finally\";
+    }
+
+    {
+        var a = 1;
+        try {
+            var b = 2;
+            throw a + b;
+        } catch (sum) {
+            var c = 10;
+            print sum + c + a;
+        }
+        print a;
+    }
+
+    var fs = [];
+    try {
+        var captured = \"captured\";
+        fun g() { return captured; }
+        fs.push(g);
+        throw nil;
+    } catch (e) {
+        print fs[0]();
+    }
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "
+1
+caught too big
+[1, 2]
+finally
+14
+1
+captured
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn exceptions_runtime_errors() {
+    let source = "
+    class Point {}
+    try {
+        nil();
+    } catch (e) {
+        print e.message;
+        print e.start;
+        print e.end;
+        print e;
+    }
+    fun missingField() { return Point().x; }
+    fun badOperands() { return 1 - \"a\"; }
+    fun badIndex() { return [1][5]; }
+    fun overflow() { return overflow(); }
+    var errors = [missingField, badOperands, badIndex, overflow];
+    for (var i = 0; i < errors.len(); i = i + 1) {
+        try {
+            errors[i]();
+        } catch (e) {
+            print e.message;
+        }
+    }
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "
+Cannot invoke nil
+33
+38
+Error instance
+<Instance Point> has no field x
+Cannot apply 'Subtract' to non-numeric types
+List index 5 out of range
+Stack overflow
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn exceptions_finally() {
+    let source = "
+    fun f(n) {
+        for (var i = 0; i < 3; i = i + 1) {
+            try {
+                try {
+                    if (i == n) return i;
+                } finally {
+                    print \"inner \" + \"finally\";
+                }
+            } finally {
+                print \"outer finally\";
+            }
+        }
+        return -1;
+    }
+    print f(1);
+
+    fun g() {
+        try {
+            return \"try\";
+        } finally {
+            var x = \"finally\";
+            print x;
+        }
+    }
+    print g();
+
+    try {
+        try {
+            throw \"inner\";
+        } catch (e) {
+            print \"catch \" + e;
+            throw \"from catch\";
+        } finally {
+            print \"inner finally\";
+        }
+    } catch (e) {
+        print \"outer \" + e;
+    }
+
+    try {
+        try {
+            throw \"first\";
+        } finally {
+            print \"cleanup\";
+        }
+    } catch (e) {
+        print e;
+    }
+    "
+    .trim()
+    .to_string();
+
+    let expected_stderr = "".trim();
+    let expected_stdout = "
+inner finally
+outer finally
+inner finally
+outer finally
+1
+finally
+try
+catch inner
+inner finally
+outer from catch
+cleanup
+first
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!(expected_stderr, stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn exceptions_uncaught() {
+    let cases = vec![
+        (
+            "fun f() {\n    throw \"oops\";\n}\nf();",
+            "
+Runtime Error - Uncaught exception: oops
+   2:     throw \"oops\";
+          ^^^^^^^^^^^^^
+[line 2] in f()
+[line 4] in script
+            ",
+            "",
+        ),
+        (
+            "try {\n    throw 1;\n} finally {\n    print \"cleanup\";\n}",
+            "
+Runtime Error - Uncaught exception: 1
+   2:     throw 1;
+          ^^^^^^^^
+[line 2] in script
+            ",
+            "cleanup",
+        ),
+        (
+            "var e;\ntry {\n    1 + nil;\n} catch (err) {\n    e = err;\n}\nthrow e;",
+            "
+Runtime Error - Cannot apply '+' to Number and Non-Number
+   3:     1 + nil;
+          ^^^^^^^
+[line 7] in script
+            ",
+            "",
+        ),
+        (
+            "try {\n    print 1;\n}",
+            "
+Parsing Error - Unexpected end of file
+   3: }
+       ^
+            ",
+            "",
+        ),
+    ];
+
+    for (source, expected_stderr, expected_stdout) in cases {
+        let (stdout, stderr) = common::run(source.to_string());
+        assert_eq!(expected_stderr.trim(), stderr.contents.trim(), "{}", source);
+        assert_eq!(expected_stdout, stdout.contents.trim());
+    }
+}
+
+#[test]
+fn compiled_exceptions() {
+    let source = "
+    try {
+        throw \"thrown\";
+    } catch (e) {
+        print e;
+    } finally {
+        print \"done\";
+    }
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run_compiled(&common::compile_to_bytes(source));
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!("thrown\ndone", stdout.contents.trim());
+}
+
+#[test]
+fn modules() {
+    let (stdout, stderr) = common::run_file("tests/modules/import.lox");
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!(
+        "9\n12\n4\n<module tests/modules/lib/shapes.lox>\nscript\n4\n15",
+        stdout.contents.trim()
+    );
+}
+
+#[test]
+fn modules_run_once() {
+    let (stdout, stderr) = common::run_file("tests/modules/cache.lox");
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!(
+        "running counter.lox\ntrue\ntrue\n2\n2",
+        stdout.contents.trim()
+    );
+}
+
+#[test]
+fn modules_relative_to_working_directory() {
+    // Scripts that were not read from a file import relative to the working directory
+    let source = "
+    import \"tests/modules/lib/counter.lox\" as counter;
+    counter.increment();
+    print counter.count;
+
+    try {
+        import \"tests/modules/lib/cycle_a.lox\" as a;
+    } catch (e) {
+        print e.message;
+    }
+    try {
+        import \"tests/modules/lib/cycle_b.lox\" as b;
+    } catch (e) {
+        print e.message;
+    }
+    "
+    .trim()
+    .to_string();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!(
+        "running counter.lox
+1
+Circular import of module 'tests/modules/lib/cycle_a.lox'
+Circular import of module 'tests/modules/lib/cycle_b.lox'",
+        stdout.contents.trim()
+    );
+}
+
+#[test]
+fn compiled_modules_relative_to_script() {
+    // A compiled script imports relative to the directory it is run from,
+    // not the directory it was compiled in
+    let directory = env::temp_dir().join(format!("rlox-modules-{}", process::id()));
+    fs::create_dir_all(directory.join("lib")).unwrap();
+    fs::copy(
+        "tests/modules/lib/counter.lox",
+        directory.join("lib/counter.lox"),
+    )
+    .unwrap();
+
+    let source = "
+    import \"lib/counter.lox\" as counter;
+    counter.increment();
+    print counter.count;
+    ";
+    let script = directory.join("main.loxc");
+    fs::write(&script, common::compile_to_bytes(source)).unwrap();
+
+    let (stdout, stderr) = common::run_compiled_file(&script);
+    fs::remove_dir_all(&directory).unwrap();
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!("running counter.lox\n1", stdout.contents.trim());
+}
+
+#[test]
+fn module_errors() {
+    let (_, stderr) = common::run_file("tests/modules/circular.lox");
+    let expected_stderr = "
+Runtime Error - Circular import of module 'tests/modules/lib/cycle_a.lox'
+   1: import \"cycle_a.lox\" as a;
+      ^^^^^^^^^^^^^^^^^^^^^^^^^^
+[line 1 of tests/modules/lib/cycle_b.lox] in script
+[line 1 of tests/modules/lib/cycle_a.lox] in script
+[line 1] in script
+    ";
+    assert_eq!(expected_stderr.trim(), stderr.contents.trim());
+
+    let cases = vec![
+        (
+            "import \"tests/modules/lib/failing.lox\" as f;\nf.fail(nil);",
+            "
+Runtime Error - Cannot apply '+' to non-numeric or non-string type
+   2:     return value + 1;
+                 ^^^^^^^^^
+[line 2 of tests/modules/lib/failing.lox] in fail()
+[line 2] in script
+            ",
+        ),
+        (
+            "import \"tests/modules/lib/failing.lox\" as f;\nf.missing;",
+            "
+Runtime Error - Module 'tests/modules/lib/failing.lox' has no member missing
+   2: f.missing;
+        ^^^^^^^
+[line 2] in script
+            ",
+        ),
+        (
+            "import \"tests/modules/missing.lox\" as m;",
+            "
+Runtime Error - Cannot import module 'tests/modules/missing.lox': No such file or directory (os error 2)
+   1: import \"tests/modules/missing.lox\" as m;
+      ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+[line 1] in script
+            ",
+        ),
+        (
+            "import \"tests/modules/lib/broken.lox\" as b;",
+            "
+Runtime Error - Failed to compile module 'tests/modules/lib/broken.lox'
+Parsing Error - Unexpected Token. Expected primary expression but got ;
+   1: var x = ;
+              ^
+   1: import \"tests/modules/lib/broken.lox\" as b;
+      ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+[line 1] in script
+            ",
+        ),
+        (
+            "import \"tests/modules/lib/returns.lox\" as r;",
+            "
+Runtime Error - Failed to compile module 'tests/modules/lib/returns.lox'
+Compilation Error - Cannot return from the top level of a module
+   1: return 1;
+      ^^^^^^^^
+   1: import \"tests/modules/lib/returns.lox\" as r;
+      ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+[line 1] in script
+            ",
+        ),
+        (
+            "import \"a.lox\" b;",
+            "
+Parsing Error - Unexpected Token. Expected 'as' but got b
+   1: import \"a.lox\" b;
+                     ^
+            ",
+        ),
+    ];
+
+    for (source, expected_stderr) in cases {
+        let (_, stderr) = common::run(source.to_string());
+        assert_eq!(expected_stderr.trim(), stderr.contents.trim(), "{}", source);
+    }
+}
+
+#[test]
+fn compiled_modules() {
+    let source = "
+    import \"tests/modules/lib/shapes.lox\" as shapes;
+    print shapes.Square(2).area();
+    "
+    .trim();
+
+    let (stdout, stderr) = common::run_compiled(&common::compile_to_bytes(source));
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!("4", stdout.contents.trim());
+}
+
+#[test]
+fn break_and_continue() {
+    let source = "
+    for (var i = 0; i < 10; i = i + 1) {
+        var square = i * i;
+        if (i == 2) continue;
+        if (square > 30) break;
+        print square;
+    }
+
+    var i = 0;
+    while (true) {
+        i = i + 1;
+        var x = i;
+        if (x < 3) {
+            var y = x;
+            continue;
+        }
+        print x;
+        if (x >= 4) break;
+    }
+
+    var closures = [];
+    for (var k = 0; k < 3; k = k + 1) {
+        var captured = k;
+        fun f() {
+            return captured;
+        }
+        closures.push(f);
+        if (k == 1) break;
+    }
+    print closures[0]() + closures[1]();
+    print i;
+    "
+    .trim()
+    .to_string();
+
+    let expected_stdout = "0\n1\n9\n16\n25\n3\n4\n1\n4";
+    let (stdout, stderr) = common::run(source);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn labeled_loops() {
+    let source = "
+    outer: for (var a = 0; a < 3; a = a + 1) {
+        for (var b = 0; b < 3; b = b + 1) {
+            if (b == 1) continue outer;
+            if (a == 2) break outer;
+            print a * 10 + b;
+        }
+    }
+
+    var n = 0;
+    rows: while (n < 5) {
+        n = n + 1;
+        var m = 0;
+        while (true) {
+            m = m + 1;
+            if (m > n) continue rows;
+            if (n * m == 6) break rows;
+        }
+    }
+    print n;
+    "
+    .trim()
+    .to_string();
+
+    let (stdout, stderr) = common::run(source);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!("0\n10\n3", stdout.contents.trim());
+}
+
+#[test]
+fn break_and_continue_through_finally() {
+    let source = "
+    fun f() {
+        while (true) {
+            try {
+                try {
+                    break;
+                } finally {
+                    print \"inner finally\";
+                }
+            } catch (e) {
+                print \"not caught\";
+            } finally {
+                print \"outer finally\";
+            }
+        }
+
+        var n = 0;
+        while (n < 3) {
+            n = n + 1;
+            try {
+                if (n == 2) continue;
+                print n;
+            } finally {
+                print \"finally\";
+            }
+        }
+
+        while (true) {
+            try {
+                throw \"thrown\";
+            } catch (e) {
+                print e;
+                break;
+            } finally {
+                print \"after catch\";
+            }
+        }
+
+        // Every handler was removed, so this is caught here
+        try {
+            throw \"still caught\";
+        } catch (e) {
+            print e;
+        }
+        return \"done\";
+    }
+    print f();
+    "
+    .trim()
+    .to_string();
+
+    let expected_stdout = "
+inner finally
+outer finally
+1
+finally
+finally
+3
+finally
+thrown
+after catch
+still caught
+done
+    "
+    .trim();
+    let (stdout, stderr) = common::run(source);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn break_and_continue_errors() {
+    let cases = vec![
+        (
+            "break;",
+            "
+Compilation Error - Cannot use 'break' outside of a loop
+   1: break;
+      ^^^^^^
+            ",
+        ),
+        (
+            "while (true) {\n    fun f() {\n        continue;\n    }\n}",
+            "
+Compilation Error - Cannot use 'continue' outside of a loop
+   3:         continue;
+              ^^^^^^^^^
+            ",
+        ),
+        (
+            "outer: while (true) {\n    break inner;\n}",
+            "
+Compilation Error - Cannot break to unknown loop label inner
+   2:     break inner;
+          ^^^^^^^^^^^^
+            ",
+        ),
+        (
+            "label: print 1;",
+            "
+Parsing Error - Unexpected Token. Expected loop after label but got print
+   1: label: print 1;
+             ^^^^^
+            ",
+        ),
+    ];
+
+    for (source, expected_stderr) in cases {
+        let (_, stderr) = common::run(source.to_string());
+        assert_eq!(expected_stderr.trim(), stderr.contents.trim(), "{}", source);
+    }
+}
+
+#[test]
+fn lambdas() {
+    let source = "
+    var add = fun (a, b) {
+        return a + b;
+    };
+    print add(1, 2);
+    print add;
+
+    fun apply(f, value) {
+        return f(value);
+    }
+    print apply(fun (value) { return value * 10; }, 4);
+    print fun () {}();
+
+    fun makeCounter() {
+        var count = 0;
+        return fun () {
+            count = count + 1;
+            return count;
+        };
+    }
+    var counter = makeCounter();
+    counter();
+    print counter();
+
+    fun (message) { print message; }(\"called immediately\");
+    "
+    .trim()
+    .to_string();
+
+    let expected_stdout = "3\n<fn: lambda>\n40\nnil\n2\ncalled immediately";
+    let (stdout, stderr) = common::run(source);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn arrow_functions() {
+    let source = "
+    var double = x => x * 2;
+    var multiply = (a, b) => a * b;
+    var constant = () => \"constant\";
+    print double(21);
+    print multiply(3, 4);
+    print constant();
+
+    var curried = a => b => a + b;
+    print curried(1)(2);
+
+    var items = [1, 2, 3];
+    fun map(list, f) {
+        var result = [];
+        for (var i = 0; i < list.len(); i = i + 1) {
+            result.push(f(list[i]));
+        }
+        return result;
+    }
+    var offset = 10;
+    print map(items, item => item + offset);
+
+    class Box {
+        init(value) {
+            this.value = value;
+        }
+        getter() {
+            return () => this.value;
+        }
+    }
+    print Box(5).getter()();
+
+    // Parentheses that are not followed by '=>' still group
+    var grouped = (multiply);
+    print (1 + 2) * grouped(2, 2);
+
+    // A block body runs like any other function body
+    var log = (message) => {
+        print message;
+    };
+    print log(\"logged\");
+    var clamp = x => {
+        if (x > 10) return 10;
+        return x;
+    };
+    print clamp(42);
+
+    // A map literal body needs parentheses
+    var wrap = x => ({\"value\": x});
+    print wrap(1);
+    "
+    .trim()
+    .to_string();
+
+    let expected_stdout =
+        "42\n12\nconstant\n3\n[11, 12, 13]\n5\n12\nlogged\nnil\n10\n{\"value\": 1}";
+    let (stdout, stderr) = common::run(source);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn lambda_errors() {
+    let cases = vec![
+        (
+            "var f = x => missing(x);\nf(1);",
+            "
+Runtime Error - Attempted to get unknown global missing
+   1: var f = x => missing(x);
+                   ^^^^^^^
+[line 1] in lambda()
+[line 2] in script
+            ",
+        ),
+        (
+            "var f = fun (a) { return a; };\nf();",
+            "
+Runtime Error - Expected 1 arguments but got 0
+   2: f();
+      ^^^
+[line 2] in script
+            ",
+        ),
+        (
+            "var f = (a, 1) => a;",
+            "
+Parsing Error - Unexpected Token. Expected ')' but got ,
+   1: var f = (a, 1) => a;
+                ^
+            ",
+        ),
+    ];
+
+    for (source, expected_stderr) in cases {
+        let (_, stderr) = common::run(source.to_string());
+        assert_eq!(expected_stderr.trim(), stderr.contents.trim(), "{}", source);
+    }
+}
+
+#[test]
+fn interpolation() {
+    let source = "
+    var name = \"World\";
+    var age = 42;
+    print \"Hello ${name}, you are ${age}\";
+
+    // Any value can be interpolated
+    print \"${1 + 2} is ${nil} and ${true}\";
+    print \"list ${[1, \"a\"]} map ${{\"k\": 1}}\";
+
+    class Point {
+        init(x) {
+            this.x = x;
+        }
+    }
+    print \"${Point(3).x} ${Point} ${Point(1)}\";
+
+    // Strings can be nested inside interpolations
+    print \"outer ${\"inner ${name}!\"} done\";
+    print \"${\"\"}\" == \"\";
+    "
+    .trim()
+    .to_string();
+
+    let expected_stdout = "Hello World, you are 42
+3 is nil and true
+list [1, \"a\"] map {\"k\": 1}
+3 Point Point instance
+outer inner World! done
+true";
+    let (stdout, stderr) = common::run(source);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn interpolation_errors() {
+    let cases = vec![
+        (
+            "var a = 1;\nprint \"value: ${a + nil}\";",
+            "
+Runtime Error - Cannot apply '+' to Number and Non-Number
+   2: print \"value: ${a + nil}\";
+                      ^^^^^^^
+[line 2] in script
+            ",
+        ),
+        (
+            "print \"${a b}\";",
+            "
+Parsing Error - Unexpected Token. Expected '}' but got b
+   1: print \"${a b}\";
+                 ^
+            ",
+        ),
+        (
+            "print \"${a}",
+            "
+Parsing Error - Unexpected Token. Expected string literal but got unclosed string literal
+   1: print \"${a}
+                ^
+            ",
+        ),
+    ];
+
+    for (source, expected_stderr) in cases {
+        let (_, stderr) = common::run(source.to_string());
+        assert_eq!(expected_stderr.trim(), stderr.contents.trim(), "{}", source);
+    }
+}
+
+#[test]
+fn escape_sequences_and_raw_strings() {
+    let source = r#"
+    print "a\tb";
+    print "quote \" backslash \\ dollar \${x}";
+    print "\u{48}\u{69}";
+    var name = "World";
+    print "two\nlines ${name}";
+
+    // Raw strings span lines and take their contents verbatim
+    print """
+<p class="greeting">Hello ${name}\n</p>
+""";
+    print """one line""" + "!";
+    "#
+    .trim()
+    .to_string();
+
+    let expected_stdout = "a\tb
+quote \" backslash \\ dollar ${x}
+Hi
+two
+lines World
+<p class=\"greeting\">Hello ${name}\\n</p>
+
+one line!";
+    let (stdout, stderr) = common::run(source);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn string_literal_errors() {
+    let cases = vec![
+        (
+            r#"print "bad \q escape";"#,
+            r#"
+Parsing Error - Unexpected Token. Expected primary expression but got invalid escape sequence
+   1: print "bad \q escape";
+                 ^^
+            "#,
+        ),
+        (
+            r#"print "x \u{110000} y";"#,
+            r#"
+Parsing Error - Unexpected Token. Expected primary expression but got invalid unicode escape sequence
+   1: print "x \u{110000} y";
+               ^^^^^^^^^^
+            "#,
+        ),
+        (
+            r#"print "${1} \q";"#,
+            r#"
+Parsing Error - Unexpected Token. Expected string literal but got invalid escape sequence
+   1: print "${1} \q";
+                  ^^
+            "#,
+        ),
+        (
+            r#"print "a ${} b";"#,
+            r#"
+Parsing Error - Unexpected Token. Expected primary expression but got empty interpolation
+   1: print "a ${} b";
+               ^^^
+            "#,
+        ),
+        (
+            r#"print """never closed"#,
+            r#"
+Parsing Error - Unexpected Token. Expected primary expression but got unclosed string literal
+   1: print """never closed
+            ^^^^^^^^^^^^^^^
+            "#,
+        ),
+    ];
+
+    for (source, expected_stderr) in cases {
+        let (_, stderr) = common::run(source.to_string());
+        assert_eq!(expected_stderr.trim(), stderr.contents.trim(), "{}", source);
+    }
+}
+
+#[test]
+fn numeric_literals() {
+    let source = "
+    print 0xFF;
+    print 0b1010 + 0B1;
+    print 1.5e-3;
+    print 2E3;
+    print 1_000_000;
+    print 0xFFFF_FFFF;
+    print 0b1111_0000 == 240;
+    "
+    .trim()
+    .to_string();
+
+    let expected_stdout = "255\n11\n0.0015\n2000\n1000000\n4294967295\ntrue";
+    let (stdout, stderr) = common::run(source);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn numeric_literal_errors() {
+    let cases = vec![
+        (
+            "var mask = 0x;",
+            "
+Parsing Error - Unexpected Token. Expected primary expression but got missing digits in hexadecimal literal
+   1: var mask = 0x;
+                 ^^
+            ",
+        ),
+        (
+            "print 1e + 2;",
+            "
+Parsing Error - Unexpected Token. Expected primary expression but got missing digits in exponent
+   1: print 1e + 2;
+            ^^
+            ",
+        ),
+        (
+            "print 1__000;",
+            "
+Parsing Error - Unexpected Token. Expected primary expression but got invalid digit separator
+   1: print 1__000;
+            ^^^^^^
+            ",
+        ),
+    ];
+
+    for (source, expected_stderr) in cases {
+        let (_, stderr) = common::run(source.to_string());
+        assert_eq!(expected_stderr.trim(), stderr.contents.trim(), "{}", source);
+    }
+}
+
+#[test]
+fn remainder_exponent_and_bitwise_operators() {
+    let source = "
+    print 7 % 3;
+    print -7 % 3;
+    print 10 - 4 % 3 * 2;
+
+    // '**' is right-associative and binds tighter than unary minus
+    print 2 ** 3 ** 2;
+    print -2 ** 2;
+    print 2 ** -1;
+
+    print 6 & 3;
+    print 6 | 3;
+    print 6 ^ 3;
+    print ~5;
+    print 1 << 4;
+    print -16 >> 2;
+    print ~0xF0 & 0xFF;
+
+    // Bitwise operators bind tighter than comparisons
+    print 1 | 2 == 3;
+    print 1 + 2 << 1;
+    "
+    .trim()
+    .to_string();
+
+    let expected_stdout = "1\n-1\n8\n512\n-4\n0.5\n2\n7\n5\n-6\n16\n-4\n15\ntrue\n6";
+    let (stdout, stderr) = common::run(source);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn operator_errors() {
+    let cases = vec![
+        (
+            "var mask = 1.5 & 1;",
+            "
+Runtime Error - Cannot apply 'BitAnd' to non-integer types
+   1: var mask = 1.5 & 1;
+                 ^^^^^^^
+[line 1] in script
+            ",
+        ),
+        (
+            "print ~\"bits\";",
+            "
+Runtime Error - Cannot apply '~' to non-integer types
+   1: print ~\"bits\";
+            ^^^^^^^
+[line 1] in script
+            ",
+        ),
+        (
+            "print \"a\" % 2;",
+            "
+Runtime Error - Cannot apply 'Modulo' to non-numeric types
+   1: print \"a\" % 2;
+            ^^^^^^^
+[line 1] in script
+            ",
+        ),
+        (
+            "print 1 << 64;",
+            "
+Runtime Error - Cannot shift by 64 bits
+   1: print 1 << 64;
+            ^^^^^^^
+[line 1] in script
+            ",
+        ),
+    ];
+
+    for (source, expected_stderr) in cases {
+        let (_, stderr) = common::run(source.to_string());
+        assert_eq!(expected_stderr.trim(), stderr.contents.trim(), "{}", source);
+    }
+}
+
+#[test]
+fn compound_assignment() {
+    let source = "
+    var g = 10;
+    g += 5;
+    g -= 3;
+    g *= 2;
+    g /= 4;
+    print g;
+    g %= 4;
+    g **= 3;
+    print g;
+    g |= 4;
+    g &= 6;
+    g ^= 1;
+    g <<= 2;
+    g >>= 1;
+    print g;
+
+    var s = \"a\";
+    s += \"b\";
+    print s;
+
+    // Locals and upvalues
+    fun counter() {
+        var count = 0;
+        var add = fun (n) { return count += n; };
+        add(2);
+        print add(3);
+        count *= 10;
+        print count;
+    }
+    counter();
+
+    // The target of a field or index is only evaluated once
+    class Box {
+        init() {
+            this.value = 1;
+        }
+    }
+    var box = Box();
+    var reads = 0;
+    fun get() {
+        reads += 1;
+        return box;
+    }
+    get().value += 4;
+    print box.value;
+    var list = [1, 2, 3];
+    list[reads] *= 7;
+    print list;
+    print reads;
+
+    var map = {\"a\": 2};
+    map[\"a\"] **= 3;
+    print map;
+    "
+    .trim()
+    .to_string();
+
+    let expected_stdout = "6\n8\n10\nab\n5\n50\n5\n[1, 14, 3]\n1\n{\"a\": 8}";
+    let (stdout, stderr) = common::run(source);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn increment_and_decrement() {
+    let source = "
+    var g = 1;
+    print g++;
+    print g;
+    print ++g;
+    print g--;
+    print --g;
+    print -g++;
+
+    fun outer() {
+        var local = 5;
+        fun inner() {
+            return local++;
+        }
+        print inner();
+        print local;
+        local--;
+        print local;
+    }
+    outer();
+
+    class Counter {
+        init() {
+            this.count = 0;
+        }
+    }
+    var counter = Counter();
+    var reads = 0;
+    fun get() {
+        reads++;
+        return counter;
+    }
+    print get().count++;
+    print ++get().count;
+    print counter.count;
+    print reads;
+
+    var list = [10, 20];
+    print list[1]--;
+    print list;
+
+    for (var i = 0; i < 3; i++) {
+        print i;
+    }
+    "
+    .trim()
+    .to_string();
+
+    let expected_stdout = "1\n2\n3\n3\n1\n-1\n5\n6\n5\n0\n2\n2\n2\n20\n[10, 19]\n0\n1\n2";
+    let (stdout, stderr) = common::run(source);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn compound_assignment_errors() {
+    let cases = vec![
+        (
+            "var s = \"a\";\ns++;",
+            "
+Runtime Error - Cannot apply '+' to String and Non-String
+   2: s++;
+      ^^^
+[line 2] in script
+            ",
+        ),
+        (
+            "var x = nil;\nx -= 1;",
+            "
+Runtime Error - Cannot apply 'Subtract' to non-numeric types
+   2: x -= 1;
+      ^^^^^^
+[line 2] in script
+            ",
+        ),
+        (
+            "print missing += 1;",
+            "
+Runtime Error - Attempted to get unknown global missing
+   1: print missing += 1;
+            ^^^^^^^
+[line 1] in script
+            ",
+        ),
+        (
+            "1++;",
+            "
+Compilation Error - Invalid assignment target
+   1: 1++;
+      ^
+            ",
+        ),
+        (
+            "fun f() {}\nf() += 1;",
+            "
+Compilation Error - Invalid assignment target
+   2: f() += 1;
+      ^^^
+            ",
+        ),
+        (
+            "fun f() {}\nf() = 1;",
+            "
+Compilation Error - Invalid assignment target
+   2: f() = 1;
+      ^^^
+            ",
+        ),
+    ];
+
+    for (source, expected_stderr) in cases {
+        let (_, stderr) = common::run(source.to_string());
+        assert_eq!(expected_stderr.trim(), stderr.contents.trim(), "{}", source);
+    }
+}
+
+#[test]
+fn conditional_operator() {
+    let source = "
+    var x = 5;
+    print x > 3 ? \"big\" : \"small\";
+
+    // Conditionals are right-associative
+    print x > 10 ? \"big\" : x > 4 ? \"medium\" : \"small\";
+
+    // Only the chosen branch is evaluated
+    var calls = 0;
+    fun count() {
+        calls = calls + 1;
+        return calls;
+    }
+    print true ? \"then\" : count();
+    print calls;
+
+    var y;
+    y = x == 5 ? x * 2 : 0;
+    print y;
+    "
+    .trim()
+    .to_string();
+
+    let expected_stdout = "big\nmedium\nthen\n0\n10";
+    let (stdout, stderr) = common::run(source);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn nil_coalescing_and_optional_chaining() {
+    let source = "
+    print nil ?? \"default\";
+    print false ?? \"default\";
+    print 0 ?? 1;
+    print nil ?? nil ?? 3;
+
+    // The right side is only evaluated when the left is nil
+    var calls = 0;
+    fun fallback() {
+        calls = calls + 1;
+        return \"fallback\";
+    }
+    print \"value\" ?? fallback();
+    print calls;
+
+    class Node {
+        init(name) {
+            this.name = name;
+            this.next = nil;
+        }
+        describe() {
+            return \"Node \" + this.name;
+        }
+    }
+    var head = Node(\"a\");
+    head.next = Node(\"b\");
+    var empty = nil;
+
+    print head?.name;
+    print head?.next?.describe();
+    print empty?.name;
+    print empty?.describe();
+
+    // A nil target skips the rest of the chain
+    print empty?.next.name.length;
+    print head.next.next?.describe() ?? \"end\";
+    print [empty?.name, head?.name];
+    "
+    .trim()
+    .to_string();
+
+    let expected_stdout =
+        "default\nfalse\n0\n3\nvalue\n0\na\nNode b\nnil\nnil\nnil\nend\n[nil, \"a\"]";
+    let (stdout, stderr) = common::run(source);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn conditional_operator_errors() {
+    let cases = vec![
+        (
+            "print true ? 1;",
+            "
+Parsing Error - Unexpected Token. Expected ':' but got ;
+   1: print true ? 1;
+                    ^
+            ",
+        ),
+        (
+            "var n = 1;\nprint n?.field;",
+            "
+Runtime Error - Number(1) is not an instance
+   2: print n?.field;
+               ^^^^^
+[line 2] in script
+            ",
+        ),
+        (
+            "var n = nil;\nn?.field = 1;",
+            "
+Compilation Error - Cannot assign to an optional chain
+   2: n?.field = 1;
+         ^^^^^
+            ",
+        ),
+    ];
+
+    for (source, expected_stderr) in cases {
+        let (_, stderr) = common::run(source.to_string());
+        assert_eq!(expected_stderr.trim(), stderr.contents.trim(), "{}", source);
+    }
+}
+
+#[test]
+fn switch_statements() {
+    let source = "
+    fun describe(n) {
+        switch (n) {
+            case 1, 2:
+                return \"small\";
+            case 3:
+                var name = \"three\";
+                return name;
+            case \"a\":
+                return \"letter\";
+            default:
+                return \"other\";
+        }
+    }
+    print describe(1);
+    print describe(2);
+    print describe(3);
+    print describe(\"a\");
+    print describe(nil);
+
+    // The scrutinee is evaluated once and cases do not fall through
+    var calls = 0;
+    fun five() {
+        calls = calls + 1;
+        return 5;
+    }
+    switch (five()) {
+        case 1:
+            print \"one\";
+        case 4, 5:
+            print \"five\";
+        default:
+            print \"default\";
+    }
+    print calls;
+
+    switch (10) {
+        case 1:
+            print \"no match\";
+    }
+
+    // break and continue apply to the enclosing loop
+    for (var i = 0; i < 5; i = i + 1) {
+        switch (i) {
+            case 1:
+                continue;
+            case 3:
+                break;
+            default:
+                var tens = i * 10;
+                print tens;
+        }
+    }
+    "
+    .trim()
+    .to_string();
+
+    let expected_stdout = "small\nsmall\nthree\nletter\nother\nfive\n1\n0\n20";
+    let (stdout, stderr) = common::run(source);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn switch_errors() {
+    let cases = vec![
+        (
+            "switch (1) {\n    case 1, 2: print 1;\n    case 1: print 3;\n}",
+            "
+Compilation Error - Duplicate case 1 in switch
+   3:     case 1: print 3;
+               ^
+            ",
+        ),
+        (
+            "switch (1) {\n    case -2, -2: print 1;\n}",
+            "
+Compilation Error - Duplicate case -2 in switch
+   2:     case -2, -2: print 1;
+                   ^^
+            ",
+        ),
+    ];
+
+    for (source, expected_stderr) in cases {
+        let (_, stderr) = common::run(source.to_string());
+        assert_eq!(expected_stderr.trim(), stderr.contents.trim(), "{}", source);
+    }
+}
+
+#[test]
+fn class_methods_and_getters() {
+    let source = "
+    class Math {
+        class square(x) {
+            return x * x;
+        }
+        class pi() {
+            return 3;
+        }
+    }
+    print Math.square(3);
+
+    class Circle {
+        init(radius) {
+            this.radius = radius;
+        }
+        area {
+            return Math.pi() * this.radius * this.radius;
+        }
+        describe() {
+            return \"Circle with area ${this.area}\";
+        }
+        class unit() {
+            return Circle(1);
+        }
+    }
+    var circle = Circle(2);
+    print circle.area;
+    print circle.describe();
+    print Circle.unit().area;
+
+    // Getters are recomputed on each access
+    circle.radius += 1;
+    print circle.area;
+
+    // Getters and class methods are inherited
+    class Ring < Circle {
+        diameter {
+            return this.radius * 2;
+        }
+    }
+    var ring = Ring(5);
+    print ring.area;
+    print ring.diameter;
+    print Ring.unit().radius;
+    "
+    .trim()
+    .to_string();
+
+    let expected_stdout = "9\n12\nCircle with area 12\n3\n27\n75\n10\n1";
+    let (stdout, stderr) = common::run(source);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn getters_and_methods_override_each_other() {
+    let source = "
+    class Base {
+        value() {
+            return 1;
+        }
+        size {
+            return 10;
+        }
+    }
+    class Derived < Base {
+        value {
+            return 2;
+        }
+        size() {
+            return 20;
+        }
+    }
+    print Derived().value;
+    print Derived().size();
+    print Base().value();
+    print Base().size;
+
+    // 'super' reaches getters defined by the superclass
+    class Doubled < Base {
+        size {
+            return super.size * 2;
+        }
+    }
+    print Doubled().size;
+    "
+    .trim()
+    .to_string();
+
+    let expected_stdout = "2\n20\n1\n10\n20";
+    let (stdout, stderr) = common::run(source);
+    assert_eq!("", stderr.contents.trim());
+    assert_eq!(expected_stdout, stdout.contents.trim());
+}
+
+#[test]
+fn class_method_and_getter_errors() {
+    let cases = vec![
+        (
+            "class Shape {\n    area() { return 0; }\n}\nShape.area();",
+            "
+Runtime Error - <Class Shape> has no class method area
+   4: Shape.area();
+            ^^^^
+[line 4] in script
+            ",
+        ),
+        (
+            "class Shape {\n    area { return 0; }\n}\nShape().area = 1;",
+            "
+Runtime Error - Cannot assign to getter area
+   4: Shape().area = 1;
+              ^^^^^^^^
+[line 4] in script
+            ",
+        ),
+        (
+            "class Shape {\n    area { return missing; }\n}\nprint Shape().area;",
+            "
+Runtime Error - Attempted to get unknown global missing
+   2:     area { return missing; }
+                        ^^^^^^^
+[line 2] in area()
+[line 4] in script
+            ",
+        ),
+        (
+            "class Shape {\n    area { return 1; }\n    area() { return 2; }\n}",
+            "
+Compilation Error - Class Shape has both a method and a getter named area
+   2:     area { return 1; }
+          ^^^^^^^^^^^^^^^^^^
+            ",
+        ),
+        (
+            "class Shape {\n    init { print 1; }\n}",
+            "
+Compilation Error - A getter cannot be named 'init'
+   2:     init { print 1; }
+          ^^^^^^^^^^^^^^^^^
+            ",
+        ),
+        (
+            "class Shape {\n    class make() { return this; }\n}",
+            "
+Compilation Error - Cannot use 'this' outside of a class method.
+   2:     class make() { return this; }
+                                ^^^^
+            ",
+        ),
+    ];
+
+    for (source, expected_stderr) in cases {
+        let (_, stderr) = common::run(source.to_string());
+        assert_eq!(expected_stderr.trim(), stderr.contents.trim(), "{}", source);
+    }
+}